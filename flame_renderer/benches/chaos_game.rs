@@ -0,0 +1,71 @@
+use criterion::{ criterion_group, criterion_main, BenchmarkId, Criterion, Throughput };
+
+use flame_renderer::rustyflame::genome::Genome;
+use flame_renderer::rustyflame::image::Dimensions;
+use flame_renderer::rustyflame::math::AugmentedMat2x2;
+use flame_renderer::rustyflame::simulator::{ FunctionElement, Kernel, Simulator, SuperSamplingFactor };
+
+const SAMPLES: usize = 2_000_000;
+const DIMENSIONS: Dimensions = Dimensions { width: 640, height: 480 };
+
+/// Linear maps only, where the batched kernel has the most to gain.
+fn sierpinski() -> Genome
+{
+    let transforms = [[[0.5, 0., -0.5], [0., 0.5, -0.5]], [[0.5, 0., 0.5], [0., 0.5, -0.5]], [[0.5, 0., 0.], [0., 0.5, 0.5]]]
+        .iter()
+        .map(|a| FunctionElement::new(AugmentedMat2x2::new(*a), 0.))
+        .collect();
+
+    Genome { transforms, ..Genome::default() }
+}
+
+fn bench_thread_scaling(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("chaos_game_threads");
+
+    group.throughput(Throughput::Elements(SAMPLES as u64));
+    group.sample_size(10);
+
+    for threads in [1, 2, 4, 8].iter()
+    {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), threads, |b, threads|
+        {
+            let mut simulator = Simulator::with_seed(DIMENSIONS, 20, SuperSamplingFactor::None, 1);
+
+            simulator.set_thread_count(*threads);
+            b.iter(|| simulator.iterate(SAMPLES));
+        });
+    }
+
+    group.finish();
+}
+
+/// Single threaded, so that only the kernels differ.
+fn bench_kernels(c: &mut Criterion)
+{
+    let mut group = c.benchmark_group("chaos_game_kernels");
+    let genomes = [("random", Simulator::random_genome(1)), ("sierpinski", sierpinski())];
+
+    group.throughput(Throughput::Elements(SAMPLES as u64));
+    group.sample_size(10);
+
+    for (name, genome) in genomes.iter()
+    {
+        for (kernel_name, kernel) in [("scalar", Kernel::Scalar), ("batched", Kernel::Batched)].iter()
+        {
+            group.bench_with_input(BenchmarkId::new(*kernel_name, name), genome, |b, genome|
+            {
+                let mut simulator = Simulator::with_seed(DIMENSIONS, 20, SuperSamplingFactor::None, 1);
+
+                simulator.set_genome(genome.clone()).expect("benchmark genomes are valid");
+                simulator.set_kernel(*kernel);
+                b.iter(|| simulator.iterate(SAMPLES));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_thread_scaling, bench_kernels);
+criterion_main!(benches);
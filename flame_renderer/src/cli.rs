@@ -0,0 +1,780 @@
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use flame_renderer::rustyflame::export::ImageFormat;
+use flame_renderer::rustyflame::filter::FilterKind;
+use flame_renderer::rustyflame::genome::{ Genome, GenomeFormat };
+use flame_renderer::rustyflame::image::Dimensions;
+use flame_renderer::rustyflame::palette::{ self, Palette };
+use flame_renderer::rustyflame::progress;
+use flame_renderer::rustyflame::simulator::{ Kernel, SuperSamplingFactor };
+use flame_renderer::rustyflame::symmetry::Symmetry;
+
+pub const PROGRAM: &str = "flame_renderer";
+pub const DEFAULT_DIMENSIONS: Dimensions = Dimensions { width: 1200, height: 960 };
+pub const DEFAULT_PREPARATION_ROUNDS: usize = 50;
+pub const DEFAULT_QUALITY: f64 = 50.;
+pub const DEFAULT_FRAMES: usize = 60;
+pub const DEFAULT_TEMPORAL_SAMPLES: usize = 16;
+/// Size of each flame of the gallery, which shows many of them.
+pub const DEFAULT_THUMBNAIL_DIMENSIONS: Dimensions = Dimensions { width: 240, height: 192 };
+pub const DEFAULT_OFFSPRING: usize = 9;
+pub const DEFAULT_CHECKPOINT_INTERVAL: Duration = Duration::from_secs(300);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command
+{
+    Interactive,
+    Render,
+    Random,
+    Convert,
+    Animate,
+    Gallery,
+}
+
+const COMMANDS: [Command; 6] = [Command::Interactive, Command::Render, Command::Random, Command::Convert, Command::Animate, Command::Gallery];
+
+impl Command
+{
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Command::Interactive => "interactive",
+            Command::Render => "render",
+            Command::Random => "random",
+            Command::Convert => "convert",
+            Command::Animate => "animate",
+            Command::Gallery => "gallery",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Command>
+    {
+        COMMANDS.iter().copied().find(|command| command.name() == name)
+    }
+
+    fn summary(&self) -> &'static str
+    {
+        match self
+        {
+            Command::Interactive => "Explore flames in a window (default)",
+            Command::Render => "Render a flame to an image file",
+            Command::Random => "Generate a random genome",
+            Command::Convert => "Convert a genome between the JSON and flam3 formats",
+            Command::Animate => "Render the interpolation between genomes to numbered image frames",
+            Command::Gallery => "Render a grid of mutants of a genome, or of children with a mate, saving their genomes",
+        }
+    }
+}
+
+/// When to stop sampling.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Budget
+{
+    Samples(usize),
+    /// Samples per output pixel.
+    Quality(f64),
+}
+
+impl Budget
+{
+    pub fn samples(&self, dimensions: &Dimensions) -> usize
+    {
+        match self
+        {
+            Budget::Samples(samples) => *samples,
+            Budget::Quality(quality) => progress::samples_for_quality(*quality, dimensions),
+        }
+    }
+}
+
+/// Display setting given on the command line, applied over the genome ones.
+pub type GenomeOverride = Box<dyn Fn(&mut Genome)>;
+
+pub struct Config
+{
+    pub command: Command,
+    pub dimensions: Dimensions,
+    pub super_sampling_factor: SuperSamplingFactor,
+    pub preparation_rounds: usize,
+    /// Unlimited when interactive if not given.
+    pub budget: Option<Budget>,
+    pub seed: Option<u64>,
+    pub genome: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub save_genome: Option<PathBuf>,
+    pub determinant_weights: bool,
+    pub palette: Option<Palette>,
+    /// Applied in command line order.
+    pub overrides: Vec<GenomeOverride>,
+    pub threads: usize,
+    pub kernel: Kernel,
+    /// Genomes the animation goes through, in order.
+    pub keyframes: Vec<PathBuf>,
+    pub frames: usize,
+    /// Fraction of the time between frames each frame is exposed for, 0 disabling motion blur.
+    pub shutter: f64,
+    pub temporal_samples: usize,
+    /// Crossed with the genome in the gallery, instead of mutating it.
+    pub mate: Option<PathBuf>,
+    /// Genomes shown in the gallery.
+    pub offspring: usize,
+    /// File the accumulation is saved to while rendering and when stopped.
+    pub checkpoint: Option<PathBuf>,
+    pub checkpoint_interval: Duration,
+    /// Checkpoint to carry on from, instead of starting from a genome.
+    pub resume: Option<PathBuf>,
+}
+
+pub enum Invocation
+{
+    Run(Box<Config>),
+    /// Help was asked for, this is the text to print.
+    Help(String),
+}
+
+struct OptionSpec
+{
+    long: &'static str,
+    short: Option<&'static str>,
+    value: Option<&'static str>,
+    help: &'static str,
+    commands: &'static [Command],
+}
+
+const VIEWING: &[Command] = &[Command::Interactive, Command::Render];
+const RENDERING: &[Command] = &[Command::Interactive, Command::Render, Command::Animate, Command::Gallery];
+const SEEDED: &[Command] = &[Command::Interactive, Command::Render, Command::Random, Command::Animate, Command::Gallery];
+const READING: &[Command] = &[Command::Interactive, Command::Render, Command::Convert, Command::Gallery];
+const WRITING: &[Command] = &[Command::Render, Command::Random, Command::Convert, Command::Animate, Command::Gallery];
+const ANIMATING: &[Command] = &[Command::Animate];
+const BREEDING: &[Command] = &[Command::Gallery];
+const ALL: &[Command] = &COMMANDS;
+/// Options whose setting comes from the checkpoint when resuming, besides the genome display ones.
+const RESUMED: &[&str] = &["--genome", "--size", "--supersampling", "--seed", "--preparation-rounds"];
+
+const OPTIONS: &[OptionSpec] =
+&[
+    OptionSpec { long: "--genome", short: Some("-g"), value: Some("<flame.json|flame.flame>"), help: "Genome to start from, a random one otherwise", commands: READING },
+    OptionSpec { long: "--output", short: Some("-o"), value: Some("<path>"), help: "Image (.png, .ppm, .pam, .pfm) to render, numbered for each frame when animating, or genome (.json, .flame) to write. The gallery genomes are saved next to it, numbered", commands: WRITING },
+    OptionSpec { long: "--keyframe", short: Some("-k"), value: Some("<flame.json|flame.flame>"), help: "Genome the animation goes through, repeated for each of them in order", commands: ANIMATING },
+    OptionSpec { long: "--frames", short: None, value: Some("<count>"), help: "Frames to render, 60 by default", commands: ANIMATING },
+    OptionSpec { long: "--motion-blur", short: None, value: Some("<shutter>"), help: "Fraction of the time between frames each frame is exposed for, 0 by default", commands: ANIMATING },
+    OptionSpec { long: "--temporal-samples", short: None, value: Some("<count>"), help: "Genomes the samples of a frame are spread over with motion blur, 16 by default", commands: ANIMATING },
+    OptionSpec { long: "--mate", short: Some("-m"), value: Some("<flame.json|flame.flame>"), help: "Genome crossed with the --genome, whose children are shown instead of mutants", commands: BREEDING },
+    OptionSpec { long: "--count", short: None, value: Some("<count>"), help: "Genomes shown in the gallery, 9 by default", commands: BREEDING },
+    OptionSpec { long: "--save-genome", short: None, value: Some("<flame.json|flame.flame>"), help: "Also save the genome being rendered", commands: VIEWING },
+    OptionSpec { long: "--checkpoint", short: None, value: Some("<path>"), help: "Saves the accumulation there periodically and when stopped, to --resume later", commands: VIEWING },
+    OptionSpec { long: "--checkpoint-interval", short: None, value: Some("<seconds>"), help: "Time between checkpoints, 300 by default", commands: VIEWING },
+    OptionSpec { long: "--resume", short: None, value: Some("<path>"), help: "Carries on from a checkpoint, which also sets the genome and its display settings, size, supersampling, preparation rounds and seed", commands: VIEWING },
+    OptionSpec { long: "--size", short: None, value: Some("<WIDTHxHEIGHT>"), help: "Resolution, 1200x960 by default, or of each flame of the gallery, 240x192 by default", commands: RENDERING },
+    OptionSpec { long: "--supersampling", short: Some("-s"), value: Some("<1|2|4|8>"), help: "Histogram cells per pixel along each axis, 1 by default", commands: RENDERING },
+    OptionSpec { long: "--quality", short: Some("-q"), value: Some("<spp>"), help: "Samples per pixel to stop at, 50 when rendering and unlimited when interactive by default", commands: RENDERING },
+    OptionSpec { long: "--samples", short: Some("-n"), value: Some("<count>"), help: "Total samples to stop at, instead of a quality", commands: RENDERING },
+    OptionSpec { long: "--preparation-rounds", short: None, value: Some("<count>"), help: "Iterations of each walker discarded before plotting, 50 by default", commands: RENDERING },
+    OptionSpec { long: "--seed", short: None, value: Some("<u64>"), help: "Seed of the random genome and of the chaos game", commands: SEEDED },
+    OptionSpec { long: "--threads", short: Some("-j"), value: Some("<count>"), help: "Worker threads, one per core by default", commands: RENDERING },
+    OptionSpec { long: "--batched", short: None, value: None, help: "Move many walkers at once per thread, faster but with different samples for a seed", commands: RENDERING },
+    OptionSpec { long: "--determinant-weights", short: None, value: None, help: "Weight transforms by the determinant of their affine part", commands: ALL },
+    OptionSpec { long: "--palette", short: Some("-p"), value: Some("<name|path>"), help: "Builtin palette, or .map, .gradient or .png file", commands: ALL },
+    OptionSpec { long: "--symmetry", short: None, value: Some("<n>"), help: "Adds n-fold rotational symmetry, or dihedral when negative, like flam3", commands: ALL },
+    OptionSpec { long: "--brightness", short: None, value: Some("<b>"), help: "Tone mapping brightness", commands: ALL },
+    OptionSpec { long: "--gamma", short: None, value: Some("<g>"), help: "Tone mapping gamma", commands: ALL },
+    OptionSpec { long: "--gamma-threshold", short: None, value: Some("<t>"), help: "Brightness below which gamma is linearized", commands: ALL },
+    OptionSpec { long: "--vibrancy", short: None, value: Some("<v>"), help: "Gamma applied to brightness (1) or to each channel (0)", commands: ALL },
+    OptionSpec { long: "--highlight-power", short: None, value: Some("<p>"), help: "How much overexposed colors fade to white", commands: ALL },
+    OptionSpec { long: "--background", short: None, value: Some("<RRGGBB>"), help: "Opaque background color", commands: ALL },
+    OptionSpec { long: "--de-radius", short: None, value: Some("<max>"), help: "Maximum density estimation radius, 0 disables it, at most 32", commands: ALL },
+    OptionSpec { long: "--de-min-radius", short: None, value: Some("<min>"), help: "Minimum density estimation radius", commands: ALL },
+    OptionSpec { long: "--de-curve", short: None, value: Some("<c>"), help: "How fast the density estimation radius shrinks", commands: ALL },
+    OptionSpec { long: "--filter", short: None, value: Some("<name>"), help: "Reconstruction filter", commands: ALL },
+    OptionSpec { long: "--filter-radius", short: None, value: Some("<r>"), help: "Reconstruction filter radius in pixels", commands: ALL },
+];
+
+impl Config
+{
+    fn new(command: Command) -> Config
+    {
+        Config
+        {
+            command,
+            dimensions: if command == Command::Gallery { DEFAULT_THUMBNAIL_DIMENSIONS } else { DEFAULT_DIMENSIONS },
+            super_sampling_factor: SuperSamplingFactor::None,
+            preparation_rounds: DEFAULT_PREPARATION_ROUNDS,
+            budget: None,
+            seed: None,
+            genome: None,
+            output: None,
+            save_genome: None,
+            determinant_weights: false,
+            palette: None,
+            overrides: vec![],
+            threads: default_thread_count(),
+            kernel: Kernel::Scalar,
+            keyframes: vec![],
+            frames: DEFAULT_FRAMES,
+            shutter: 0.,
+            temporal_samples: DEFAULT_TEMPORAL_SAMPLES,
+            mate: None,
+            offspring: DEFAULT_OFFSPRING,
+            checkpoint: None,
+            checkpoint_interval: DEFAULT_CHECKPOINT_INTERVAL,
+            resume: None,
+        }
+    }
+
+    /// Parses the arguments following the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Invocation, String>
+    {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek().map(String::as_str)
+        {
+            Some("help") =>
+            {
+                args.next();
+
+                return match args.next()
+                {
+                    Some(name) => Command::from_name(&name)
+                        .map(|command| Invocation::Help(command_usage(command)))
+                        .ok_or(format!("Unknown command '{}'", name)),
+                    None => Ok(Invocation::Help(usage())),
+                };
+            },
+            Some(name) if !name.starts_with('-') =>
+            {
+                let command = Command::from_name(name).ok_or(format!("Unknown command '{}'", name))?;
+
+                args.next();
+                Some(command)
+            },
+            _ => None,
+        };
+
+        let mut config = Config::new(command.unwrap_or(Command::Interactive));
+        let mut given = vec![];
+
+        while let Some(arg) = args.next()
+        {
+            if arg == "--help" || arg == "-h"
+            {
+                return Ok(Invocation::Help(command.map_or_else(usage, command_usage)));
+            }
+
+            let option = OPTIONS
+                .iter()
+                .find(|o| o.long == arg || o.short == Some(&arg[..]))
+                .ok_or(format!("Unknown option '{}'", arg))?;
+
+            if !option.commands.contains(&config.command)
+            {
+                return Err(format!("{} is not an option of the {} command", option.long, config.command.name()));
+            }
+
+            let value = match option.value
+            {
+                Some(_) => args.next().ok_or(format!("Missing value after {}", arg))?,
+                None => String::new(),
+            };
+
+            config.set(option.long, value)?;
+            given.push(option);
+        }
+
+        if config.resume.is_some()
+        {
+            // The genome display options apply to every command.
+            if let Some(option) = given.iter().find(|o| RESUMED.contains(&o.long) || o.commands == ALL)
+            {
+                return Err(format!("--resume carries on with the settings of the checkpoint, {} cannot be given too", option.long));
+            }
+        }
+
+        config.validate()?;
+        Ok(Invocation::Run(Box::new(config)))
+    }
+
+    fn set(&mut self, option: &str, value: String) -> Result<(), String>
+    {
+        match option
+        {
+            "--genome" =>
+            {
+                GenomeFormat::from_path(value.as_ref())?;
+                self.genome = Some(PathBuf::from(value));
+            },
+            "--output" => self.output = Some(PathBuf::from(value)),
+            "--keyframe" =>
+            {
+                GenomeFormat::from_path(value.as_ref())?;
+                self.keyframes.push(PathBuf::from(value));
+            },
+            "--mate" =>
+            {
+                GenomeFormat::from_path(value.as_ref())?;
+                self.mate = Some(PathBuf::from(value));
+            },
+            "--count" => self.offspring = parse_count(&value, option)?,
+            "--frames" => self.frames = parse_count(&value, option)?,
+            "--motion-blur" =>
+            {
+                let shutter: f64 = value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))?;
+
+                if !(0. ..=1.).contains(&shutter)
+                {
+                    return Err(format!("Invalid motion blur {}, the shutter must be in [0, 1]", shutter));
+                }
+
+                self.shutter = shutter;
+            },
+            "--temporal-samples" => self.temporal_samples = parse_count(&value, option)?,
+            "--save-genome" =>
+            {
+                GenomeFormat::from_path(value.as_ref())?;
+                self.save_genome = Some(PathBuf::from(value));
+            },
+            "--checkpoint" => self.checkpoint = Some(PathBuf::from(value)),
+            "--checkpoint-interval" =>
+            {
+                let seconds: f64 = value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))?;
+
+                if !(seconds.is_finite() && seconds > 0.)
+                {
+                    return Err(format!("Invalid checkpoint interval {}, it must be strictly positive", seconds));
+                }
+
+                self.checkpoint_interval = Duration::try_from_secs_f64(seconds).map_err(|_| format!("Invalid value '{}' for {}", value, option))?;
+            },
+            "--resume" => self.resume = Some(PathBuf::from(value)),
+            "--size" => self.dimensions = parse_size(&value)?,
+            "--supersampling" =>
+            {
+                self.super_sampling_factor = value
+                    .parse()
+                    .ok()
+                    .and_then(SuperSamplingFactor::from_factor)
+                    .ok_or(format!("Invalid supersampling '{}', expected 1, 2, 4 or 8", value))?;
+            },
+            "--quality" =>
+            {
+                let quality: f64 = value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))?;
+
+                if !(quality.is_finite() && quality > 0.)
+                {
+                    return Err(format!("Invalid quality {}, it must be strictly positive", quality));
+                }
+
+                self.set_budget(Budget::Quality(quality))?;
+            },
+            "--samples" => self.set_budget(Budget::Samples(parse_count(&value, option)?))?,
+            "--preparation-rounds" => self.preparation_rounds = value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))?,
+            "--seed" => self.seed = Some(value.parse().map_err(|_| format!("Invalid seed '{}'", value))?),
+            "--threads" => self.threads = parse_count(&value, option)?,
+            "--batched" => self.kernel = Kernel::Batched,
+            "--determinant-weights" => self.determinant_weights = true,
+            "--palette" =>
+            {
+                self.palette = Some(match Palette::builtin(&value)
+                {
+                    Some(builtin) => builtin,
+                    None => Palette::load(value.as_ref())?,
+                });
+            },
+            "--filter" =>
+            {
+                let kind = FilterKind::from_name(&value).ok_or(format!("Unknown filter '{}'", value))?;
+
+                self.overrides.push(Box::new(move |g| g.reconstruction_filter.kind = kind));
+            },
+            "--symmetry" =>
+            {
+                let symmetry = Symmetry::from_flam3(value.parse().map_err(|_| format!("Invalid symmetry '{}'", value))?);
+
+                symmetry.validate()?;
+                self.overrides.push(Box::new(move |g| g.symmetry = symmetry));
+            },
+            "--background" =>
+            {
+                let background = match palette::parse_hex_colors(&value)?[..]
+                {
+                    [color] => color,
+                    _ => return Err(format!("Invalid background color '{}', expected RRGGBB", value)),
+                };
+
+                self.overrides.push(Box::new(move |g| g.tone_mapping.background = Some(background)));
+            },
+            _ =>
+            {
+                let value: f64 = value.parse().map_err(|_| format!("Invalid value '{}' for {}", value, option))?;
+
+                self.overrides.push(match option
+                {
+                    "--brightness" => Box::new(move |g| g.tone_mapping.brightness = value),
+                    "--gamma" => Box::new(move |g| g.tone_mapping.gamma = value),
+                    "--gamma-threshold" => Box::new(move |g| g.tone_mapping.gamma_threshold = value),
+                    "--vibrancy" => Box::new(move |g| g.tone_mapping.vibrancy = value),
+                    "--highlight-power" => Box::new(move |g| g.tone_mapping.highlight_power = value),
+                    "--de-radius" => Box::new(move |g| g.density_estimation.max_radius = value),
+                    "--de-min-radius" => Box::new(move |g| g.density_estimation.min_radius = value),
+                    "--de-curve" => Box::new(move |g| g.density_estimation.curve = value),
+                    "--filter-radius" => Box::new(move |g| g.reconstruction_filter.radius = value),
+                    _ => unreachable!("option {} has no handler", option),
+                });
+            },
+        }
+
+        Ok(())
+    }
+
+    fn set_budget(&mut self, budget: Budget) -> Result<(), String>
+    {
+        match (self.budget, budget)
+        {
+            (Some(Budget::Samples(_)), Budget::Quality(_)) | (Some(Budget::Quality(_)), Budget::Samples(_)) =>
+            {
+                Err(String::from("--quality and --samples both set the sample budget, only one can be given"))
+            },
+            _ =>
+            {
+                self.budget = Some(budget);
+                Ok(())
+            },
+        }
+    }
+
+    /// Checks what each command needs once all the options are known.
+    fn validate(&self) -> Result<(), String>
+    {
+        let command = self.command.name();
+
+        match self.command
+        {
+            Command::Interactive => {},
+            Command::Render =>
+            {
+                let output = self.output.as_ref().ok_or("The render command needs an image --output")?;
+
+                ImageFormat::from_path(output)?;
+            },
+            Command::Random | Command::Convert =>
+            {
+                if self.command == Command::Convert && self.genome.is_none()
+                {
+                    return Err(String::from("The convert command needs a --genome to read"));
+                }
+
+                let output = self.output.as_ref().ok_or(format!("The {} command needs a genome --output", command))?;
+
+                GenomeFormat::from_path(output)?;
+            },
+            Command::Animate =>
+            {
+                if self.keyframes.len() < 2
+                {
+                    return Err(String::from("The animate command needs at least two --keyframe"));
+                }
+
+                let output = self.output.as_ref().ok_or("The animate command needs an image --output")?;
+
+                ImageFormat::from_path(output)?;
+            },
+            Command::Gallery =>
+            {
+                if self.mate.is_some() && self.genome.is_none()
+                {
+                    return Err(String::from("The gallery needs a --genome to cross with its --mate"));
+                }
+
+                let output = self.output.as_ref().ok_or("The gallery command needs an image --output")?;
+
+                if ImageFormat::from_path(output)? == ImageFormat::Pfm
+                {
+                    return Err(String::from("The gallery is labelled once tone mapped, it cannot be saved as .pfm"));
+                }
+            },
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_size(value: &str) -> Result<Dimensions, String>
+{
+    let invalid = || format!("Invalid size '{}', expected WIDTHxHEIGHT", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let (width, height): (u32, u32) = (width.parse().map_err(|_| invalid())?, height.parse().map_err(|_| invalid())?);
+
+    if width == 0 || height == 0
+    {
+        return Err(invalid());
+    }
+
+    Ok(Dimensions { width, height })
+}
+
+fn parse_count(value: &str, option: &str) -> Result<usize, String>
+{
+    match value.parse()
+    {
+        Ok(0) | Err(_) => Err(format!("Invalid value '{}' for {}, expected a positive count", value, option)),
+        Ok(count) => Ok(count),
+    }
+}
+
+fn default_thread_count() -> usize
+{
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+pub fn usage() -> String
+{
+    let mut text = format!("Usage: {} [<command>] [options]\n\nCommands:\n", PROGRAM);
+
+    for command in COMMANDS.iter()
+    {
+        text.push_str(&format!("  {:<13}{}\n", command.name(), command.summary()));
+    }
+
+    text.push_str(&format!("\nRun '{} <command> --help' for the options of a command.", PROGRAM));
+    text
+}
+
+pub fn command_usage(command: Command) -> String
+{
+    let options: Vec<(String, &str)> = OPTIONS
+        .iter()
+        .filter(|o| o.commands.contains(&command))
+        .map(|o|
+        {
+            let short = o.short.map_or(String::from("    "), |short| format!("{}, ", short));
+
+            (format!("{}{} {}", short, o.long, o.value.unwrap_or_default()), o.help)
+        })
+        .collect();
+    let width = options.iter().map(|(flags, _)| flags.len()).max().unwrap_or(0);
+    let mut text = format!("Usage: {} {} [options]\n\n{}\n\nOptions:\n", PROGRAM, command.name(), command.summary());
+
+    for (flags, help) in options.iter()
+    {
+        text.push_str(&format!("  {:<width$}  {}\n", flags, help, width = width));
+    }
+
+    text.push_str(&format!("  {:<width$}  {}\n\n", "-h, --help", "Print this help", width = width));
+    text.push_str(&format!("Builtin palettes: {}\n", Palette::builtin_names().collect::<Vec<&str>>().join(", ")));
+    text.push_str(&format!("Filters: {}", FilterKind::ALL.iter().map(FilterKind::name).collect::<Vec<&str>>().join(", ")));
+    text
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use flame_renderer::rustyflame::genome::Genome;
+    use flame_renderer::rustyflame::image::Dimensions;
+    use flame_renderer::rustyflame::simulator::Kernel;
+    use flame_renderer::rustyflame::symmetry::Symmetry;
+
+    use super::{ Budget, Command, Config, Invocation, DEFAULT_CHECKPOINT_INTERVAL, DEFAULT_DIMENSIONS, DEFAULT_OFFSPRING, DEFAULT_TEMPORAL_SAMPLES, DEFAULT_THUMBNAIL_DIMENSIONS };
+
+    fn parse(args: &str) -> Result<Invocation, String>
+    {
+        Config::parse(args.split_whitespace().map(String::from))
+    }
+
+    fn config(args: &str) -> Config
+    {
+        match parse(args)
+        {
+            Ok(Invocation::Run(config)) => *config,
+            Ok(Invocation::Help(_)) => panic!("'{}' asked for help", args),
+            Err(e) => panic!("'{}' failed: {}", args, e),
+        }
+    }
+
+    fn help(args: &str) -> String
+    {
+        match parse(args)
+        {
+            Ok(Invocation::Help(text)) => text,
+            _ => panic!("'{}' did not ask for help", args),
+        }
+    }
+
+    #[test]
+    fn test_defaults_to_interactive()
+    {
+        let defaults = config("");
+
+        assert_eq!(defaults.command, Command::Interactive);
+        assert_eq!(defaults.dimensions, DEFAULT_DIMENSIONS);
+        assert_eq!(defaults.super_sampling_factor as usize, 1);
+        assert_eq!(defaults.budget, None);
+        assert!(defaults.threads >= 1);
+        assert_eq!(config("--seed 3 -j 2").seed, Some(3));
+    }
+
+    #[test]
+    fn test_render_options()
+    {
+        let config = config("render --size 640x480 -s 4 -n 5000 --seed 42 -j 3 -g flame.json -o out.png --preparation-rounds 10");
+
+        assert_eq!(config.command, Command::Render);
+        assert_eq!(config.dimensions, Dimensions { width: 640, height: 480 });
+        assert_eq!(config.super_sampling_factor as usize, 4);
+        assert_eq!(config.budget, Some(Budget::Samples(5000)));
+        assert_eq!(config.seed, Some(42));
+        assert_eq!(config.threads, 3);
+        assert_eq!(config.genome.unwrap().to_str(), Some("flame.json"));
+        assert_eq!(config.output.unwrap().to_str(), Some("out.png"));
+        assert_eq!(config.preparation_rounds, 10);
+        assert_eq!(config.kernel, Kernel::Scalar);
+    }
+
+    #[test]
+    fn test_animate_options()
+    {
+        let config = config("animate -k a.flame -k b.json --keyframe c.flame -o frames/f.png --frames 24 --motion-blur 0.5 -q 10 --batched");
+
+        assert_eq!(config.command, Command::Animate);
+        assert_eq!(config.keyframes, vec![PathBuf::from("a.flame"), PathBuf::from("b.json"), PathBuf::from("c.flame")]);
+        assert_eq!(config.frames, 24);
+        assert_eq!(config.shutter, 0.5);
+        assert_eq!(config.temporal_samples, DEFAULT_TEMPORAL_SAMPLES);
+        assert_eq!(config.budget, Some(Budget::Quality(10.)));
+        assert_eq!(config.kernel, Kernel::Batched);
+    }
+
+    #[test]
+    fn test_gallery_options()
+    {
+        let mutants = config("gallery -o sheep.png");
+
+        assert_eq!(mutants.command, Command::Gallery);
+        assert_eq!(mutants.dimensions, DEFAULT_THUMBNAIL_DIMENSIONS);
+        assert_eq!(mutants.offspring, DEFAULT_OFFSPRING);
+        assert_eq!(mutants.mate, None);
+
+        let children = config("gallery -g a.json -m b.flame --count 4 --size 100x80 -o sheep.png");
+
+        assert_eq!(children.mate, Some(PathBuf::from("b.flame")));
+        assert_eq!(children.offspring, 4);
+        assert_eq!(children.dimensions, Dimensions { width: 100, height: 80 });
+    }
+
+    #[test]
+    fn test_checkpoint_options()
+    {
+        let defaults = config("render -o out.png");
+
+        assert_eq!(defaults.checkpoint, None);
+        assert_eq!(defaults.checkpoint_interval, DEFAULT_CHECKPOINT_INTERVAL);
+        assert_eq!(defaults.resume, None);
+
+        let config = config("render -o out.png --checkpoint out.ckp --checkpoint-interval 0.5 --resume old.ckp");
+
+        assert_eq!(config.checkpoint, Some(PathBuf::from("out.ckp")));
+        assert_eq!(config.checkpoint_interval, Duration::from_millis(500));
+        assert_eq!(config.resume, Some(PathBuf::from("old.ckp")));
+    }
+
+    #[test]
+    fn test_quality_budget()
+    {
+        let config = config("interactive --size 100x20 -q 2.5 -q 3");
+
+        assert_eq!(config.budget, Some(Budget::Quality(3.)));
+        assert_eq!(config.budget.unwrap().samples(&config.dimensions), 6000);
+        assert_eq!(Budget::Samples(10).samples(&config.dimensions), 10);
+    }
+
+    #[test]
+    fn test_overrides_apply_in_order()
+    {
+        let config = config("convert -g in.flame -o out.json --gamma 3 --filter gaussian --gamma 4 --background 102030 --symmetry -5");
+        let mut genome = Genome::default();
+
+        config.overrides.iter().for_each(|apply| apply(&mut genome));
+
+        assert_eq!(genome.tone_mapping.gamma, 4.);
+        assert_eq!(genome.tone_mapping.background.map(|bg| (bg.r, bg.g, bg.b)), Some((0x10, 0x20, 0x30)));
+        assert_eq!(genome.reconstruction_filter.kind.name(), "gaussian");
+        assert_eq!(genome.symmetry, Symmetry::Dihedral(5));
+    }
+
+    #[test]
+    fn test_validation_errors()
+    {
+        let errors =
+        [
+            "paint",
+            "render",
+            "render -o out.json",
+            "random -o out.png",
+            "convert -o out.json",
+            "convert -g in.json -o out.json --size 10x10",
+            "interactive -o out.png",
+            "render -o out.png --size 0x10",
+            "render -o out.png --size 10",
+            "render -o out.png -s 3",
+            "render -o out.png -n 0",
+            "render -o out.png -q 0",
+            "render -o out.png -q 10 -n 1000",
+            "render -o out.png -j",
+            "render -o out.png --gamma high",
+            "render -o out.png --filter sinc",
+            "render -o out.png --bogus",
+            "render -o out.png -g flame.toml",
+            "animate -k a.flame -o out.png",
+            "animate -k a.flame -k b.flame -o out.flame",
+            "animate -k a.flame -k b.flame -o out.png --motion-blur 2",
+            "animate -k a.flame -k b.flame -o out.png -g c.flame",
+            "render -o out.png --frames 10",
+            "gallery",
+            "gallery -o sheep.json",
+            "gallery -m b.flame -o sheep.png",
+            "gallery -o sheep.png --count 0",
+            "gallery -o sheep.pfm",
+            "render -o out.png --mate b.flame",
+            "random -o out.json --batched",
+            "render -o out.png --resume out.ckp -g flame.json",
+            "render -o out.png --resume out.ckp --size 10x10",
+            "render -o out.png --resume out.ckp -s 2",
+            "render -o out.png --resume out.ckp --seed 1",
+            "render -o out.png --resume out.ckp --preparation-rounds 5",
+            "render -o out.png --resume out.ckp --gamma 3",
+            "interactive --palette fire --resume out.ckp",
+            "render -o out.png --checkpoint-interval 0",
+            "render -o out.png --checkpoint-interval 1e30",
+            "animate -k a.flame -k b.flame -o out.png --checkpoint out.ckp",
+            "help paint",
+        ];
+
+        for args in errors.iter()
+        {
+            assert!(parse(args).is_err(), "'{}' should fail", args);
+        }
+
+        assert!(parse("random -o out.flame --seed 1").is_ok());
+    }
+
+    #[test]
+    fn test_help()
+    {
+        let global = help("--help");
+
+        for command in ["interactive", "render", "random", "convert", "animate", "gallery"].iter()
+        {
+            assert!(global.contains(command));
+        }
+
+        let render = help("render -o out.png -h");
+
+        assert!(render.contains("--supersampling"));
+        assert!(render.contains("-o, --output <path>"));
+        assert!(!help("help convert").contains("--size"));
+    }
+}
@@ -1,35 +1,465 @@
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 extern crate contracts;
 
-mod rustyflame;
+mod cli;
 
-use rustyflame::render::{ Renderer, RenderConfig, Dimensions };
-use rustyflame::simulator::{ Simulator, SuperSamplingFactor };
+use std::env;
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use std::process;
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::sync::Arc;
+use std::time::{ Duration, Instant };
+#[cfg(feature = "sdl")]
+use std::thread;
 
+use rand::{ Rng, SeedableRng };
+
+use flame_renderer::rustyflame::animation::{ self, Animation };
+use flame_renderer::rustyflame::breeding::Breeder;
+use flame_renderer::rustyflame::color::Rgb;
+use flame_renderer::rustyflame::export::{ self, ImageFormat };
+use flame_renderer::rustyflame::generator::Generator;
+use flame_renderer::rustyflame::genome::Genome;
+use flame_renderer::rustyflame::image::{ Dimensions, RgbaImage };
+use flame_renderer::rustyflame::overlay;
+use flame_renderer::rustyflame::progress::{ self, Progress };
+use flame_renderer::rustyflame::simulator::{ self, FlameRng, Simulator };
+
+#[cfg(feature = "sdl")]
+use flame_renderer::rustyflame::camera::Camera;
+#[cfg(feature = "sdl")]
+use flame_renderer::rustyflame::editor::Editor;
+#[cfg(feature = "sdl")]
+use flame_renderer::rustyflame::math::Vec2D;
+#[cfg(feature = "sdl")]
+use flame_renderer::rustyflame::render::{ Renderer, RenderConfig };
+
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
+#[cfg(feature = "sdl")]
 use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use sdl2::mouse::MouseButton;
 
+use cli::{ Budget, Command, Config, Invocation };
 
-fn main() -> Result<(), String> 
+/// Samples per `iterate` call, small enough to keep checking the clock.
+const HEADLESS_BATCH: usize = 2_000_000;
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+/// Distance in pixels of the gallery labels from the corner of their flame.
+const LABEL_MARGIN: u32 = 4;
+#[cfg(feature = "sdl")]
+const INTERACTIVE_BATCH: usize = 50_000;
+#[cfg(feature = "sdl")]
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+#[cfg(feature = "sdl")]
+const IDLE_FRAME: Duration = Duration::from_millis(16);
+#[cfg(feature = "sdl")]
+const ZOOM_STEP: f64 = 1.1;
+#[cfg(feature = "sdl")]
+const ROTATION_STEP: f64 = 5.;
+/// The preview shown while editing is this many times smaller along each axis, so that it fills in quickly.
+#[cfg(feature = "sdl")]
+const PREVIEW_DIVISOR: u32 = 4;
+/// Time without edits after which the full resolution render takes over from the preview.
+#[cfg(feature = "sdl")]
+const EDIT_SETTLE: Duration = Duration::from_millis(500);
+
+fn main()
+{
+    let config = match Config::parse(env::args().skip(1))
+    {
+        Ok(Invocation::Run(config)) => *config,
+        Ok(Invocation::Help(text)) =>
+        {
+            println!("{}", text);
+            return;
+        },
+        Err(err) =>
+        {
+            eprintln!("{}", err);
+            eprintln!("Run '{} --help' for usage", cli::PROGRAM);
+            process::exit(2);
+        },
+    };
+
+    if let Err(e) = run(config)
+    {
+        eprintln!("Error while rendering : {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(config: Config) -> Result<(), String>
+{
+    match config.command
+    {
+        Command::Convert =>
+        {
+            let genome = Genome::load(config.genome.as_ref().expect("validated by the command line parser"))?;
+
+            save_genome(&prepare_genome(&config, genome)?, config.output.as_ref().expect("validated by the command line parser"))
+        },
+        Command::Random =>
+        {
+            let seed = config.seed.unwrap_or_else(simulator::random_seed);
+
+            println!("Seed: {}", seed);
+            let genome = Generator::default().generate(&mut FlameRng::seed_from_u64(seed))?;
+
+            save_genome(&prepare_genome(&config, genome)?, config.output.as_ref().expect("validated by the command line parser"))
+        },
+        Command::Render | Command::Interactive =>
+        {
+            let mut simulator = match &config.resume
+            {
+                Some(path) =>
+                {
+                    let simulator = Simulator::load_checkpoint(path)?;
+
+                    println!("Resumed {} samples from {}", progress::format_count(simulator.sample_count()), path.display());
+                    simulator
+                },
+                None => create_simulator(&config)?,
+            };
+
+            simulator.set_thread_count(config.threads);
+            simulator.set_kernel(config.kernel);
+
+            if let Some(path) = &config.save_genome
+            {
+                save_genome(&simulator.genome(), path)?;
+            }
+
+            let dimensions = simulator.dimensions();
+            let checkpointer = config.checkpoint.as_ref().map(|path| Checkpointer::new(path.clone(), config.checkpoint_interval));
+
+            match &config.output
+            {
+                Some(output) =>
+                {
+                    let budget = config.budget.unwrap_or(Budget::Quality(cli::DEFAULT_QUALITY));
+
+                    run_headless(simulator, output, budget.samples(&dimensions), checkpointer)
+                },
+                None => run_interactive(simulator, config.budget.map(|budget| budget.samples(&dimensions)), checkpointer),
+            }
+        },
+        Command::Animate => run_animation(&config),
+        Command::Gallery => run_gallery(&config),
+    }
+}
+
+fn create_simulator(config: &Config) -> Result<Simulator, String>
+{
+    let seed = config.seed.unwrap_or_else(simulator::random_seed);
+    let mut simulator = Simulator::with_seed(config.dimensions, config.preparation_rounds, config.super_sampling_factor, seed);
+
+    println!("Seed: {}", seed);
+
+    let genome = match &config.genome
+    {
+        Some(path) => Genome::load(path)?,
+        None => Generator::default().generate(&mut FlameRng::seed_from_u64(seed))?,
+    };
+
+    simulator.set_genome(prepare_genome(config, genome)?)?;
+
+    Ok(simulator)
+}
+
+/// Saves the accumulation to its file every so often, overwriting the previous checkpoint.
+struct Checkpointer
+{
+    path: PathBuf,
+    interval: Duration,
+    last: Instant,
+}
+
+impl Checkpointer
+{
+    fn new(path: PathBuf, interval: Duration) -> Checkpointer
+    {
+        Checkpointer { path, interval, last: Instant::now() }
+    }
+
+    fn maybe_save(&mut self, simulator: &Simulator) -> Result<(), String>
+    {
+        if self.last.elapsed() >= self.interval
+        {
+            self.save(simulator)?;
+        }
+
+        Ok(())
+    }
+
+    fn save(&mut self, simulator: &Simulator) -> Result<(), String>
+    {
+        simulator.save_checkpoint(&self.path)?;
+        self.last = Instant::now();
+
+        Ok(())
+    }
+}
+
+/// Applies the display settings given on the command line over the genome ones.
+fn prepare_genome(config: &Config, mut genome: Genome) -> Result<Genome, String>
+{
+    if config.determinant_weights
+    {
+        genome.use_determinant_weights();
+    }
+
+    if let Some(palette) = &config.palette
+    {
+        genome.palette = palette.clone();
+    }
+
+    for apply in config.overrides.iter()
+    {
+        apply(&mut genome);
+    }
+
+    genome.validate()?;
+    Ok(genome)
+}
+
+fn save_genome(genome: &Genome, path: &Path) -> Result<(), String>
+{
+    genome.save(path)?;
+    println!("Saved {}", path.display());
+
+    Ok(())
+}
+
+/// Samples until the budget is spent, reporting progress on a single line along the way. When
+/// checkpointing, Ctrl-C saves the accumulation before stopping instead of losing it.
+fn run_headless(mut simulator: Simulator, output: &Path, samples: usize, mut checkpointer: Option<Checkpointer>) -> Result<(), String>
+{
+    let start = Instant::now();
+    let mut progress = Progress::new(Some(samples), simulator.dimensions());
+    let mut last_report = start;
+    let interrupted = Arc::new(AtomicBool::new(false));
+
+    if checkpointer.is_some()
+    {
+        let interrupted = interrupted.clone();
+
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst)).map_err(|e| format!("Cannot handle Ctrl-C: {}", e))?;
+    }
+
+    let samples_before = simulator.sample_count();
+
+    progress.update(samples_before);
+
+    while let Some(remaining) = progress.remaining().filter(|remaining| *remaining > 0)
+    {
+        simulator.iterate(remaining.min(HEADLESS_BATCH));
+        progress.update(simulator.sample_count());
+
+        if let Some(checkpointer) = checkpointer.as_mut()
+        {
+            if interrupted.load(Ordering::SeqCst)
+            {
+                if last_report != start
+                {
+                    println!();
+                }
+
+                checkpointer.save(&simulator)?;
+
+                return Err(format!("Interrupted, run again with --resume {} to carry on", checkpointer.path.display()));
+            }
+
+            checkpointer.maybe_save(&simulator)?;
+        }
+
+        if last_report.elapsed() >= REPORT_INTERVAL
+        {
+            print!("\r{}    ", progress.status());
+            io::stdout().flush().map_err(|e| e.to_string())?;
+            last_report = Instant::now();
+        }
+    }
+
+    if last_report != start
+    {
+        println!();
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let samples = simulator.sample_count();
+
+    println!
+    (
+        "{} samples ({:.1} per pixel) on {} threads in {:.2}s ({:.0} samples/s)",
+        samples, progress::quality(samples, &simulator.dimensions()), simulator.thread_count(), elapsed, (samples - samples_before) as f64 / elapsed
+    );
+
+    print_stats(&simulator);
+
+    if let Some(checkpointer) = checkpointer.as_mut()
+    {
+        checkpointer.save(&simulator)?;
+        println!("Saved {}", checkpointer.path.display());
+    }
+
+    save_render(&simulator, output)?;
+    println!("Saved {}", output.display());
+
+    Ok(())
+}
+
+fn print_stats(simulator: &Simulator)
+{
+    for line in simulator.stats().report()
+    {
+        println!("{}", line);
+    }
+}
+
+fn save_render(simulator: &Simulator, output: &Path) -> Result<(), String>
+{
+    match ImageFormat::from_path(output)?
+    {
+        ImageFormat::Pfm => export::save_linear_image(&simulator.render_linear(), output),
+        _ => export::save_image(&simulator.render(), output),
+    }
+}
+
+/// Renders each frame headlessly to its own numbered image. With motion blur, the samples of a
+/// frame are shared between the genomes along its exposure, while the view is the one at the frame time.
+fn run_animation(config: &Config) -> Result<(), String>
+{
+    let keyframes = config.keyframes
+        .iter()
+        .map(|path| prepare_genome(config, Genome::load(path)?))
+        .collect::<Result<Vec<Genome>, String>>()?;
+    let animation = Animation::new(keyframes)?;
+    let output = config.output.as_ref().expect("validated by the command line parser");
+    let seed = config.seed.unwrap_or_else(simulator::random_seed);
+    let samples = config.budget.unwrap_or(Budget::Quality(cli::DEFAULT_QUALITY)).samples(&config.dimensions);
+    let start = Instant::now();
+
+    println!("Seed: {}", seed);
+
+    for frame in 0..config.frames
+    {
+        let mut simulator = Simulator::with_seed(config.dimensions, config.preparation_rounds, config.super_sampling_factor, seed);
+        let times = animation::motion_blur_times(frame, config.frames, config.shutter, config.temporal_samples);
+
+        simulator.set_thread_count(config.threads);
+        simulator.set_kernel(config.kernel);
+        simulator.set_genome(animation.genome_at(animation::frame_time(frame, config.frames)))?;
+
+        for (index, time) in times.iter().enumerate()
+        {
+            simulator.morph(&animation.genome_at(*time))?;
+            simulator.iterate(samples / times.len() + usize::from(index < samples % times.len()));
+        }
+
+        let path = animation::frame_path(output, frame, config.frames);
+
+        save_render(&simulator, &path)?;
+        println!("Frame {}/{} saved to {} ({} elapsed)", frame + 1, config.frames, path.display(), progress::format_duration(start.elapsed()));
+    }
+
+    Ok(())
+}
+
+/// Renders offspring of the genome in a numbered grid, like the sheep of Electric Sheep, and
+/// saves their genomes next to the image with the same numbers, to go on from the one we like.
+fn run_gallery(config: &Config) -> Result<(), String>
+{
+    let output = config.output.as_ref().expect("validated by the command line parser");
+    let seed = config.seed.unwrap_or_else(simulator::random_seed);
+    let mut rng = FlameRng::seed_from_u64(seed);
+
+    println!("Seed: {}", seed);
+
+    let parent = match &config.genome
+    {
+        Some(path) => Genome::load(path)?,
+        None => Generator::default().generate(&mut rng)?,
+    };
+    let parent = prepare_genome(config, parent)?;
+    let mate = config.mate.as_ref().map(|path| prepare_genome(config, Genome::load(path)?)).transpose()?;
+    let breeder = Breeder::default();
+
+    let cell = config.dimensions;
+    let columns = (config.offspring as f64).sqrt().ceil() as u32;
+    let rows = (config.offspring as u32).div_ceil(columns);
+    let mut sheet = RgbaImage::new(Dimensions { width: cell.width * columns, height: cell.height * rows });
+    let samples = config.budget.unwrap_or(Budget::Quality(cli::DEFAULT_QUALITY)).samples(&cell);
+    let genome_output = output.with_extension("json");
+
+    for index in 0..config.offspring
+    {
+        let genome = match &mate
+        {
+            Some(mate) => breeder.crossover(&parent, mate, &mut rng)?,
+            None => breeder.mutate(&parent, &mut rng)?,
+        };
+        let mut simulator = Simulator::with_seed(cell, config.preparation_rounds, config.super_sampling_factor, rng.gen());
+        let (x, y) = ((index as u32 % columns) * cell.width, (index as u32 / columns) * cell.height);
+        let number = index + 1;
+
+        simulator.set_thread_count(config.threads);
+        simulator.set_kernel(config.kernel);
+        simulator.set_genome(genome)?;
+        simulator.iterate(samples);
+
+        sheet.paste(&simulator.render(), x, y);
+        overlay::draw_text(&mut sheet, x + LABEL_MARGIN, y + LABEL_MARGIN, &number.to_string(), Rgb::WHITE);
+        save_genome(&simulator.genome(), &animation::frame_path(&genome_output, number, config.offspring + 1))?;
+    }
+
+    export::save_image(&sheet, output)?;
+    println!("Saved {}", output.display());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sdl"))]
+fn run_interactive(_simulator: Simulator, _budget: Option<usize>, _checkpointer: Option<Checkpointer>) -> Result<(), String>
+{
+    Err(String::from("Built without the 'sdl' feature, only the render, random and convert commands are available"))
+}
+
+#[cfg(feature = "sdl")]
+fn run_interactive(mut simulator: Simulator, budget: Option<usize>, mut checkpointer: Option<Checkpointer>) -> Result<(), String>
 {
     let sdl_context = sdl2::init()?;
 
     let config = RenderConfig 
     {
         window_title: String::from("Flame renderer"),
-        window_dimensions: Dimensions { width: 1200, height: 960 },
-        //window_dimensions: Dimensions { width: 400, height: 400 },
+        window_dimensions: simulator.dimensions(),
     };
 
-    let renderer = Renderer::new(&sdl_context, config)?;
-    let mut simulator = Simulator::new(renderer, 50, SuperSamplingFactor::None)?;
+    let mut frame = RgbaImage::new(simulator.dimensions());
+    let mut renderer = Renderer::new(&sdl_context, config)?;
 
     let mut evt_pump = sdl_context.event_pump()?;
 
     let mut iter = 0usize;
+    let mut progress = Progress::new(budget, simulator.dimensions());
+    // None until the first frame, or when the frame must be redrawn without new samples.
+    let mut last_redraw: Option<Instant> = None;
+    let mut show_status = true;
+    // The editor holds the genome being edited, and the preview a low resolution render of it.
+    let mut editor: Option<Editor> = None;
+    let mut preview: Option<(Simulator, RgbaImage)> = None;
+    let mut last_edit = Instant::now();
+    // Draws the mutants, independently of the chaos game.
+    let mut rng = FlameRng::seed_from_u64(simulator.seed());
 
     'mainloop: loop 
     {
+        let mut edited = false;
+
         for evt in evt_pump.poll_iter()
         {
             match evt
@@ -44,20 +474,292 @@ fn main() -> Result<(), String>
                 {
                     keycode: Some(Keycode::R),
                     ..
-                } => simulator.reset(),
+                } =>
+                {
+                    simulator.reset();
+
+                    if editor.is_some()
+                    {
+                        editor = Some(Editor::new(simulator.genome()));
+                        preview = None;
+                    }
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::M),
+                    ..
+                } =>
+                {
+                    match Breeder::default().mutate(&simulator.genome(), &mut rng)
+                    {
+                        Ok(mutant) => simulator.set_genome(mutant)?,
+                        Err(e) => eprintln!("{}", e),
+                    }
+
+                    if editor.is_some()
+                    {
+                        editor = Some(Editor::new(simulator.genome()));
+                        preview = None;
+                    }
+
+                    last_redraw = None;
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::S),
+                    ..
+                } => 
+                {
+                    let path = PathBuf::from(format!("flame_{}.png", iter));
+
+                    simulator.render_into(&mut frame);
+                    export::save_image(&frame, &path)?;
+                    println!("Saved {}", path.display());
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::G),
+                    ..
+                } => 
+                {
+                    let path = PathBuf::from(format!("flame_{}.json", iter));
+
+                    match &editor
+                    {
+                        Some(editor) => Genome { camera: simulator.camera(), ..editor.genome().clone() }.save(&path)?,
+                        None => simulator.save_genome(&path)?,
+                    }
+
+                    println!("Saved {}", path.display());
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::Tab),
+                    ..
+                } =>
+                {
+                    // Leaving the editor applies any pending edit right away, below.
+                    editor = match editor
+                    {
+                        Some(_) => None,
+                        None => Some(Editor::new(simulator.genome())),
+                    };
+                    last_redraw = None;
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(key @ (Keycode::Left | Keycode::Right | Keycode::N | Keycode::Delete | Keycode::Backspace)),
+                    ..
+                } if editor.is_some() =>
+                {
+                    let editor = editor.as_mut().expect("checked by the guard");
+
+                    match key
+                    {
+                        Keycode::Left => editor.select_previous(),
+                        Keycode::Right => editor.select_next(),
+                        Keycode::N =>
+                        {
+                            editor.add_transform();
+                            edited = true;
+                        },
+                        _ => edited = editor.delete_selected(),
+                    }
+
+                    last_redraw = None;
+                },
+                Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } =>
+                {
+                    if let Some(editor) = editor.as_mut()
+                    {
+                        if editor.press(Vec2D { x: x as f64, y: y as f64 }, &simulator.camera(), &simulator.dimensions())
+                        {
+                            last_redraw = None;
+                        }
+                    }
+                },
+                Event::MouseButtonUp { mouse_btn: MouseButton::Left, .. } =>
+                {
+                    if let Some(editor) = editor.as_mut()
+                    {
+                        editor.release();
+                    }
+                },
+                Event::MouseMotion { x, y, .. } if editor.as_ref().is_some_and(Editor::is_dragging) =>
+                {
+                    let editor = editor.as_mut().expect("checked by the guard");
+
+                    edited |= editor.drag_to(Vec2D { x: x as f64, y: y as f64 }, &simulator.camera(), &simulator.dimensions());
+                },
+                Event::MouseMotion { mousestate, xrel, yrel, .. } if mousestate.left() =>
+                {
+                    let mut camera = simulator.camera();
+
+                    camera.pan(xrel as f64, yrel as f64, &simulator.dimensions());
+                    set_camera(&mut simulator, &mut preview, camera);
+                },
+                Event::MouseWheel { y, .. } if y != 0 =>
+                {
+                    let mut camera = simulator.camera();
+
+                    camera.zoom_by(ZOOM_STEP.powi(y));
+                    set_camera(&mut simulator, &mut preview, camera);
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(key @ (Keycode::Q | Keycode::E)),
+                    ..
+                } =>
+                {
+                    let mut camera = simulator.camera();
+                    let direction = if key == Keycode::Q { -1. } else { 1. };
+
+                    camera.rotate_by(direction * ROTATION_STEP.to_radians());
+                    set_camera(&mut simulator, &mut preview, camera);
+                },
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::C),
+                    ..
+                } => set_camera(&mut simulator, &mut preview, Camera::default()),
+                Event::KeyDown
+                {
+                    keycode: Some(Keycode::I),
+                    ..
+                } =>
+                {
+                    show_status = !show_status;
+                    last_redraw = None;
+                },
                 _ => {}
             }
         }
 
-        iter += 1;
+        if edited
+        {
+            let genome = Genome { camera: simulator.camera(), ..editor.as_ref().expect("only the editor edits").genome().clone() };
+
+            match preview.as_mut()
+            {
+                Some((preview, _)) => preview.set_genome(genome)?,
+                None => preview = Some(edit_preview(&simulator, genome)?),
+            }
+
+            last_edit = Instant::now();
+        }
+
+        let dragging = editor.as_ref().is_some_and(Editor::is_dragging);
+
+        // Once edits settle, the full resolution render restarts from the edited genome.
+        if preview.is_some() && (editor.is_none() || (!dragging && last_edit.elapsed() >= EDIT_SETTLE))
+        {
+            if let Some((preview, _)) = preview.take()
+            {
+                simulator.set_genome(Genome { camera: simulator.camera(), ..preview.genome() })?;
+            }
+
+            last_redraw = None;
+        }
+
+        if let Some((preview, preview_frame)) = preview.as_mut()
+        {
+            preview.iterate(INTERACTIVE_BATCH);
+            preview.render_into(preview_frame);
+            frame.copy_scaled_from(preview_frame);
+            draw_overlays(&mut frame, editor.as_ref(), &simulator.camera(), show_status, &progress);
+            renderer.display(&frame)?;
+
+            continue;
+        }
+
+        progress.update(simulator.sample_count());
+
+        let samples = progress.remaining().map_or(INTERACTIVE_BATCH, |remaining| remaining.min(INTERACTIVE_BATCH));
 
-        simulator.step();
+        if samples > 0
+        {
+            iter += samples;
+            simulator.iterate(samples);
+            progress.update(simulator.sample_count());
+
+            if let Some(checkpointer) = checkpointer.as_mut()
+            {
+                checkpointer.maybe_save(&simulator)?;
+            }
+        }
 
-        if iter % 10000 == 0
+        // Redraws on a fixed cadence while refining, always showing the last samples of the budget.
+        let redraw = match last_redraw
+        {
+            None => true,
+            Some(last) => samples > 0 && (last.elapsed() >= REDRAW_INTERVAL || progress.is_done()),
+        };
+
+        if redraw
+        {
+            simulator.render_into(&mut frame);
+            draw_overlays(&mut frame, editor.as_ref(), &simulator.camera(), show_status, &progress);
+            renderer.display(&frame)?;
+            last_redraw = Some(Instant::now());
+        }
+        else if samples == 0
         {
-            simulator.render()?;
+            // Once the budget is spent the picture no longer changes, only events are handled.
+            thread::sleep(IDLE_FRAME);
         }
     }
 
+    print_stats(&simulator);
+
+    // Closing the window or Ctrl-C, which SDL turns into a quit event, both end up here.
+    if let Some(checkpointer) = checkpointer.as_mut()
+    {
+        checkpointer.save(&simulator)?;
+        println!("Saved {}", checkpointer.path.display());
+    }
+
     Ok(())
 }
+
+/// Moves the camera of both the full resolution render and the edit preview, so that they stay aligned.
+#[cfg(feature = "sdl")]
+fn set_camera(simulator: &mut Simulator, preview: &mut Option<(Simulator, RgbaImage)>, camera: Camera)
+{
+    if let Some((preview, _)) = preview.as_mut()
+    {
+        preview.set_camera(camera);
+    }
+
+    simulator.set_camera(camera);
+}
+
+/// Low resolution simulator showing edits at once, while the full resolution one would take a while to fill in.
+#[cfg(feature = "sdl")]
+fn edit_preview(simulator: &Simulator, genome: Genome) -> Result<(Simulator, RgbaImage), String>
+{
+    let dimensions = simulator.dimensions();
+    let dimensions = Dimensions { width: (dimensions.width / PREVIEW_DIVISOR).max(1), height: (dimensions.height / PREVIEW_DIVISOR).max(1) };
+    let mut preview = Simulator::with_seed(dimensions, cli::DEFAULT_PREPARATION_ROUNDS, simulator::SuperSamplingFactor::None, simulator.seed());
+
+    preview.set_thread_count(simulator.thread_count());
+    preview.set_kernel(simulator.kernel());
+    preview.set_genome(genome)?;
+
+    Ok((preview, RgbaImage::new(dimensions)))
+}
+
+#[cfg(feature = "sdl")]
+fn draw_overlays(frame: &mut RgbaImage, editor: Option<&Editor>, camera: &Camera, show_status: bool, progress: &Progress)
+{
+    if let Some(editor) = editor
+    {
+        editor.draw(frame, camera);
+    }
+
+    if show_status
+    {
+        let lines: Vec<String> = editor.map(Editor::status).into_iter().chain(Some(progress.status())).collect();
+
+        overlay::draw_panel(frame, &lines, Rgb::WHITE);
+    }
+}
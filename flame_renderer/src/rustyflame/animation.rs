@@ -0,0 +1,424 @@
+use std::f64::consts::PI;
+use std::path::{ Path, PathBuf };
+
+use super::camera::Camera;
+use super::density::DensityEstimation;
+use super::filter::ReconstructionFilter;
+use super::genome::Genome;
+use super::math::{ AugmentedMat2x2, Vec2D };
+use super::palette::Palette;
+use super::simulator::FunctionElement;
+use super::symmetry::Symmetry;
+use super::tonemap::ToneMapping;
+use super::variations::{ Variation, WeightedVariation };
+
+/// Keyframe genomes spread evenly over the animation, interpolated in between.
+pub struct Animation
+{
+    keyframes: Vec<Genome>,
+}
+
+impl Animation
+{
+    pub fn new(keyframes: Vec<Genome>) -> Result<Animation, String>
+    {
+        if keyframes.is_empty()
+        {
+            return Err(String::from("An animation needs at least one keyframe"));
+        }
+
+        for keyframe in keyframes.iter()
+        {
+            keyframe.validate()?;
+        }
+
+        Ok(Animation { keyframes })
+    }
+
+    /// Genome at `time` in [0, 1], going from the first keyframe to the last.
+    pub fn genome_at(&self, time: f64) -> Genome
+    {
+        let segments = self.keyframes.len() - 1;
+
+        if segments == 0
+        {
+            return self.keyframes[0].clone();
+        }
+
+        let position = time.clamp(0., 1.) * segments as f64;
+        let index = (position as usize).min(segments - 1);
+
+        interpolate(&self.keyframes[index], &self.keyframes[index + 1], position - index as f64)
+    }
+}
+
+/// Time of a frame, the first and last frames showing the first and last keyframes.
+pub fn frame_time(frame: usize, frame_count: usize) -> f64
+{
+    if frame_count <= 1
+    {
+        return 0.;
+    }
+
+    frame as f64 / (frame_count - 1) as f64
+}
+
+/// Times the samples of a frame are spread over. Like a camera shutter, the frame is exposed
+/// during the fraction `shutter` of the interval between frames, centered on the frame time.
+pub fn motion_blur_times(frame: usize, frame_count: usize, shutter: f64, temporal_samples: usize) -> Vec<f64>
+{
+    let time = frame_time(frame, frame_count);
+
+    if frame_count <= 1 || temporal_samples <= 1 || shutter <= 0.
+    {
+        return vec![time];
+    }
+
+    let exposure = shutter / (frame_count - 1) as f64;
+
+    (0..temporal_samples)
+        .map(|index| (time + exposure * ((index as f64 + 0.5) / temporal_samples as f64 - 0.5)).clamp(0., 1.))
+        .collect()
+}
+
+/// `output` with the frame number before the extension, zero padded so that the files sort in order.
+pub fn frame_path(output: &Path, frame: usize, frame_count: usize) -> PathBuf
+{
+    let digits = frame_count.saturating_sub(1).to_string().len().max(4);
+    let stem = output.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let name = match output.extension().and_then(|e| e.to_str())
+    {
+        Some(extension) => format!("{}_{:0digits$}.{}", stem, frame, extension, digits = digits),
+        None => format!("{}_{:0digits$}", stem, frame, digits = digits),
+    };
+
+    output.with_file_name(name)
+}
+
+/// Exact at both ends.
+fn lerp(a: f64, b: f64, t: f64) -> f64
+{
+    a * (1. - t) + b * t
+}
+
+/// Genome `t` of the way from `a` to `b`. Transforms are paired in order, the shorter list being
+/// padded with transforms of null weight that do not move points, as in flam3. Settings that
+/// cannot be blended are taken from the closest genome.
+pub fn interpolate(a: &Genome, b: &Genome, t: f64) -> Genome
+{
+    let closest = if t < 0.5 { a } else { b };
+
+    // Genomes of different symmetries only blend smoothly once it is expanded into transforms.
+    let (a_transforms, b_transforms, symmetry) = if a.symmetry == b.symmetry
+    {
+        (a.transforms.clone(), b.transforms.clone(), a.symmetry)
+    }
+    else
+    {
+        (expand_symmetry(a), expand_symmetry(b), Symmetry::None)
+    };
+
+    let count = a_transforms.len().max(b_transforms.len());
+    let transforms = (0..count)
+        .map(|index|
+        {
+            match (a_transforms.get(index), b_transforms.get(index))
+            {
+                (Some(a), Some(b)) => interpolate_transform(a, b, t),
+                (Some(a), None) => interpolate_transform(a, &padding(a), t),
+                (None, Some(b)) => interpolate_transform(&padding(b), b, t),
+                (None, None) => unreachable!("index below the longest transform list"),
+            }
+        })
+        .collect();
+
+    let final_transform = match (&a.final_transform, &b.final_transform)
+    {
+        (None, None) => None,
+        (a, b) =>
+        {
+            let a = a.clone().unwrap_or_else(final_padding);
+            let b = b.clone().unwrap_or_else(final_padding);
+
+            Some(interpolate_transform(&a, &b, t))
+        },
+    };
+
+    let colors: Vec<_> = a.palette.colors().iter().zip(b.palette.colors().iter()).map(|(a, b)| a.mix(*b, t)).collect();
+
+    Genome
+    {
+        name: closest.name.clone(),
+        transforms,
+        final_transform,
+        symmetry,
+        camera: Camera
+        {
+            center: (1. - t) * a.camera.center + t * b.camera.center,
+            // Geometric, so that zooming looks steady.
+            zoom: a.camera.zoom * (b.camera.zoom / a.camera.zoom).powf(t),
+            rotation: lerp(a.camera.rotation, b.camera.rotation, t),
+        },
+        palette: Palette::from_colors(&colors).expect("palettes have the same size"),
+        tone_mapping: ToneMapping
+        {
+            brightness: lerp(a.tone_mapping.brightness, b.tone_mapping.brightness, t),
+            gamma: lerp(a.tone_mapping.gamma, b.tone_mapping.gamma, t),
+            gamma_threshold: lerp(a.tone_mapping.gamma_threshold, b.tone_mapping.gamma_threshold, t),
+            vibrancy: lerp(a.tone_mapping.vibrancy, b.tone_mapping.vibrancy, t),
+            highlight_power: lerp(a.tone_mapping.highlight_power, b.tone_mapping.highlight_power, t),
+            background: match (a.tone_mapping.background, b.tone_mapping.background)
+            {
+                (Some(a), Some(b)) => Some(a.mix(b, t)),
+                _ => closest.tone_mapping.background,
+            },
+        },
+        density_estimation: DensityEstimation
+        {
+            min_radius: lerp(a.density_estimation.min_radius, b.density_estimation.min_radius, t),
+            max_radius: lerp(a.density_estimation.max_radius, b.density_estimation.max_radius, t),
+            curve: lerp(a.density_estimation.curve, b.density_estimation.curve, t),
+        },
+        reconstruction_filter: ReconstructionFilter
+        {
+            kind: closest.reconstruction_filter.kind,
+            radius: lerp(a.reconstruction_filter.radius, b.reconstruction_filter.radius, t),
+        },
+    }
+}
+
+fn expand_symmetry(genome: &Genome) -> Vec<FunctionElement>
+{
+    genome.transforms.iter().cloned().chain(genome.symmetry.transforms()).collect()
+}
+
+/// Stands in for a missing transform: never picked, and keeping the colors of its counterpart.
+fn padding(counterpart: &FunctionElement) -> FunctionElement
+{
+    FunctionElement
+    {
+        weight: 0.,
+        color_speed: counterpart.color_speed,
+        ..FunctionElement::new(AugmentedMat2x2::IDENTITY, counterpart.color)
+    }
+}
+
+/// Stands in for a missing final transform, changing neither points nor colors.
+fn final_padding() -> FunctionElement
+{
+    FunctionElement { color_speed: 0., ..FunctionElement::new(AugmentedMat2x2::IDENTITY, 0.) }
+}
+
+fn interpolate_transform(a: &FunctionElement, b: &FunctionElement, t: f64) -> FunctionElement
+{
+    let post_transform = match (a.post_transform, b.post_transform)
+    {
+        (None, None) => None,
+        (a, b) => Some(interpolate_affine(&a.unwrap_or(AugmentedMat2x2::IDENTITY), &b.unwrap_or(AugmentedMat2x2::IDENTITY), t)),
+    };
+
+    FunctionElement
+    {
+        application: interpolate_affine(&a.application, &b.application, t),
+        weight: lerp(a.weight, b.weight, t),
+        variations: interpolate_variations(&a.variations, &b.variations, t),
+        post_transform,
+        color: lerp(a.color, b.color, t),
+        color_speed: lerp(a.color_speed, b.color_speed, t),
+    }
+}
+
+/// Weights of the variations of either blend, a variation missing from one of them having a null weight there.
+fn interpolate_variations(a: &[WeightedVariation], b: &[WeightedVariation], t: f64) -> Vec<WeightedVariation>
+{
+    // An empty blend is linear.
+    let linear = [WeightedVariation { variation: Variation::Linear, weight: 1. }];
+    let (a, b) = (if a.is_empty() { &linear[..] } else { a }, if b.is_empty() { &linear[..] } else { b });
+    let weight = |blend: &[WeightedVariation], variation: Variation| blend.iter().find(|v| v.variation == variation).map(|v| v.weight);
+
+    a.iter()
+        .chain(b.iter().filter(|v| weight(a, v.variation).is_none()))
+        .filter_map(|v|
+        {
+            match (weight(a, v.variation), weight(b, v.variation))
+            {
+                (Some(a), Some(b)) => Some(lerp(a, b, t)),
+                (a, b) => Some(lerp(a.unwrap_or(0.), b.unwrap_or(0.), t)).filter(|w| *w != 0.),
+            }
+            .map(|weight| WeightedVariation { variation: v.variation, weight })
+        })
+        .collect()
+}
+
+/// Rotation angle and symmetric factor `S` of the linear part `M = R(angle) S`. `S` is not
+/// always positive, so that reflections are kept rather than turned into half turns.
+fn polar_decomposition(m: &AugmentedMat2x2) -> (f64, [[f64; 2]; 2])
+{
+    let [[a, b, _], [c, d, _]] = m.coeffs();
+    let angle = (c - b).atan2(a + d);
+    let (sin, cos) = angle.sin_cos();
+
+    (angle, [[cos * a + sin * c, cos * b + sin * d], [cos * c - sin * a, cos * d - sin * b]])
+}
+
+/// Rotations are interpolated by angle, the shortest way around, and the rest linearly, so that
+/// a turning transform keeps its shape instead of shrinking through the middle of the turn.
+fn interpolate_affine(a: &AugmentedMat2x2, b: &AugmentedMat2x2, t: f64) -> AugmentedMat2x2
+{
+    let (a_angle, a_stretch) = polar_decomposition(a);
+    let (b_angle, b_stretch) = polar_decomposition(b);
+    let turn = (b_angle - a_angle + PI).rem_euclid(2. * PI) - PI;
+    let (sin, cos) = (a_angle + turn * t).sin_cos();
+
+    let s = |i: usize, j: usize| lerp(a_stretch[i][j], b_stretch[i][j], t);
+    let (a_coeffs, b_coeffs) = (a.coeffs(), b.coeffs());
+    let offset = Vec2D
+    {
+        x: lerp(a_coeffs[0][2], b_coeffs[0][2], t),
+        y: lerp(a_coeffs[1][2], b_coeffs[1][2], t),
+    };
+
+    AugmentedMat2x2::new
+    ([
+        [cos * s(0, 0) - sin * s(1, 0), cos * s(0, 1) - sin * s(1, 1), offset.x],
+        [sin * s(0, 0) + cos * s(1, 0), sin * s(0, 1) + cos * s(1, 1), offset.y],
+    ])
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::f64::consts::PI;
+    use std::path::Path;
+
+    use super::{ frame_path, frame_time, interpolate, interpolate_affine, motion_blur_times, Animation };
+    use super::super::camera::Camera;
+    use super::super::genome::Genome;
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+    use super::super::palette::Palette;
+    use super::super::simulator::FunctionElement;
+    use super::super::symmetry::Symmetry;
+    use super::super::variations::{ Variation, WeightedVariation };
+
+    fn rotation(angle: f64, scale: f64) -> AugmentedMat2x2
+    {
+        let (sin, cos) = angle.sin_cos();
+
+        AugmentedMat2x2::new([[scale * cos, -scale * sin, 0.], [scale * sin, scale * cos, 0.]])
+    }
+
+    fn assert_close(a: &AugmentedMat2x2, b: &AugmentedMat2x2)
+    {
+        let close = a.coeffs().iter().flatten().zip(b.coeffs().iter().flatten()).all(|(a, b)| (a - b).abs() < 1e-9);
+
+        assert!(close, "{:?} != {:?}", a, b);
+    }
+
+    fn genome(transforms: Vec<FunctionElement>) -> Genome
+    {
+        Genome { transforms, ..Genome::default() }
+    }
+
+    #[test]
+    fn test_rotations_keep_their_scale()
+    {
+        let halfway = interpolate_affine(&rotation(0., 0.5), &rotation(PI / 2., 0.5), 0.5);
+
+        assert_close(&halfway, &rotation(PI / 4., 0.5));
+        // The shortest way around, through the half turn rather than back through 0.
+        assert_close(&interpolate_affine(&rotation(3., 1.), &rotation(-3., 1.), 0.5), &rotation(PI, 1.));
+    }
+
+    #[test]
+    fn test_ends_are_exact()
+    {
+        let mirror = AugmentedMat2x2::new([[-0.5, 0.2, 0.3], [0.1, 0.6, -0.4]]);
+        let shear = AugmentedMat2x2::new([[0.3, 0.7, -1.], [0., 0.2, 0.5]]);
+
+        assert_close(&interpolate_affine(&mirror, &shear, 0.), &mirror);
+        assert_close(&interpolate_affine(&mirror, &shear, 1.), &shear);
+    }
+
+    #[test]
+    fn test_interpolate_genomes()
+    {
+        let mut spherical = FunctionElement::new(rotation(0., 0.5), 1.);
+
+        spherical.variations = vec![WeightedVariation { variation: Variation::Spherical, weight: 0.5 }];
+
+        let mut a = genome(vec![FunctionElement::new(rotation(0., 0.5), 0.)]);
+        let mut b = genome(vec![spherical.clone(), spherical]);
+
+        a.camera = Camera { center: Vec2D { x: 1., y: 0. }, zoom: 1., rotation: 0. };
+        b.camera = Camera { center: Vec2D { x: -1., y: 2. }, zoom: 4., rotation: 1. };
+        b.palette = Palette::builtin("ocean").unwrap();
+
+        let halfway = interpolate(&a, &b, 0.5);
+
+        assert_eq!(halfway.transforms.len(), 2);
+        assert_eq!(halfway.transforms[0].color, 0.5);
+        assert_eq!
+        (
+            halfway.transforms[0].variations,
+            vec!
+            [
+                WeightedVariation { variation: Variation::Linear, weight: 0.5 },
+                WeightedVariation { variation: Variation::Spherical, weight: 0.25 },
+            ]
+        );
+        // The padding transform fades in.
+        assert_eq!(halfway.transforms[1].weight, 0.5);
+        assert_eq!(halfway.camera, Camera { center: Vec2D { x: 0., y: 1. }, zoom: 2., rotation: 0.5 });
+        assert!(halfway.validate().is_ok());
+
+        assert_eq!(interpolate(&a, &b, 1.), b);
+    }
+
+    #[test]
+    fn test_different_symmetries_are_expanded()
+    {
+        let mut a = genome(vec![FunctionElement::new(rotation(0., 0.5), 0.)]);
+        let b = a.clone();
+
+        a.symmetry = Symmetry::Rotational(3);
+
+        let start = interpolate(&a, &b, 0.);
+
+        assert_eq!(start.symmetry, Symmetry::None);
+        assert_eq!(start.transforms.len(), 3);
+        assert_eq!(interpolate(&a, &b, 1.).transforms[1].weight, 0.);
+        assert_eq!(interpolate(&a, &a, 0.5), a);
+    }
+
+    #[test]
+    fn test_animation_keyframes()
+    {
+        let keyframes: Vec<Genome> = (0..3).map(|i| genome(vec![FunctionElement::new(rotation(0., 0.5), i as f64 / 2.)])).collect();
+        let animation = Animation::new(keyframes.clone()).unwrap();
+
+        assert_eq!(animation.genome_at(0.), keyframes[0]);
+        assert_eq!(animation.genome_at(0.5), keyframes[1]);
+        assert_eq!(animation.genome_at(1.), keyframes[2]);
+        assert_eq!(animation.genome_at(0.75).transforms[0].color, 0.75);
+        assert!(Animation::new(vec![]).is_err());
+        assert!(Animation::new(vec![Genome::default()]).is_err());
+    }
+
+    #[test]
+    fn test_frame_timing()
+    {
+        assert_eq!(frame_time(0, 1), 0.);
+        assert_eq!(frame_time(3, 5), 0.75);
+        assert_eq!(motion_blur_times(3, 5, 0., 8), vec![0.75]);
+        assert_eq!(motion_blur_times(2, 5, 1., 2), vec![0.4375, 0.5625]);
+        // Clamped to the animation.
+        assert_eq!(motion_blur_times(0, 5, 1., 2), vec![0., 0.0625]);
+    }
+
+    #[test]
+    fn test_frame_path()
+    {
+        assert_eq!(frame_path(Path::new("out/flame.png"), 7, 100), Path::new("out/flame_0007.png"));
+        assert_eq!(frame_path(Path::new("flame"), 7, 20000), Path::new("flame_00007"));
+    }
+}
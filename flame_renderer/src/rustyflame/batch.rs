@@ -0,0 +1,392 @@
+use std::io::{ Read, Write };
+
+use rand::{ Error, Rng, RngCore, SeedableRng };
+
+use super::checkpoint::{ Reader, Writer };
+use super::math::{ AugmentedMat2x2, Vec2D };
+use super::sampling::AliasTable;
+use super::simulator::{ self, FunctionElement };
+use super::utils;
+use super::variations;
+
+/// Walkers run together by the batched kernel, enough to amortize the per function work.
+pub const BATCH_WALKERS: usize = 1024;
+
+/// wyrand, a 64 bit generator a few instructions long. Its stream is fixed, so a seed still replays
+/// the same render, but it is only meant for the many draws of the batched kernel.
+#[derive(Clone, Debug)]
+pub struct FastRng
+{
+    state: u64,
+}
+
+impl RngCore for FastRng
+{
+    fn next_u32(&mut self) -> u32
+    {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64
+    {
+        self.state = self.state.wrapping_add(0xa076_1d64_78bd_642f);
+
+        let product = self.state as u128 * (self.state ^ 0xe703_7ed1_a0b4_28db) as u128;
+
+        ((product >> 64) ^ product) as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8])
+    {
+        for chunk in dest.chunks_mut(8)
+        {
+            let bytes = self.next_u64().to_le_bytes();
+
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error>
+    {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for FastRng
+{
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: [u8; 8]) -> FastRng
+    {
+        FastRng { state: u64::from_le_bytes(seed) }
+    }
+}
+
+/// Independent chaos game walkers in struct of arrays layout. Each round draws a function for
+/// every walker and groups them by function, so that the affine parts and the variations run as
+/// loops over contiguous coordinates with constant coefficients. Walkers are interchangeable, so
+/// they stay in the grouped order afterwards.
+pub struct Walkers
+{
+    xs: Vec<f64>,
+    ys: Vec<f64>,
+    colors: Vec<f64>,
+    /// Iterations since each walker was last sent to a random point.
+    ages: Vec<usize>,
+    rng: FastRng,
+    // Scratch space, kept between rounds to avoid allocating.
+    selected: Vec<usize>,
+    ends: Vec<usize>,
+    grouped_x: Vec<f64>,
+    grouped_y: Vec<f64>,
+    grouped_colors: Vec<f64>,
+    grouped_ages: Vec<usize>,
+    affine_x: Vec<f64>,
+    affine_y: Vec<f64>,
+}
+
+impl Walkers
+{
+    /// `count` walkers at random points of [-1, 1]², with a stream derived from `rng`.
+    pub fn new<R: Rng + ?Sized>(count: usize, rng: &mut R) -> Walkers
+    {
+        let mut rng = FastRng::seed_from_u64(rng.gen());
+        let xs = (0..count).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let ys = (0..count).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let colors = (0..count).map(|_| rng.gen()).collect();
+
+        Walkers::from_parts(xs, ys, colors, vec![0; count], rng)
+    }
+
+    fn from_parts(xs: Vec<f64>, ys: Vec<f64>, colors: Vec<f64>, ages: Vec<usize>, rng: FastRng) -> Walkers
+    {
+        let count = xs.len();
+
+        Walkers
+        {
+            xs,
+            ys,
+            colors,
+            ages,
+            rng,
+            selected: vec![0; count],
+            ends: vec![],
+            grouped_x: vec![0.; count],
+            grouped_y: vec![0.; count],
+            grouped_colors: vec![0.; count],
+            grouped_ages: vec![0; count],
+            affine_x: vec![0.; count],
+            affine_y: vec![0.; count],
+        }
+    }
+
+    /// Saves the walkers along with the state of their generator, so that walkers read back carry on
+    /// exactly as these would.
+    pub fn write<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), String>
+    {
+        writer.u64(self.rng.state)?;
+        writer.f64s(&self.xs)?;
+        writer.f64s(&self.ys)?;
+        writer.f64s(&self.colors)?;
+        writer.usizes(&self.ages)
+    }
+
+    pub fn read<R: Read>(reader: &mut Reader<R>) -> Result<Walkers, String>
+    {
+        let rng = FastRng { state: reader.u64()? };
+        let (xs, ys, colors, ages) = (reader.f64s()?, reader.f64s()?, reader.f64s()?, reader.usizes()?);
+
+        if ys.len() != xs.len() || colors.len() != xs.len() || ages.len() != xs.len()
+        {
+            return Err(String::from("Inconsistent walkers in checkpoint"));
+        }
+
+        Ok(Walkers::from_parts(xs, ys, colors, ages, rng))
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.xs.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.xs.is_empty()
+    }
+
+    /// Walkers that went through each function in the last `advance`.
+    pub fn group_sizes(&self) -> impl Iterator<Item = usize> + '_
+    {
+        self.ends.iter().scan(0, |begin, end|
+        {
+            let size = end - *begin;
+
+            *begin = *end;
+            Some(size)
+        })
+    }
+
+    /// Moves the first `count` walkers one iteration with `functions`, picked by `selector`, and
+    /// calls `plot` with the point and color coordinate of those past their preparation rounds.
+    /// Walkers escaping to infinity or NaN are sent back to a random point instead, and counted
+    /// in the returned number of bad samples, as are the points escaping through the final transform.
+    pub fn advance<F: FnMut(Vec2D, f64)>(&mut self, count: usize, functions: &[FunctionElement], selector: &AliasTable, final_transform: Option<&FunctionElement>, preparation_rounds: usize, mut plot: F) -> usize
+    {
+        let count = count.min(self.len());
+
+        self.group_by_function(count, selector);
+
+        for (index, function) in functions.iter().enumerate()
+        {
+            let range = index.checked_sub(1).map_or(0, |previous| self.ends[previous])..self.ends[index];
+
+            apply_function
+            (
+                function,
+                &self.grouped_x[range.clone()], &self.grouped_y[range.clone()],
+                &mut self.affine_x[range.clone()], &mut self.affine_y[range.clone()],
+                &mut self.xs[range.clone()], &mut self.ys[range.clone()],
+                &mut self.rng,
+            );
+
+            for (color, grouped) in self.colors[range.clone()].iter_mut().zip(self.grouped_colors[range].iter())
+            {
+                *color = function.blend_color(*grouped);
+            }
+        }
+
+        for (age, grouped) in self.ages[..count].iter_mut().zip(self.grouped_ages[..count].iter())
+        {
+            *age = grouped + 1;
+        }
+
+        let mut bad_samples = 0;
+
+        for index in 0..count
+        {
+            if simulator::escaped(Vec2D { x: self.xs[index], y: self.ys[index] })
+            {
+                bad_samples += 1;
+                self.ages[index] = 0;
+                self.xs[index] = self.rng.gen_range(-1.0..1.0);
+                self.ys[index] = self.rng.gen_range(-1.0..1.0);
+            }
+        }
+
+        match final_transform
+        {
+            // The final transform only changes what gets plotted, the walkers carry on from their own point.
+            Some(last) =>
+            {
+                let (grouped_x, grouped_y) = (&mut self.grouped_x[..count], &mut self.grouped_y[..count]);
+
+                apply_function(last, &self.xs[..count], &self.ys[..count], &mut self.affine_x[..count], &mut self.affine_y[..count], grouped_x, grouped_y, &mut self.rng);
+
+                for index in (0..count).filter(|index| self.ages[*index] > preparation_rounds)
+                {
+                    let p = Vec2D { x: self.grouped_x[index], y: self.grouped_y[index] };
+
+                    if simulator::escaped(p)
+                    {
+                        bad_samples += 1;
+                    }
+                    else
+                    {
+                        plot(p, last.blend_color(self.colors[index]));
+                    }
+                }
+            },
+            None =>
+            {
+                for index in (0..count).filter(|index| self.ages[*index] > preparation_rounds)
+                {
+                    plot(Vec2D { x: self.xs[index], y: self.ys[index] }, self.colors[index]);
+                }
+            },
+        }
+
+        bad_samples
+    }
+
+    /// Draws a function for each of the first `count` walkers and copies them to the grouped
+    /// buffers, sorted by function. `ends` holds where the walkers of each function end.
+    fn group_by_function(&mut self, count: usize, selector: &AliasTable)
+    {
+        self.ends.clear();
+        self.ends.resize(selector.len(), 0);
+
+        for selected in self.selected[..count].iter_mut()
+        {
+            *selected = selector.sample(&mut self.rng);
+            self.ends[*selected] += 1;
+        }
+
+        // Counts become where each group begins, then move to where it ends as it is filled.
+        let mut begin = 0;
+
+        for end in self.ends.iter_mut()
+        {
+            let size = *end;
+
+            *end = begin;
+            begin += size;
+        }
+
+        for (index, selected) in self.selected[..count].iter().enumerate()
+        {
+            let position = self.ends[*selected];
+
+            self.ends[*selected] += 1;
+            self.grouped_x[position] = self.xs[index];
+            self.grouped_y[position] = self.ys[index];
+            self.grouped_colors[position] = self.colors[index];
+            self.grouped_ages[position] = self.ages[index];
+        }
+    }
+}
+
+/// `FunctionElement::apply` over many points, `affine_x` and `affine_y` holding the points once
+/// through the affine part.
+#[allow(clippy::too_many_arguments)]
+fn apply_function<R: Rng + ?Sized>(function: &FunctionElement, xs: &[f64], ys: &[f64], affine_x: &mut [f64], affine_y: &mut [f64], out_x: &mut [f64], out_y: &mut [f64], rng: &mut R)
+{
+    apply_affine(&function.application, xs, ys, affine_x, affine_y);
+    variations::apply_blend_batch(&function.variations, &function.application, affine_x, affine_y, out_x, out_y, rng);
+
+    if let Some(post_transform) = &function.post_transform
+    {
+        affine_x.copy_from_slice(out_x);
+        affine_y.copy_from_slice(out_y);
+        apply_affine(post_transform, affine_x, affine_y, out_x, out_y);
+    }
+}
+
+fn apply_affine(affine: &AugmentedMat2x2, xs: &[f64], ys: &[f64], out_x: &mut [f64], out_y: &mut [f64])
+{
+    let [[a, b, c], [d, e, f]] = affine.coeffs();
+
+    for ((x, y), (ox, oy)) in utils::zip((xs.iter(), ys.iter())).zip(utils::zip((out_x.iter_mut(), out_y.iter_mut())))
+    {
+        *ox = a * x + b * y + c;
+        *oy = d * x + e * y + f;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::{ Rng, SeedableRng };
+
+    use super::{ FastRng, Walkers };
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+    use super::super::sampling::AliasTable;
+    use super::super::simulator::FunctionElement;
+
+    #[test]
+    fn test_fast_rng()
+    {
+        let mut rng = FastRng::seed_from_u64(1);
+        let draws: Vec<f64> = (0..10000).map(|_| rng.gen()).collect();
+        let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+
+        assert!((mean - 0.5).abs() < 0.01);
+        assert!(draws.iter().all(|d| (0. ..1.).contains(d)));
+        assert_eq!(FastRng::seed_from_u64(1).gen::<u64>(), FastRng::seed_from_u64(1).gen::<u64>());
+        assert_ne!(FastRng::seed_from_u64(1).gen::<u64>(), FastRng::seed_from_u64(2).gen::<u64>());
+
+        let mut bytes = [0u8; 11];
+
+        rng.fill(&mut bytes[..]);
+        assert!(bytes.iter().any(|b| *b != 0));
+    }
+
+    #[test]
+    fn test_walkers_converge_and_plot_once_prepared()
+    {
+        // Both maps share the fixed point (0.2, 0.2).
+        let functions = vec!
+        [
+            FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., 0.1], [0., 0.5, 0.1]]), 0.),
+            FunctionElement::new(AugmentedMat2x2::new([[0., 0.5, 0.1], [0.5, 0., 0.1]]), 1.),
+        ];
+        let selector = AliasTable::new(&[1., 3.]).unwrap();
+        let mut walkers = Walkers::new(100, &mut FastRng::seed_from_u64(3));
+        let mut plotted = vec![];
+
+        for _ in 0..30
+        {
+            let bad = walkers.advance(60, &functions, &selector, None, 20, |p, color| plotted.push((p, color)));
+
+            assert_eq!(bad, 0);
+        }
+
+        // Only the first 60 walkers moved, each plotting after its 20 preparation rounds.
+        assert_eq!(plotted.len(), 60 * 10);
+        assert!(plotted.iter().all(|(p, color)| p.approx_eq(Vec2D { x: 0.2, y: 0.2 }, 1e-5) && (0. ..=1.).contains(color)));
+    }
+
+    #[test]
+    fn test_final_transform_and_escapes()
+    {
+        let functions = vec![FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., 0.], [0., 0.5, 0.]]), 0.)];
+        let shift = FunctionElement::new(AugmentedMat2x2::new([[1., 0., 3.], [0., 1., 0.]]), 0.);
+        let selector = AliasTable::new(&[1.]).unwrap();
+        let mut walkers = Walkers::new(10, &mut FastRng::seed_from_u64(3));
+        let mut plotted = vec![];
+
+        for _ in 0..60
+        {
+            walkers.advance(10, &functions, &selector, Some(&shift), 50, |p, _| plotted.push(p));
+        }
+
+        assert_eq!(plotted.len(), 10 * 10);
+        assert!(plotted.iter().all(|p| p.approx_eq(Vec2D { x: 3., y: 0. }, 1e-12)));
+
+        // Every walker lands past the escape bound, and is sent back each time.
+        let escaping = vec![FunctionElement::new(AugmentedMat2x2::new([[1., 0., 1e11], [0., 1., 0.]]), 0.)];
+        let bad: usize = (0..4).map(|_| walkers.advance(10, &escaping, &selector, None, 0, |_, _| panic!("escaped walkers are not plotted"))).sum();
+
+        assert_eq!(bad, 4 * 10);
+    }
+}
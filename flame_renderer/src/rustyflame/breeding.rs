@@ -0,0 +1,260 @@
+use rand::seq::{ IteratorRandom, SliceRandom };
+use rand::Rng;
+
+use super::generator::Generator;
+use super::genome::Genome;
+use super::math::AugmentedMat2x2;
+use super::palette::Palette;
+use super::simulator::FunctionElement;
+use super::variations::{ Variation, WeightedVariation };
+
+/// Small random change of a genome, in the spirit of Electric Sheep.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mutation
+{
+    /// Nudges every coefficient of the affine part of a transform.
+    Coefficients,
+    /// Replaces a variation of a transform by another one, with the same weight.
+    Variations,
+    /// Rotates the palette, changing which colors the transforms pull towards.
+    Palette,
+}
+
+impl Mutation
+{
+    pub const ALL: [Mutation; 3] = [Mutation::Coefficients, Mutation::Variations, Mutation::Palette];
+}
+
+/// Derives new genomes from ones we like, keeping only the offspring the generator would accept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Breeder
+{
+    /// Largest change of an affine coefficient.
+    pub coefficient_spread: f64,
+    /// Judges the offspring, up to its `max_attempts` times per request.
+    pub generator: Generator,
+}
+
+impl Default for Breeder
+{
+    fn default() -> Breeder
+    {
+        Breeder { coefficient_spread: 0.2, generator: Generator::default() }
+    }
+}
+
+impl Breeder
+{
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if !(self.coefficient_spread.is_finite() && self.coefficient_spread > 0.)
+        {
+            return Err(String::from("The coefficient spread must be strictly positive"));
+        }
+
+        self.generator.validate()
+    }
+
+    /// First mutant of `genome` passing the generator checks.
+    pub fn mutate<R: Rng + ?Sized>(&self, genome: &Genome, rng: &mut R) -> Result<Genome, String>
+    {
+        self.validate()?;
+        self.first_accepted(rng, |breeder, rng|
+        {
+            let mutation = *Mutation::ALL.choose(rng).expect("there are mutations");
+
+            breeder.apply(mutation, genome, rng)
+        })
+        .ok_or(format!("No interesting mutant found in {} attempts", self.generator.max_attempts))
+    }
+
+    /// First child of `a` and `b` passing the generator checks.
+    pub fn crossover<R: Rng + ?Sized>(&self, a: &Genome, b: &Genome, rng: &mut R) -> Result<Genome, String>
+    {
+        self.validate()?;
+        self.first_accepted(rng, |breeder, rng| breeder.combine(a, b, rng))
+            .ok_or(format!("No interesting child found in {} attempts", self.generator.max_attempts))
+    }
+
+    fn first_accepted<R, F>(&self, rng: &mut R, mut offspring: F) -> Option<Genome>
+    where
+        R: Rng + ?Sized,
+        F: FnMut(&Breeder, &mut R) -> Genome,
+    {
+        (0..self.generator.max_attempts).find_map(|_|
+        {
+            let genome = offspring(self, rng);
+
+            self.generator.judge(&genome, rng.gen()).is_ok().then_some(genome)
+        })
+    }
+
+    /// `genome` changed by `mutation`, without any check.
+    pub fn apply<R: Rng + ?Sized>(&self, mutation: Mutation, genome: &Genome, rng: &mut R) -> Genome
+    {
+        let mut mutant = genome.clone();
+
+        match mutation
+        {
+            Mutation::Coefficients =>
+            {
+                let transform = mutant.transforms.choose_mut(rng).expect("valid genomes have transforms");
+                let mut coeffs = transform.application.coeffs();
+
+                coeffs.iter_mut().flatten().for_each(|c| *c += rng.gen_range(-self.coefficient_spread..=self.coefficient_spread));
+                transform.application = AugmentedMat2x2::new(coeffs);
+            },
+            Mutation::Variations =>
+            {
+                let transform = mutant.transforms.choose_mut(rng).expect("valid genomes have transforms");
+
+                swap_variation(transform, rng);
+            },
+            Mutation::Palette =>
+            {
+                let mut colors = mutant.palette.colors().to_vec();
+                let offset = rng.gen_range(1..colors.len());
+
+                colors.rotate_left(offset);
+                mutant.palette = Palette::from_colors(&colors).expect("the palette keeps its size");
+            },
+        }
+
+        mutant
+    }
+
+    /// Each transform comes from one parent or the other, at the same position. The palette,
+    /// symmetry and final transform come from a random parent, the view and display settings from `a`.
+    pub fn combine<R: Rng + ?Sized>(&self, a: &Genome, b: &Genome, rng: &mut R) -> Genome
+    {
+        let count = a.transforms.len().max(b.transforms.len());
+        let mut transforms: Vec<FunctionElement> = (0..count)
+            .filter_map(|index| if rng.gen() { a.transforms.get(index) } else { b.transforms.get(index) })
+            .cloned()
+            .collect();
+
+        if transforms.is_empty()
+        {
+            transforms.push(a.transforms[0].clone());
+        }
+
+        let pick = |rng: &mut R| if rng.gen() { a } else { b };
+
+        Genome
+        {
+            transforms,
+            palette: pick(rng).palette.clone(),
+            symmetry: pick(rng).symmetry,
+            final_transform: pick(rng).final_transform.clone(),
+            ..a.clone()
+        }
+    }
+}
+
+/// Leaves `transform` alone when it already uses every variation.
+fn swap_variation<R: Rng + ?Sized>(transform: &mut FunctionElement, rng: &mut R)
+{
+    let unused = Variation::ALL.iter().filter(|variation| transform.variations.iter().all(|v| v.variation != **variation));
+    let replacement = match unused.choose(rng)
+    {
+        Some(variation) => *variation,
+        None => return,
+    };
+
+    match transform.variations.choose_mut(rng)
+    {
+        Some(swapped) => swapped.variation = replacement,
+        None => transform.variations.push(WeightedVariation { variation: replacement, weight: 1. }),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::SeedableRng;
+
+    use super::{ Breeder, Mutation };
+    use super::super::genome::Genome;
+    use super::super::math::AugmentedMat2x2;
+    use super::super::simulator::{ FlameRng, FunctionElement };
+    use super::super::symmetry::Symmetry;
+    use super::super::variations::{ Variation, WeightedVariation };
+
+    #[test]
+    fn test_mutations_change_their_part_only()
+    {
+        let breeder = Breeder::default();
+        let parent = Genome::sierpinski();
+        let mut rng = FlameRng::seed_from_u64(3);
+
+        let mutant = breeder.apply(Mutation::Coefficients, &parent, &mut rng);
+        let changed: Vec<_> = mutant.transforms.iter().zip(parent.transforms.iter()).filter(|(m, p)| m != p).collect();
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0.variations, changed[0].1.variations);
+        assert!(!changed[0].0.application.approx_eq(&changed[0].1.application, 1e-12));
+        assert_eq!(mutant.palette, parent.palette);
+
+        let mutant = breeder.apply(Mutation::Variations, &parent, &mut rng);
+        let changed: Vec<_> = mutant.transforms.iter().zip(parent.transforms.iter()).filter(|(m, p)| m != p).collect();
+
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].0.application, changed[0].1.application);
+        assert_ne!(changed[0].0.variations[0].variation, changed[0].1.variations[0].variation);
+        assert_eq!(changed[0].0.variations[0].weight, 1.);
+
+        let mutant = breeder.apply(Mutation::Palette, &parent, &mut rng);
+
+        assert_eq!(mutant.transforms, parent.transforms);
+        assert_ne!(mutant.palette, parent.palette);
+        assert!(parent.palette.colors().iter().all(|c| mutant.palette.colors().contains(c)));
+
+        // With every variation already in use, there is nothing to swap in.
+        let variations = Variation::ALL.iter().map(|variation| WeightedVariation { variation: *variation, weight: 1. }).collect();
+        let saturated = Genome { transforms: vec![FunctionElement { variations, ..parent.transforms[0].clone() }], ..parent.clone() };
+
+        assert_eq!(breeder.apply(Mutation::Variations, &saturated, &mut rng), saturated);
+    }
+
+    #[test]
+    fn test_combine_keeps_positions()
+    {
+        let breeder = Breeder::default();
+        let a = Genome::sierpinski();
+        let b = Genome
+        {
+            transforms: vec![FunctionElement::new(AugmentedMat2x2::new([[0.3, 0.1, 0.], [0., 0.3, 0.]]), 1.)],
+            symmetry: Symmetry::Rotational(3),
+            ..Genome::default()
+        };
+        let mut rng = FlameRng::seed_from_u64(1);
+
+        for _ in 0..20
+        {
+            let child = breeder.combine(&a, &b, &mut rng);
+
+            assert!(!child.transforms.is_empty() && child.transforms.len() <= 3);
+            assert!(child.transforms[0] == a.transforms[0] || child.transforms[0] == b.transforms[0]);
+            assert!(child.transforms[1..].iter().all(|t| a.transforms.contains(t)));
+            assert!(child.symmetry == a.symmetry || child.symmetry == b.symmetry);
+            assert!(child.validate().is_ok());
+        }
+    }
+
+    #[test]
+    fn test_offspring_are_accepted()
+    {
+        let breeder = Breeder::default();
+        let parent = Genome::sierpinski();
+        let mutant = breeder.mutate(&parent, &mut FlameRng::seed_from_u64(7)).unwrap();
+
+        assert_ne!(mutant, parent);
+        assert_eq!(breeder.mutate(&parent, &mut FlameRng::seed_from_u64(7)).unwrap(), mutant);
+        assert!(breeder.generator.judge(&mutant, 1).is_ok());
+
+        let child = breeder.crossover(&parent, &mutant, &mut FlameRng::seed_from_u64(7)).unwrap();
+
+        assert!(child.validate().is_ok());
+        assert!(Breeder { coefficient_spread: 0., ..Breeder::default() }.mutate(&parent, &mut FlameRng::seed_from_u64(7)).is_err());
+    }
+}
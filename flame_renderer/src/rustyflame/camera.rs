@@ -0,0 +1,142 @@
+use serde::{ Deserialize, Serialize };
+
+use super::image::Dimensions;
+use super::math::{ AugmentedMat2x2, Vec2D };
+
+/// View on the flame. At zoom 1 the square [-1, 1]² fits the shortest side of the frame,
+/// whatever its aspect ratio; the rotation (in radians) turns the view around `center`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Camera
+{
+    pub center: Vec2D,
+    pub zoom: f64,
+    pub rotation: f64,
+}
+
+impl Default for Camera
+{
+    fn default() -> Camera
+    {
+        Camera { center: Vec2D { x: 0., y: 0. }, zoom: 1., rotation: 0. }
+    }
+}
+
+impl Camera
+{
+    /// Pixels per world unit on a frame of the given dimensions.
+    pub fn pixels_per_unit(&self, dimensions: &Dimensions) -> f64
+    {
+        u32::min(dimensions.width, dimensions.height) as f64 / 2. * self.zoom
+    }
+
+    /// World to frame coordinates, as an affine map so plotting costs a single matrix product.
+    pub fn world_to_frame(&self, dimensions: &Dimensions) -> AugmentedMat2x2
+    {
+        let k = self.pixels_per_unit(dimensions);
+        let (sin, cos) = self.rotation.sin_cos();
+        let linear = [[k * cos, -k * sin], [k * sin, k * cos]];
+        let offset = Vec2D { x: dimensions.width as f64 / 2., y: dimensions.height as f64 / 2. };
+
+        AugmentedMat2x2::new
+        (
+            [[linear[0][0], linear[0][1], offset.x - linear[0][0] * self.center.x - linear[0][1] * self.center.y],
+             [linear[1][0], linear[1][1], offset.y - linear[1][0] * self.center.x - linear[1][1] * self.center.y]]
+        )
+    }
+
+    pub fn frame_to_world(&self, p: Vec2D, dimensions: &Dimensions) -> Vec2D
+    {
+        let offset = Vec2D { x: dimensions.width as f64 / 2., y: dimensions.height as f64 / 2. };
+
+        self.center + self.frame_delta_to_world(p - offset, dimensions)
+    }
+
+    /// Moves the view so that the content follows a drag of `(dx, dy)` pixels.
+    pub fn pan(&mut self, dx: f64, dy: f64, dimensions: &Dimensions)
+    {
+        self.center = self.center - self.frame_delta_to_world(Vec2D { x: dx, y: dy }, dimensions);
+    }
+
+    pub fn zoom_by(&mut self, factor: f64)
+    {
+        self.zoom *= factor;
+    }
+
+    pub fn rotate_by(&mut self, angle: f64)
+    {
+        self.rotation += angle;
+    }
+
+    fn frame_delta_to_world(&self, delta: Vec2D, dimensions: &Dimensions) -> Vec2D
+    {
+        let k = self.pixels_per_unit(dimensions);
+        let (sin, cos) = self.rotation.sin_cos();
+
+        Vec2D
+        {
+            x: (cos * delta.x + sin * delta.y) / k,
+            y: (-sin * delta.x + cos * delta.y) / k,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::f64::consts::FRAC_PI_2;
+
+    use super::Camera;
+    use super::super::image::Dimensions;
+    use super::super::math::Vec2D;
+
+    const FRAME: Dimensions = Dimensions { width: 400, height: 200 };
+
+    fn assert_close(p: Vec2D, expected: Vec2D)
+    {
+        assert!((p.x - expected.x).abs() < 1e-9 && (p.y - expected.y).abs() < 1e-9, "{:?} != {:?}", p, expected);
+    }
+
+    #[test]
+    fn test_default_camera_preserves_aspect()
+    {
+        let transform = Camera::default().world_to_frame(&FRAME);
+
+        assert_close(transform * Vec2D { x: 0., y: 0. }, Vec2D { x: 200., y: 100. });
+        assert_close(transform * Vec2D { x: -1., y: -1. }, Vec2D { x: 100., y: 0. });
+        assert_close(transform * Vec2D { x: 2., y: 1. }, Vec2D { x: 400., y: 200. });
+    }
+
+    #[test]
+    fn test_zoom_rotation_and_center()
+    {
+        let camera = Camera { center: Vec2D { x: 1., y: 0.5 }, zoom: 2., rotation: FRAC_PI_2 };
+        let transform = camera.world_to_frame(&FRAME);
+
+        assert_close(transform * Vec2D { x: 1., y: 0.5 }, Vec2D { x: 200., y: 100. });
+        // A quarter turn sends +x to +y on screen, at 200 pixels per unit.
+        assert_close(transform * Vec2D { x: 1.25, y: 0.5 }, Vec2D { x: 200., y: 150. });
+        assert_close(transform * Vec2D { x: 1., y: 0.75 }, Vec2D { x: 150., y: 100. });
+    }
+
+    #[test]
+    fn test_frame_to_world_inverts_world_to_frame()
+    {
+        let camera = Camera { center: Vec2D { x: -0.3, y: 0.2 }, zoom: 1.7, rotation: 0.4 };
+        let transform = camera.world_to_frame(&FRAME);
+        let p = Vec2D { x: 0.6, y: -0.9 };
+
+        assert_close(camera.frame_to_world(transform * p, &FRAME), p);
+    }
+
+    #[test]
+    fn test_pan_follows_drag()
+    {
+        let mut camera = Camera { rotation: 0.7, ..Camera::default() };
+        let p = Vec2D { x: 0.2, y: 0.1 };
+        let before = camera.world_to_frame(&FRAME) * p;
+
+        camera.pan(15., -4., &FRAME);
+
+        assert_close(camera.world_to_frame(&FRAME) * p, before + Vec2D { x: 15., y: -4. });
+    }
+}
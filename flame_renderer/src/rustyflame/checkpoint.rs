@@ -0,0 +1,287 @@
+use std::convert::TryFrom;
+use std::fs::{ self, File };
+use std::io::{ self, BufReader, BufWriter, Read, Write };
+use std::path::{ Path, PathBuf };
+
+/// Start of every checkpoint file.
+pub const MAGIC: &[u8; 8] = b"FLAMECKP";
+/// Bumped whenever the layout changes. Files of other versions are refused rather than misread.
+pub const VERSION: u32 = 1;
+
+/// Lengths are only trusted this far before the data is actually there, so that a corrupted
+/// length fails on a truncated read rather than on a huge allocation.
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+/// Little endian encoder of the checkpoint fields, sequences being prefixed with their length.
+pub struct Writer<W: Write>
+{
+    inner: W,
+}
+
+impl<W: Write> Writer<W>
+{
+    /// Starts the file with the magic number and version.
+    pub fn new(inner: W) -> Result<Writer<W>, String>
+    {
+        let mut writer = Writer { inner };
+
+        writer.raw(MAGIC)?;
+        writer.u32(VERSION)?;
+
+        Ok(writer)
+    }
+
+    fn raw(&mut self, bytes: &[u8]) -> Result<(), String>
+    {
+        self.inner.write_all(bytes).map_err(|e| format!("Cannot write checkpoint: {}", e))
+    }
+
+    pub fn u8(&mut self, value: u8) -> Result<(), String>
+    {
+        self.raw(&[value])
+    }
+
+    pub fn u32(&mut self, value: u32) -> Result<(), String>
+    {
+        self.raw(&value.to_le_bytes())
+    }
+
+    pub fn u64(&mut self, value: u64) -> Result<(), String>
+    {
+        self.raw(&value.to_le_bytes())
+    }
+
+    pub fn usize(&mut self, value: usize) -> Result<(), String>
+    {
+        self.u64(value as u64)
+    }
+
+    pub fn f64(&mut self, value: f64) -> Result<(), String>
+    {
+        self.raw(&value.to_le_bytes())
+    }
+
+    pub fn str(&mut self, value: &str) -> Result<(), String>
+    {
+        self.usize(value.len())?;
+        self.raw(value.as_bytes())
+    }
+
+    pub fn f64s(&mut self, values: &[f64]) -> Result<(), String>
+    {
+        self.usize(values.len())?;
+        values.iter().try_for_each(|v| self.f64(*v))
+    }
+
+    pub fn usizes(&mut self, values: &[usize]) -> Result<(), String>
+    {
+        self.usize(values.len())?;
+        values.iter().try_for_each(|v| self.usize(*v))
+    }
+
+    pub fn finish(mut self) -> Result<W, String>
+    {
+        self.inner.flush().map_err(|e| format!("Cannot write checkpoint: {}", e))?;
+        Ok(self.inner)
+    }
+}
+
+/// Decoder matching `Writer`.
+pub struct Reader<R: Read>
+{
+    inner: R,
+}
+
+impl<R: Read> Reader<R>
+{
+    /// Checks the magic number and version.
+    pub fn new(inner: R) -> Result<Reader<R>, String>
+    {
+        let mut reader = Reader { inner };
+        let mut magic = [0; 8];
+
+        reader.raw(&mut magic).map_err(|_| String::from("Not a checkpoint file"))?;
+
+        if &magic != MAGIC
+        {
+            return Err(String::from("Not a checkpoint file"));
+        }
+
+        match reader.u32()?
+        {
+            VERSION => Ok(reader),
+            version => Err(format!("Unsupported checkpoint version {}, expected {}", version, VERSION)),
+        }
+    }
+
+    fn raw(&mut self, bytes: &mut [u8]) -> Result<(), String>
+    {
+        self.inner.read_exact(bytes).map_err(|e| match e.kind()
+        {
+            io::ErrorKind::UnexpectedEof => String::from("Truncated checkpoint"),
+            _ => format!("Cannot read checkpoint: {}", e),
+        })
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String>
+    {
+        let mut bytes = [0; N];
+
+        self.raw(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String>
+    {
+        Ok(self.array::<1>()?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String>
+    {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String>
+    {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn usize(&mut self) -> Result<usize, String>
+    {
+        let value = self.u64()?;
+
+        usize::try_from(value).map_err(|_| format!("Checkpoint value {} is too large for this platform", value))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, String>
+    {
+        Ok(f64::from_le_bytes(self.array()?))
+    }
+
+    pub fn str(&mut self) -> Result<String, String>
+    {
+        let length = self.usize()?;
+        let mut bytes = Vec::with_capacity(length.min(MAX_PREALLOCATION));
+
+        (&mut self.inner).take(length as u64).read_to_end(&mut bytes).map_err(|e| format!("Cannot read checkpoint: {}", e))?;
+
+        if bytes.len() < length
+        {
+            return Err(String::from("Truncated checkpoint"));
+        }
+
+        String::from_utf8(bytes).map_err(|_| String::from("Invalid text in checkpoint"))
+    }
+
+    pub fn f64s(&mut self) -> Result<Vec<f64>, String>
+    {
+        self.sequence(Reader::f64)
+    }
+
+    pub fn usizes(&mut self) -> Result<Vec<usize>, String>
+    {
+        self.sequence(Reader::usize)
+    }
+
+    fn sequence<T, F: Fn(&mut Reader<R>) -> Result<T, String>>(&mut self, read: F) -> Result<Vec<T>, String>
+    {
+        let length = self.usize()?;
+        let mut values = Vec::with_capacity(length.min(MAX_PREALLOCATION));
+
+        for _ in 0..length
+        {
+            values.push(read(self)?);
+        }
+
+        Ok(values)
+    }
+}
+
+/// Writes the checkpoint next to `path` first and renames it over, so that dying while saving
+/// leaves the previous checkpoint whole.
+pub fn save<F: FnOnce(&mut Writer<BufWriter<File>>) -> Result<(), String>>(path: &Path, write: F) -> Result<(), String>
+{
+    let temporary = temporary_path(path);
+    let file = File::create(&temporary).map_err(|e| format!("Cannot create '{}': {}", temporary.display(), e))?;
+    let mut writer = Writer::new(BufWriter::new(file))?;
+
+    write(&mut writer)?;
+    writer.finish()?;
+
+    fs::rename(&temporary, path).map_err(|e| format!("Cannot move '{}' to '{}': {}", temporary.display(), path.display(), e))
+}
+
+pub fn load<T, F: FnOnce(&mut Reader<BufReader<File>>) -> Result<T, String>>(path: &Path, read: F) -> Result<T, String>
+{
+    let file = File::open(path).map_err(|e| format!("Cannot open '{}': {}", path.display(), e))?;
+    let mut reader = Reader::new(BufReader::new(file)).map_err(|e| format!("'{}': {}", path.display(), e))?;
+
+    read(&mut reader).map_err(|e| format!("'{}': {}", path.display(), e))
+}
+
+fn temporary_path(path: &Path) -> PathBuf
+{
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ Reader, Writer, VERSION };
+
+    #[test]
+    fn test_round_trip()
+    {
+        let mut writer = Writer::new(vec![]).unwrap();
+
+        writer.u8(7).unwrap();
+        writer.u32(0xdead_beef).unwrap();
+        writer.usize(usize::MAX).unwrap();
+        writer.f64(-0.1).unwrap();
+        writer.str("flame ✓").unwrap();
+        writer.f64s(&[1.5, f64::INFINITY]).unwrap();
+        writer.usizes(&[]).unwrap();
+
+        let bytes = writer.finish().unwrap();
+        let mut reader = Reader::new(&bytes[..]).unwrap();
+
+        assert_eq!(reader.u8().unwrap(), 7);
+        assert_eq!(reader.u32().unwrap(), 0xdead_beef);
+        assert_eq!(reader.usize().unwrap(), usize::MAX);
+        assert_eq!(reader.f64().unwrap(), -0.1);
+        assert_eq!(reader.str().unwrap(), "flame ✓");
+        assert_eq!(reader.f64s().unwrap(), vec![1.5, f64::INFINITY]);
+        assert_eq!(reader.usizes().unwrap(), Vec::<usize>::new());
+        assert_eq!(reader.u8(), Err(String::from("Truncated checkpoint")));
+    }
+
+    #[test]
+    fn test_header_is_checked()
+    {
+        let bytes = Writer::new(vec![]).unwrap().finish().unwrap();
+        let mut newer = bytes.clone();
+
+        newer[8..12].copy_from_slice(&(VERSION + 1).to_le_bytes());
+
+        assert!(Reader::new(&bytes[..]).is_ok());
+        assert_eq!(Reader::new(&b"FLAME"[..]).err(), Some(String::from("Not a checkpoint file")));
+        assert_eq!(Reader::new(&b"P6\n3 2\n255\n"[..]).err(), Some(String::from("Not a checkpoint file")));
+        assert_eq!(Reader::new(&newer[..]).err(), Some(format!("Unsupported checkpoint version {}, expected {}", VERSION + 1, VERSION)));
+    }
+
+    #[test]
+    fn test_corrupted_lengths_fail_cleanly()
+    {
+        let mut writer = Writer::new(vec![]).unwrap();
+
+        writer.u64(u64::MAX / 2).unwrap();
+
+        let bytes = writer.finish().unwrap();
+
+        assert_eq!(Reader::new(&bytes[..]).unwrap().f64s(), Err(String::from("Truncated checkpoint")));
+        assert_eq!(Reader::new(&bytes[..]).unwrap().str(), Err(String::from("Truncated checkpoint")));
+    }
+}
@@ -0,0 +1,46 @@
+use serde::{ Deserialize, Serialize };
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rgb
+{
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb
+{
+    pub const BLACK: Rgb = Rgb { r: 0, g: 0, b: 0 };
+    pub const WHITE: Rgb = Rgb { r: 255, g: 255, b: 255 };
+
+    pub fn new(r: u8, g: u8, b: u8) -> Rgb
+    {
+        Rgb { r, g, b }
+    }
+
+    /// Linear interpolation, `factor` 0 giving `self` and 1 giving `other`.
+    pub fn mix(self, other: Rgb, factor: f64) -> Rgb
+    {
+        let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * factor).round().clamp(0., 255.) as u8;
+
+        Rgb::new(lerp(self.r, other.r), lerp(self.g, other.g), lerp(self.b, other.b))
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Rgb;
+
+    #[test]
+    fn test_mix_goes_both_ways()
+    {
+        let dark = Rgb::new(10, 200, 0);
+        let light = Rgb::new(250, 100, 0);
+
+        assert_eq!(dark.mix(light, 0.), dark);
+        assert_eq!(dark.mix(light, 1.), light);
+        assert_eq!(dark.mix(light, 0.25), Rgb::new(70, 175, 0));
+        assert_eq!(light.mix(dark, 0.25), Rgb::new(190, 125, 0));
+    }
+}
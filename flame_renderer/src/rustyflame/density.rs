@@ -0,0 +1,229 @@
+use serde::{ Deserialize, Serialize };
+
+use super::image::LinearImage;
+
+/// Kernels are cached for radii rounded to this fraction of a cell.
+const RADIUS_STEPS_PER_CELL: f64 = 8.;
+/// Largest radius in output pixels, several times flam3's usual ones. Kernels grow with its square,
+/// and with the supersampling, so much larger radii would only exhaust memory.
+pub const MAX_RADIUS: f64 = 32.;
+
+/// flam3 density estimation: every histogram cell is spread over a kernel whose radius shrinks as
+/// the cell gets denser, `max_radius / density^curve` but no less than `min_radius`. Sparse areas
+/// get smoothed while detailed dense ones stay sharp. Radii are in output pixels, 0 disables it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DensityEstimation
+{
+    pub min_radius: f64,
+    pub max_radius: f64,
+    pub curve: f64,
+}
+
+impl Default for DensityEstimation
+{
+    fn default() -> DensityEstimation
+    {
+        DensityEstimation { min_radius: 0., max_radius: 0., curve: 0.4 }
+    }
+}
+
+/// Offsets and normalized weights of the cells a sample gets spread on.
+type Kernel = Vec<(isize, isize, f64)>;
+
+impl DensityEstimation
+{
+    /// flam3 default estimator.
+    pub const FLAM3: DensityEstimation = DensityEstimation { min_radius: 0., max_radius: 9., curve: 0.4 };
+
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if !(self.min_radius.is_finite() && self.max_radius.is_finite() && 0. <= self.min_radius && self.min_radius <= self.max_radius)
+        {
+            return Err(format!("Invalid density estimation radii [{}, {}]", self.min_radius, self.max_radius));
+        }
+
+        if self.max_radius > MAX_RADIUS
+        {
+            return Err(format!("Density estimation radius {} is too large, it must be at most {}", self.max_radius, MAX_RADIUS));
+        }
+
+        if !(self.curve.is_finite() && self.curve >= 0.)
+        {
+            return Err(format!("Invalid density estimation curve {}, it must be positive", self.curve));
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool
+    {
+        self.max_radius > 0.
+    }
+
+    /// Kernel radius in output pixels for a cell hit `density` times.
+    pub fn radius(&self, density: usize) -> f64
+    {
+        (self.max_radius / (density.max(1) as f64).powf(self.curve)).max(self.min_radius)
+    }
+
+    /// Filters the supersampled `cells`, `densities` being their hit counts and `super_sampling`
+    /// the number of cells per output pixel along each axis.
+    pub fn apply(&self, cells: LinearImage, densities: &[usize], super_sampling: usize) -> LinearImage
+    {
+        if !self.is_enabled()
+        {
+            return cells;
+        }
+
+        let dimensions = cells.dimensions();
+        let (width, height) = (dimensions.width as isize, dimensions.height as isize);
+        let mut kernels: Vec<Option<Kernel>> = vec![];
+        let mut filtered = LinearImage::new(dimensions);
+
+        for (idx, (cell, density)) in cells.pixels().iter().zip(densities).enumerate()
+        {
+            if *density == 0
+            {
+                continue;
+            }
+
+            let radius = self.radius(*density) * super_sampling as f64;
+            let step = (radius * RADIUS_STEPS_PER_CELL).round() as usize;
+
+            if step >= kernels.len()
+            {
+                kernels.resize(step + 1, None);
+            }
+
+            let kernel = kernels[step].get_or_insert_with(|| build_kernel(step as f64 / RADIUS_STEPS_PER_CELL));
+            let (x, y) = ((idx % dimensions.width as usize) as isize, (idx / dimensions.width as usize) as isize);
+
+            for (dx, dy, weight) in kernel.iter()
+            {
+                let (tx, ty) = (x + dx, y + dy);
+
+                if tx >= 0 && tx < width && ty >= 0 && ty < height
+                {
+                    let target = &mut filtered.pixels_mut()[(ty * width + tx) as usize];
+
+                    for (t, c) in target.iter_mut().zip(cell.iter())
+                    {
+                        *t += weight * c;
+                    }
+                }
+            }
+        }
+
+        filtered
+    }
+}
+
+/// Epanechnikov kernel, normalized so that filtering keeps the overall brightness. Radii under
+/// a cell leave the sample where it is.
+fn build_kernel(radius: f64) -> Kernel
+{
+    if radius <= 1.
+    {
+        return vec![(0, 0, 1.)];
+    }
+
+    let extent = radius.floor() as isize;
+    let mut kernel: Kernel = (-extent..=extent)
+        .flat_map(|dy| (-extent..=extent).map(move |dx| (dx, dy)))
+        .map(|(dx, dy)| (dx, dy, 1. - (dx * dx + dy * dy) as f64 / (radius * radius)))
+        .filter(|(_, _, weight)| *weight > 0.)
+        .collect();
+    let total: f64 = kernel.iter().map(|(_, _, weight)| weight).sum();
+
+    for (_, _, weight) in kernel.iter_mut()
+    {
+        *weight /= total;
+    }
+
+    kernel
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ DensityEstimation, MAX_RADIUS };
+    use super::super::image::{ Dimensions, LinearImage };
+
+    const DIMENSIONS: Dimensions = Dimensions { width: 9, height: 9 };
+
+    fn single_cell(density: usize) -> (LinearImage, Vec<usize>)
+    {
+        let mut cells = LinearImage::new(DIMENSIONS);
+        let mut densities = vec![0; DIMENSIONS.area()];
+
+        cells.pixels_mut()[40] = [0.5, 0.25, 0., 1.];
+        densities[40] = density;
+
+        (cells, densities)
+    }
+
+    fn lit_cells(image: &LinearImage) -> usize
+    {
+        image.pixels().iter().filter(|p| p[3] > 0.).count()
+    }
+
+    #[test]
+    fn test_radius_shrinks_with_density()
+    {
+        let estimation = DensityEstimation { min_radius: 0.5, max_radius: 4., curve: 0.5 };
+
+        assert_eq!(estimation.radius(0), 4.);
+        assert_eq!(estimation.radius(1), 4.);
+        assert_eq!(estimation.radius(16), 1.);
+        assert_eq!(estimation.radius(10000), 0.5);
+    }
+
+    #[test]
+    fn test_sparse_cell_is_spread_and_keeps_energy()
+    {
+        let estimation = DensityEstimation { min_radius: 0., max_radius: 3., curve: 0.5 };
+        let (cells, densities) = single_cell(1);
+        let filtered = estimation.apply(cells, &densities, 1);
+        let total = filtered.pixels().iter().fold([0.; 4], |acc, p| [acc[0] + p[0], acc[1] + p[1], acc[2] + p[2], acc[3] + p[3]]);
+
+        // Radius 3 covers the cells at distance < 3 from the center.
+        assert_eq!(lit_cells(&filtered), 25);
+        assert!(filtered.pixels()[40][3] > filtered.pixels()[41][3]);
+        assert!(total.iter().zip([0.5, 0.25, 0., 1.].iter()).all(|(t, e)| (t - e).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_dense_cell_stays_sharp()
+    {
+        let estimation = DensityEstimation { min_radius: 0., max_radius: 3., curve: 0.5 };
+        let (cells, densities) = single_cell(100);
+        let filtered = estimation.apply(cells.clone(), &densities, 1);
+
+        assert_eq!(filtered.pixels(), cells.pixels());
+
+        // The same density spreads further on a supersampled histogram, radii being in output pixels:
+        // 0.3 pixels are 1.2 cells, reaching the four neighbours.
+        assert_eq!(lit_cells(&estimation.apply(cells, &densities, 4)), 5);
+    }
+
+    #[test]
+    fn test_disabled_is_identity()
+    {
+        let (cells, densities) = single_cell(1);
+
+        assert_eq!(DensityEstimation::default().apply(cells.clone(), &densities, 2).pixels(), cells.pixels());
+    }
+
+    #[test]
+    fn test_validate()
+    {
+        assert!(DensityEstimation::default().validate().is_ok());
+        assert!(DensityEstimation::FLAM3.validate().is_ok());
+        assert!(DensityEstimation { min_radius: 2., max_radius: 1., curve: 0.4 }.validate().is_err());
+        assert!(DensityEstimation { min_radius: 0., max_radius: 1., curve: -1. }.validate().is_err());
+        assert!(DensityEstimation { min_radius: 0., max_radius: f64::INFINITY, curve: 0.4 }.validate().is_err());
+        assert!(DensityEstimation { min_radius: 0., max_radius: MAX_RADIUS, curve: 0.4 }.validate().is_ok());
+        assert!(DensityEstimation { min_radius: 0., max_radius: 1e9, curve: 0.4 }.validate().is_err());
+    }
+}
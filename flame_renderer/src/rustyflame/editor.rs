@@ -0,0 +1,319 @@
+use super::camera::Camera;
+use super::color::Rgb;
+use super::genome::Genome;
+use super::image::{ Dimensions, RgbaImage };
+use super::math::{ AugmentedMat2x2, Vec2D };
+use super::overlay;
+use super::simulator::FunctionElement;
+
+/// How close to a vertex, in pixels, a click has to be to grab it.
+const PICK_RADIUS: f64 = 8.;
+const MARKER_HALF_SIZE: f64 = 3.;
+const SELECTED_COLOR: Rgb = Rgb::WHITE;
+const UNSELECTED_COLOR: Rgb = Rgb { r: 128, g: 128, b: 128 };
+const VERTEX_NAMES: [&str; 3] = ["O", "X", "Y"];
+
+/// Where a transform sends the origin and the two unit vectors, which pins it down. Moving the
+/// origin vertex translates the map, the others change the images of the axes.
+pub fn triangle(application: &AugmentedMat2x2) -> [Vec2D; 3]
+{
+    let origin = application.offset();
+
+    [
+        origin,
+        *application * Vec2D { x: 1., y: 0. },
+        *application * Vec2D { x: 0., y: 1. },
+    ]
+}
+
+pub fn from_triangle(vertices: &[Vec2D; 3]) -> AugmentedMat2x2
+{
+    let [origin, x, y] = *vertices;
+
+    AugmentedMat2x2::new
+    ([
+        [x.x - origin.x, y.x - origin.x, origin.x],
+        [x.y - origin.y, y.y - origin.y, origin.y],
+    ])
+}
+
+/// Edits the transforms of a genome by dragging the vertices of their triangles, as drawn over
+/// the flame with `draw`. Positions are in pixels of a frame seen through a camera.
+pub struct Editor
+{
+    genome: Genome,
+    selected: usize,
+    /// Vertex of the selected transform being dragged.
+    grabbed: Option<usize>,
+}
+
+impl Editor
+{
+    pub fn new(genome: Genome) -> Editor
+    {
+        Editor { genome, selected: 0, grabbed: None }
+    }
+
+    pub fn genome(&self) -> &Genome
+    {
+        &self.genome
+    }
+
+    pub fn selected(&self) -> usize
+    {
+        self.selected
+    }
+
+    pub fn is_dragging(&self) -> bool
+    {
+        self.grabbed.is_some()
+    }
+
+    pub fn select_next(&mut self)
+    {
+        self.selected = (self.selected + 1) % self.genome.transforms.len();
+    }
+
+    pub fn select_previous(&mut self)
+    {
+        let count = self.genome.transforms.len();
+
+        self.selected = (self.selected + count - 1) % count;
+    }
+
+    /// Adds a half size copy of the selected transform, slightly moved, and selects it.
+    pub fn add_transform(&mut self)
+    {
+        let model = &self.genome.transforms[self.selected];
+        let application = AugmentedMat2x2::translation(Vec2D { x: 0.25, y: 0.25 }) * model.application * AugmentedMat2x2::scale(0.5, 0.5);
+
+        self.genome.transforms.push(FunctionElement { application, ..model.clone() });
+        self.selected = self.genome.transforms.len() - 1;
+    }
+
+    /// Removes the selected transform, unless it is the last one.
+    pub fn delete_selected(&mut self) -> bool
+    {
+        if self.genome.transforms.len() <= 1
+        {
+            return false;
+        }
+
+        self.genome.transforms.remove(self.selected);
+        self.selected = self.selected.min(self.genome.transforms.len() - 1);
+        self.grabbed = None;
+
+        true
+    }
+
+    /// Grabs the vertex under `position`, preferring the selected transform, and selects its
+    /// transform. False when there is none, so that the click can be used for something else.
+    pub fn press(&mut self, position: Vec2D, camera: &Camera, dimensions: &Dimensions) -> bool
+    {
+        let to_frame = camera.world_to_frame(dimensions);
+        let count = self.genome.transforms.len();
+        let candidates = (0..count).map(|offset| (self.selected + offset) % count);
+
+        for index in candidates
+        {
+            let vertices = triangle(&self.genome.transforms[index].application);
+
+            if let Some(vertex) = vertices.iter().position(|v| (to_frame * *v - position).length() <= PICK_RADIUS)
+            {
+                self.selected = index;
+                self.grabbed = Some(vertex);
+
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Moves the grabbed vertex to `position`. True when the genome changed.
+    pub fn drag_to(&mut self, position: Vec2D, camera: &Camera, dimensions: &Dimensions) -> bool
+    {
+        let vertex = match self.grabbed
+        {
+            Some(vertex) => vertex,
+            None => return false,
+        };
+
+        let transform = &mut self.genome.transforms[self.selected];
+        let mut vertices = triangle(&transform.application);
+        let target = camera.frame_to_world(position, dimensions);
+
+        if vertex == 0
+        {
+            // The origin carries the whole triangle along.
+            let shift = target - vertices[0];
+
+            vertices.iter_mut().for_each(|v| *v = *v + shift);
+        }
+        else
+        {
+            vertices[vertex] = target;
+        }
+
+        transform.application = from_triangle(&vertices);
+        true
+    }
+
+    pub fn release(&mut self)
+    {
+        self.grabbed = None;
+    }
+
+    /// Draws every triangle, the selected one last, in white and with its vertices marked.
+    pub fn draw(&self, image: &mut RgbaImage, camera: &Camera)
+    {
+        let dimensions = image.dimensions();
+        let to_frame = camera.world_to_frame(&dimensions);
+        let order = (0..self.genome.transforms.len()).filter(|index| *index != self.selected).chain(Some(self.selected));
+
+        for index in order
+        {
+            let vertices = triangle(&self.genome.transforms[index].application).map(|v| to_frame * v);
+            let color = if index == self.selected { SELECTED_COLOR } else { UNSELECTED_COLOR };
+
+            for (from, to) in [(0, 1), (1, 2), (2, 0)].iter()
+            {
+                overlay::draw_line(image, vertices[*from], vertices[*to], color);
+            }
+
+            if index == self.selected
+            {
+                for (vertex, name) in vertices.iter().zip(VERTEX_NAMES.iter())
+                {
+                    overlay::draw_marker(image, *vertex, MARKER_HALF_SIZE, color);
+
+                    // Labels of vertices off the image would not show anyway, and their position may not fit a u32.
+                    if vertex.x >= 0. && vertex.y >= 0. && vertex.x < dimensions.width as f64 && vertex.y < dimensions.height as f64
+                    {
+                        let label = *vertex + Vec2D { x: MARKER_HALF_SIZE + 2., y: MARKER_HALF_SIZE + 2. };
+
+                        overlay::draw_text(image, label.x as u32, label.y as u32, name, color);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn status(&self) -> String
+    {
+        let transform = &self.genome.transforms[self.selected];
+
+        format!
+        (
+            "Transform {}/{}  weight {:.2}  color {:.2}",
+            self.selected + 1, self.genome.transforms.len(), transform.weight, transform.color
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ from_triangle, triangle, Editor };
+    use super::super::camera::Camera;
+    use super::super::color::Rgb;
+    use super::super::genome::Genome;
+    use super::super::image::{ Dimensions, RgbaImage };
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+    use super::super::simulator::FunctionElement;
+
+    const DIMENSIONS: Dimensions = Dimensions { width: 100, height: 100 };
+
+    fn editor() -> Editor
+    {
+        let transforms = vec!
+        [
+            FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., -0.5], [0., 0.5, -0.5]]), 0.),
+            FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., 0.5], [0., 0.5, -0.5]]), 1.),
+        ];
+
+        Editor::new(Genome { transforms, ..Genome::default() })
+    }
+
+    fn frame(p: Vec2D) -> Vec2D
+    {
+        Camera::default().world_to_frame(&DIMENSIONS) * p
+    }
+
+    #[test]
+    fn test_triangle_round_trip()
+    {
+        let m = AugmentedMat2x2::new([[1., 2., 3.], [4., 5., 6.]]);
+
+        assert_eq!(triangle(&m), [Vec2D { x: 3., y: 6. }, Vec2D { x: 4., y: 10. }, Vec2D { x: 5., y: 11. }]);
+        assert_eq!(from_triangle(&triangle(&m)), m);
+    }
+
+    #[test]
+    fn test_drag_vertices()
+    {
+        let mut editor = editor();
+        let camera = Camera::default();
+
+        // The X vertex of the second transform, near but not exactly on it.
+        assert!(editor.press(frame(Vec2D { x: 1., y: -0.5 }) + Vec2D { x: 2., y: -3. }, &camera, &DIMENSIONS));
+        assert_eq!(editor.selected(), 1);
+        assert!(editor.drag_to(frame(Vec2D { x: 1., y: 0. }), &camera, &DIMENSIONS));
+
+        let edited = editor.genome().transforms[1].application;
+
+        assert!(edited.approx_eq(&AugmentedMat2x2::new([[0.5, 0., 0.5], [0.5, 0.5, -0.5]]), 1e-12));
+
+        // Dragging the origin moves the whole triangle.
+        editor.release();
+        assert!(editor.press(frame(Vec2D { x: -0.5, y: -0.5 }), &camera, &DIMENSIONS));
+        assert!(editor.drag_to(frame(Vec2D { x: 0., y: 0. }), &camera, &DIMENSIONS));
+        assert!(editor.genome().transforms[0].application.approx_eq(&AugmentedMat2x2::new([[0.5, 0., 0.], [0., 0.5, 0.]]), 1e-12));
+
+        editor.release();
+        assert!(!editor.is_dragging());
+        assert!(!editor.drag_to(frame(Vec2D { x: 0.3, y: 0. }), &camera, &DIMENSIONS));
+        assert!(!editor.press(Vec2D { x: 99., y: 1. }, &camera, &DIMENSIONS));
+    }
+
+    #[test]
+    fn test_add_select_delete()
+    {
+        let mut editor = editor();
+
+        editor.add_transform();
+        assert_eq!(editor.genome().transforms.len(), 3);
+        assert_eq!(editor.selected(), 2);
+        assert!(editor.genome().validate().is_ok());
+
+        editor.select_next();
+        assert_eq!(editor.selected(), 0);
+        editor.select_previous();
+        assert_eq!(editor.status(), "Transform 3/3  weight 1.00  color 0.00");
+
+        assert!(editor.delete_selected());
+        assert_eq!(editor.selected(), 1);
+        assert!(editor.delete_selected());
+        assert!(!editor.delete_selected());
+        assert_eq!(editor.genome().transforms.len(), 1);
+    }
+
+    #[test]
+    fn test_draw_marks_the_selected_triangle()
+    {
+        let editor = editor();
+        let mut image = RgbaImage::new(DIMENSIONS);
+
+        editor.draw(&mut image, &Camera::default());
+
+        let origin = frame(Vec2D { x: -0.5, y: -0.5 });
+        let other = frame(Vec2D { x: 0.5, y: -0.5 });
+        let white = [Rgb::WHITE.r, Rgb::WHITE.g, Rgb::WHITE.b, 255];
+
+        assert_eq!(image.get_pixel(origin.x.round() as u32, origin.y.round() as u32), white);
+        assert_eq!(image.get_pixel(other.x.round() as u32, other.y.round() as u32), [128, 128, 128, 255]);
+
+        // Zoomed far in, the vertices land way off the image and their labels are left out.
+        editor.draw(&mut image, &Camera { zoom: 1e12, ..Camera::default() });
+    }
+}
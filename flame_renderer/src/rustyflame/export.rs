@@ -0,0 +1,229 @@
+use std::fs::File;
+use std::io::{ self, BufWriter, Write };
+use std::path::Path;
+
+use super::image::{ LinearImage, RgbaImage, RGBA_CHANNELS };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ImageFormat
+{
+    Png,
+    Ppm,
+    Pam,
+    /// Linear floats, only for `LinearImage`.
+    Pfm,
+}
+
+impl ImageFormat
+{
+    pub fn from_path(path: &Path) -> Result<ImageFormat, String>
+    {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref()
+        {
+            Some("png") => Ok(ImageFormat::Png),
+            Some("ppm") => Ok(ImageFormat::Ppm),
+            Some("pam") => Ok(ImageFormat::Pam),
+            Some("pfm") => Ok(ImageFormat::Pfm),
+            _ => Err(format!("Unsupported image format for '{}' (expected .png, .ppm, .pam or .pfm)", path.display())),
+        }
+    }
+}
+
+/// Binary PPM (P6). The format has no alpha channel, so the image is composited over black.
+pub fn write_ppm<W: Write>(image: &RgbaImage, mut writer: W) -> io::Result<()>
+{
+    let dimensions = image.dimensions();
+
+    write!(writer, "P6\n{} {}\n255\n", dimensions.width, dimensions.height)?;
+
+    let composited: Vec<u8> = image
+        .pixels()
+        .chunks_exact(RGBA_CHANNELS)
+        .flat_map(|pixel| 
+        {
+            let alpha = pixel[3] as u32;
+
+            [pixel[0], pixel[1], pixel[2]].map(|c| ((c as u32 * alpha + 127) / 255) as u8)
+        })
+        .collect();
+
+    writer.write_all(&composited)?;
+    writer.flush()
+}
+
+/// Binary PAM (P7) with the RGB_ALPHA tuple type, keeping straight alpha.
+pub fn write_pam<W: Write>(image: &RgbaImage, mut writer: W) -> io::Result<()>
+{
+    let dimensions = image.dimensions();
+
+    write!
+    (
+        writer,
+        "P7\nWIDTH {}\nHEIGHT {}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n",
+        dimensions.width,
+        dimensions.height
+    )?;
+    writer.write_all(image.pixels())?;
+    writer.flush()
+}
+
+pub fn write_png<W: Write>(image: &RgbaImage, writer: W) -> Result<(), String>
+{
+    let dimensions = image.dimensions();
+    let mut encoder = png::Encoder::new(writer, dimensions.width, dimensions.height);
+
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png_writer = encoder.write_header().map_err(|e| e.to_string())?;
+
+    png_writer.write_image_data(image.pixels()).map_err(|e| e.to_string())?;
+    png_writer.finish().map_err(|e| e.to_string())
+}
+
+/// Color portable float map (PF), little endian. The format stores rows bottom to top and has no
+/// alpha channel, so only the premultiplied color is written.
+pub fn write_pfm<W: Write>(image: &LinearImage, mut writer: W) -> io::Result<()>
+{
+    let dimensions = image.dimensions();
+
+    write!(writer, "PF\n{} {}\n-1.0\n", dimensions.width, dimensions.height)?;
+
+    for row in image.pixels().chunks_exact(dimensions.width as usize).rev()
+    {
+        for pixel in row
+        {
+            for c in &pixel[..3]
+            {
+                writer.write_all(&(*c as f32).to_le_bytes())?;
+            }
+        }
+    }
+
+    writer.flush()
+}
+
+pub fn save_linear_image(image: &LinearImage, path: &Path) -> Result<(), String>
+{
+    if ImageFormat::from_path(path)? != ImageFormat::Pfm
+    {
+        return Err(format!("Linear images can only be saved as .pfm, not '{}'", path.display()));
+    }
+
+    let file = File::create(path).map_err(|e| format!("Cannot create '{}': {}", path.display(), e))?;
+
+    write_pfm(image, BufWriter::new(file)).map_err(|e| e.to_string())
+}
+
+/// Saves the image, picking the format from the file extension.
+pub fn save_image(image: &RgbaImage, path: &Path) -> Result<(), String>
+{
+    let create = || File::create(path).map(BufWriter::new).map_err(|e| format!("Cannot create '{}': {}", path.display(), e));
+
+    match ImageFormat::from_path(path)?
+    {
+        ImageFormat::Png => write_png(image, create()?),
+        ImageFormat::Ppm => write_ppm(image, create()?).map_err(|e| e.to_string()),
+        ImageFormat::Pam => write_pam(image, create()?).map_err(|e| e.to_string()),
+        ImageFormat::Pfm => Err(format!("'{}' is a float format, only linear images can be saved to it", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+
+    use super::{ ImageFormat, write_pam, write_pfm, write_png, write_ppm };
+    use super::super::image::{ Dimensions, LinearImage, RgbaImage };
+
+    fn test_image() -> RgbaImage
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 2, height: 1 });
+
+        image.set_pixel(0, 0, [255, 128, 0, 255]);
+        image.set_pixel(1, 0, [200, 100, 50, 51]);
+
+        image
+    }
+
+    #[test]
+    fn test_format_from_path()
+    {
+        assert_eq!(ImageFormat::from_path(Path::new("frame.png")), Ok(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path(Path::new("out/frame.PPM")), Ok(ImageFormat::Ppm));
+        assert_eq!(ImageFormat::from_path(Path::new("frame.pam")), Ok(ImageFormat::Pam));
+        assert_eq!(ImageFormat::from_path(Path::new("frame.pfm")), Ok(ImageFormat::Pfm));
+        assert!(ImageFormat::from_path(Path::new("frame.jpg")).is_err());
+        assert!(ImageFormat::from_path(Path::new("frame")).is_err());
+    }
+
+    #[test]
+    fn test_write_ppm_composites_over_black()
+    {
+        let mut out = vec![];
+
+        write_ppm(&test_image(), &mut out).unwrap();
+
+        let mut expected = b"P6\n2 1\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 128, 0, 40, 20, 10]);
+
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn test_write_pam_keeps_alpha()
+    {
+        let mut out = vec![];
+
+        write_pam(&test_image(), &mut out).unwrap();
+
+        let header = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n";
+
+        assert_eq!(&out[..header.len()], &header[..]);
+        assert_eq!(&out[header.len()..], &[255, 128, 0, 255, 200, 100, 50, 51]);
+    }
+
+    #[test]
+    fn test_write_png_round_trip()
+    {
+        let image = test_image();
+        let mut out = vec![];
+
+        write_png(&image, &mut out).unwrap();
+
+        let decoder = png::Decoder::new(&out[..]);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(info.color_type, png::ColorType::Rgba);
+        assert_eq!(&buffer[..info.buffer_size()], image.pixels());
+    }
+
+    #[test]
+    fn test_write_pfm_bottom_up()
+    {
+        let mut image = LinearImage::new(Dimensions { width: 1, height: 2 });
+        let mut out = vec![];
+
+        image.pixels_mut()[0] = [1., 2., 3., 4.];
+        image.pixels_mut()[1] = [0.5, 0., 1e6, 1.];
+        write_pfm(&image, &mut out).unwrap();
+
+        let header = b"PF\n1 2\n-1.0\n";
+        let floats: Vec<f32> = out[header.len()..]
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+
+        assert_eq!(&out[..header.len()], &header[..]);
+        assert_eq!(floats, vec![0.5, 0., 1e6, 1., 2., 3.]);
+    }
+}
@@ -0,0 +1,340 @@
+use std::f64::consts::PI;
+
+use serde::{ Deserialize, Serialize };
+
+use super::image::{ Dimensions, LinearImage, LinearPixel };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind
+{
+    Box,
+    Gaussian,
+    Mitchell,
+    Lanczos,
+}
+
+impl FilterKind
+{
+    pub const ALL: [FilterKind; 4] = [FilterKind::Box, FilterKind::Gaussian, FilterKind::Mitchell, FilterKind::Lanczos];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            FilterKind::Box => "box",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FilterKind>
+    {
+        FilterKind::ALL.iter().copied().find(|kind| kind.name() == name)
+    }
+
+    /// Kernel at `x`, in units of the filter radius: everything is 0 outside of [-1, 1].
+    pub fn evaluate(&self, x: f64) -> f64
+    {
+        let x = x.abs();
+
+        if x > 1.
+        {
+            return 0.;
+        }
+
+        match self
+        {
+            FilterKind::Box => 1.,
+            // Truncated at three standard deviations.
+            FilterKind::Gaussian => (-4.5 * x * x).exp(),
+            // Mitchell-Netravali with B = C = 1/3, whose support is [-2, 2].
+            FilterKind::Mitchell =>
+            {
+                let u = 2. * x;
+
+                if u < 1.
+                {
+                    (7. * u * u * u - 12. * u * u + 16. / 3.) / 6.
+                }
+                else
+                {
+                    (-7. / 3. * u * u * u + 12. * u * u - 20. * u + 32. / 3.) / 6.
+                }
+            },
+            // Lanczos 3, whose support is [-3, 3].
+            FilterKind::Lanczos =>
+            {
+                let u = 3. * x;
+
+                sinc(u) * sinc(u / 3.)
+            },
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64
+{
+    if x == 0.
+    {
+        1.
+    }
+    else
+    {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+/// Filter used to bring the supersampled histogram down to the output resolution. The radius is
+/// in output pixels; the default box of radius 1/2 averages each block of cells.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconstructionFilter
+{
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Default for ReconstructionFilter
+{
+    fn default() -> ReconstructionFilter
+    {
+        ReconstructionFilter { kind: FilterKind::Box, radius: 0.5 }
+    }
+}
+
+/// Per output coordinate, the first input cell and the weights of `taps` consecutive cells.
+struct AxisWeights
+{
+    starts: Vec<usize>,
+    taps: usize,
+    weights: Vec<f64>,
+}
+
+impl ReconstructionFilter
+{
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if !(self.radius.is_finite() && self.radius > 0.)
+        {
+            return Err(format!("Invalid filter radius {}, it must be strictly positive", self.radius));
+        }
+
+        Ok(())
+    }
+
+    /// Resamples `cells` to `1 / factor` of their size. The filter is separable, so it runs as a
+    /// horizontal then a vertical pass with precomputed weights.
+    pub fn downsample(&self, cells: &LinearImage, factor: usize) -> LinearImage
+    {
+        let input = cells.dimensions();
+        let output = Dimensions { width: input.width / factor as u32, height: input.height / factor as u32 };
+        let horizontal = self.axis_weights(input.width as usize, output.width as usize, factor);
+        let vertical = self.axis_weights(input.height as usize, output.height as usize, factor);
+
+        let mut columns = LinearImage::new(Dimensions { width: output.width, height: input.height });
+
+        for (row, filtered_row) in cells.pixels().chunks_exact(input.width as usize).zip(columns.pixels_mut().chunks_exact_mut(output.width as usize))
+        {
+            for (x, pixel) in filtered_row.iter_mut().enumerate()
+            {
+                let start = horizontal.starts[x];
+                let weights = &horizontal.weights[x * horizontal.taps..(x + 1) * horizontal.taps];
+
+                for (weight, cell) in weights.iter().zip(&row[start..])
+                {
+                    accumulate(pixel, cell, *weight);
+                }
+            }
+        }
+
+        let mut image = LinearImage::new(output);
+        let width = output.width as usize;
+
+        for (y, filtered_row) in image.pixels_mut().chunks_exact_mut(width).enumerate()
+        {
+            let start = vertical.starts[y];
+            let weights = &vertical.weights[y * vertical.taps..(y + 1) * vertical.taps];
+
+            for (k, weight) in weights.iter().enumerate().filter(|(_, w)| **w != 0.)
+            {
+                let row = &columns.pixels()[(start + k) * width..(start + k + 1) * width];
+
+                for (pixel, cell) in filtered_row.iter_mut().zip(row)
+                {
+                    accumulate(pixel, cell, *weight);
+                }
+            }
+        }
+
+        image
+    }
+
+    fn axis_weights(&self, cells: usize, outputs: usize, factor: usize) -> AxisWeights
+    {
+        let support = self.radius * factor as f64;
+        let taps = (2. * support).floor() as usize + 1;
+        let mut starts = Vec::with_capacity(outputs);
+        let mut weights = vec![0.; outputs * taps];
+
+        for (o, output_weights) in weights.chunks_exact_mut(taps).enumerate()
+        {
+            // Distances are measured between cell centers and output pixel centers, in cells.
+            let center = (o as f64 + 0.5) * factor as f64;
+            let first = (center - support - 0.5).ceil().max(0.) as usize;
+            let start = first.min(cells.saturating_sub(taps));
+
+            for (k, weight) in output_weights.iter_mut().enumerate()
+            {
+                let cell = start + k;
+
+                if cell < cells
+                {
+                    *weight = self.kind.evaluate((cell as f64 + 0.5 - center) / support);
+                }
+            }
+
+            let mut total: f64 = output_weights.iter().sum();
+
+            // A support narrower than the distance to the closest cells misses them all, as with a
+            // small radius and even supersampling. The closest cells then share the pixel instead.
+            if total == 0.
+            {
+                let distance = |k: usize| (((start + k) as f64 + 0.5) - center).abs();
+                let closest = (0..taps).filter(|k| start + k < cells).map(distance).fold(f64::INFINITY, f64::min);
+
+                for (k, weight) in output_weights.iter_mut().enumerate()
+                {
+                    if start + k < cells && distance(k) <= closest + 1e-9
+                    {
+                        *weight = 1.;
+                        total += 1.;
+                    }
+                }
+            }
+
+            if total != 0.
+            {
+                output_weights.iter_mut().for_each(|w| *w /= total);
+            }
+
+            starts.push(start);
+        }
+
+        AxisWeights { starts, taps, weights }
+    }
+}
+
+fn accumulate(pixel: &mut LinearPixel, cell: &LinearPixel, weight: f64)
+{
+    for (p, c) in pixel.iter_mut().zip(cell.iter())
+    {
+        *p += weight * c;
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ FilterKind, ReconstructionFilter };
+    use super::super::image::{ Dimensions, LinearImage };
+
+    fn assert_close(a: f64, b: f64)
+    {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_kernels()
+    {
+        for kind in FilterKind::ALL.iter()
+        {
+            assert_eq!(FilterKind::from_name(kind.name()), Some(*kind));
+            assert_eq!(kind.evaluate(1.5), 0.);
+            assert_eq!(kind.evaluate(-0.3), kind.evaluate(0.3));
+        }
+
+        assert_close(FilterKind::Mitchell.evaluate(0.), 8. / 9.);
+        assert_close(FilterKind::Mitchell.evaluate(1.), 0.);
+        assert_close(FilterKind::Lanczos.evaluate(0.), 1.);
+        assert_close(FilterKind::Lanczos.evaluate(1. / 3.), 0.);
+        assert!(FilterKind::Lanczos.evaluate(0.5) < 0.);
+        assert!(FilterKind::from_name("sinc").is_none());
+    }
+
+    #[test]
+    fn test_default_box_averages_blocks()
+    {
+        let mut cells = LinearImage::new(Dimensions { width: 4, height: 2 });
+
+        cells.pixels_mut()[0] = [4., 0., 0., 4.];
+        cells.pixels_mut()[5] = [0., 2., 0., 2.];
+        cells.pixels_mut()[3] = [0., 0., 1., 1.];
+
+        let image = ReconstructionFilter::default().downsample(&cells, 2);
+
+        assert_eq!(image.dimensions(), Dimensions { width: 2, height: 1 });
+        assert_eq!(image.pixels(), &[[1., 0.5, 0., 1.5], [0., 0., 0.25, 0.25]]);
+    }
+
+    #[test]
+    fn test_flat_image_stays_flat()
+    {
+        let mut cells = LinearImage::new(Dimensions { width: 12, height: 8 });
+
+        cells.pixels_mut().iter_mut().for_each(|p| *p = [0.5, 0.25, 1., 2.]);
+
+        for kind in FilterKind::ALL.iter()
+        {
+            let image = ReconstructionFilter { kind: *kind, radius: 1.5 }.downsample(&cells, 4);
+
+            for pixel in image.pixels()
+            {
+                for (c, expected) in pixel.iter().zip([0.5, 0.25, 1., 2.].iter())
+                {
+                    assert_close(*c, *expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_narrow_filter_keeps_flat_image()
+    {
+        let mut cells = LinearImage::new(Dimensions { width: 4, height: 4 });
+
+        cells.pixels_mut().iter_mut().for_each(|p| *p = [1.; 4]);
+
+        for kind in FilterKind::ALL.iter()
+        {
+            let image = ReconstructionFilter { kind: *kind, radius: 0.2 }.downsample(&cells, 2);
+
+            assert!(image.pixels().iter().flatten().all(|c| (c - 1.).abs() < 1e-9), "{:?}: {:?}", kind, image.pixels());
+        }
+    }
+
+    #[test]
+    fn test_wide_filter_spreads_and_keeps_energy()
+    {
+        let mut cells = LinearImage::new(Dimensions { width: 10, height: 10 });
+
+        cells.pixels_mut()[4 * 10 + 5] = [0., 0., 0., 1.];
+
+        let image = ReconstructionFilter { kind: FilterKind::Gaussian, radius: 2. }.downsample(&cells, 1);
+        let lit = image.pixels().iter().filter(|p| p[3] > 0.).count();
+        let total: f64 = image.pixels().iter().map(|p| p[3]).sum();
+
+        assert_eq!(lit, 25);
+        assert!(image.pixels()[4 * 10 + 5][3] > image.pixels()[4 * 10 + 6][3]);
+        assert_close(total, 1.);
+    }
+
+    #[test]
+    fn test_validate()
+    {
+        assert!(ReconstructionFilter::default().validate().is_ok());
+        assert!(ReconstructionFilter { kind: FilterKind::Box, radius: 0. }.validate().is_err());
+        assert!(ReconstructionFilter { kind: FilterKind::Lanczos, radius: f64::NAN }.validate().is_err());
+    }
+}
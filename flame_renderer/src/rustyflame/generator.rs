@@ -0,0 +1,302 @@
+use std::ops::RangeInclusive;
+
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+use super::color::Rgb;
+use super::genome::Genome;
+use super::image::Dimensions;
+use super::math::AugmentedMat2x2;
+use super::palette::Palette;
+use super::progress;
+use super::simulator::{ FunctionElement, Simulator, SuperSamplingFactor };
+use super::symmetry::Symmetry;
+use super::variations;
+
+const PREVIEW_PREPARATION_ROUNDS: usize = 20;
+
+/// Why a candidate genome was not interesting enough.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rejection
+{
+    /// The affine parts grow areas on average, so the walker runs away instead of settling on an attractor.
+    Expanding,
+    /// Too few samples landed in the frame.
+    Sparse { in_frame: f64 },
+    /// Too little of the frame was reached, typically a point or a thin curve.
+    Small { coverage: f64 },
+    /// Nearly all of the frame was reached, typically a shapeless blob.
+    Blob { coverage: f64 },
+}
+
+/// Draws random genomes from configurable distributions, keeping only those whose quick preview
+/// looks like a flame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Generator
+{
+    /// Transform counts, picked uniformly.
+    pub transforms: RangeInclusive<usize>,
+    /// Most variations blended in a transform.
+    pub max_variations: usize,
+    /// Weights the transforms by the determinant of their affine part rather than uniformly.
+    pub determinant_weights: bool,
+    /// Chance of a random gradient rather than a builtin palette.
+    pub gradient_probability: f64,
+    /// Chance of rotational or dihedral symmetry, of an order picked uniformly in `symmetry_orders`.
+    pub symmetry_probability: f64,
+    pub symmetry_orders: RangeInclusive<u32>,
+    pub preview_dimensions: Dimensions,
+    /// Samples per pixel of the preview.
+    pub preview_quality: f64,
+    /// Fraction of the preview samples that must land in the frame.
+    pub min_in_frame: f64,
+    /// Bounds of the fraction of preview pixels reached.
+    pub min_coverage: f64,
+    pub max_coverage: f64,
+    pub max_attempts: usize,
+}
+
+impl Default for Generator
+{
+    fn default() -> Generator
+    {
+        Generator
+        {
+            transforms: 2..=5,
+            max_variations: 3,
+            determinant_weights: false,
+            gradient_probability: 0.5,
+            symmetry_probability: 0.2,
+            symmetry_orders: 2..=6,
+            preview_dimensions: Dimensions { width: 64, height: 64 },
+            preview_quality: 10.,
+            min_in_frame: 0.5,
+            min_coverage: 0.1,
+            max_coverage: 0.95,
+            max_attempts: 200,
+        }
+    }
+}
+
+impl Generator
+{
+    pub fn validate(&self) -> Result<(), String>
+    {
+        let probability = |p: f64| (0. ..=1.).contains(&p);
+
+        if self.transforms.is_empty() || *self.transforms.start() == 0
+        {
+            return Err(String::from("Random genomes need at least one transform"));
+        }
+
+        if self.max_variations == 0
+        {
+            return Err(String::from("Random transforms need at least one variation"));
+        }
+
+        if !probability(self.gradient_probability) || !probability(self.symmetry_probability)
+        {
+            return Err(String::from("Probabilities must be in [0, 1]"));
+        }
+
+        if self.symmetry_orders.is_empty() || Symmetry::Rotational(*self.symmetry_orders.end()).validate().is_err() || *self.symmetry_orders.start() < 2
+        {
+            return Err(String::from("Invalid range of symmetry orders"));
+        }
+
+        if self.preview_dimensions.area() == 0 || !(self.preview_quality.is_finite() && self.preview_quality > 0.)
+        {
+            return Err(String::from("The preview needs pixels and samples"));
+        }
+
+        if !probability(self.min_in_frame) || !probability(self.min_coverage) || !probability(self.max_coverage) || self.min_coverage > self.max_coverage
+        {
+            return Err(String::from("Acceptance thresholds must be fractions, with the minimum coverage below the maximum"));
+        }
+
+        if self.max_attempts == 0
+        {
+            return Err(String::from("The generator needs at least one attempt"));
+        }
+
+        Ok(())
+    }
+
+    /// First candidate passing `judge`, or an error once `max_attempts` were rejected.
+    pub fn generate<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<Genome, String>
+    {
+        self.validate()?;
+
+        for _ in 0..self.max_attempts
+        {
+            let genome = self.candidate(rng);
+
+            if self.judge(&genome, rng.gen()).is_ok()
+            {
+                return Ok(genome);
+            }
+        }
+
+        Err(format!("No interesting flame found in {} attempts", self.max_attempts))
+    }
+
+    /// A genome drawn from the distributions, without any check.
+    pub fn candidate<R: Rng + ?Sized>(&self, rng: &mut R) -> Genome
+    {
+        let count = rng.gen_range(self.transforms.clone());
+        let transforms = (0..count)
+            .map(|_|
+            {
+                let application = AugmentedMat2x2::rand(rng, -1., 1.);
+                let transform = FunctionElement
+                {
+                    variations: variations::random_blend(rng, self.max_variations),
+                    ..FunctionElement::new(application, rng.gen())
+                };
+
+                if self.determinant_weights
+                {
+                    FunctionElement { weight: FunctionElement::determinant_weight(&application), ..transform }
+                }
+                else
+                {
+                    transform
+                }
+            })
+            .collect();
+
+        let symmetry = match rng.gen_bool(self.symmetry_probability)
+        {
+            false => Symmetry::None,
+            true if rng.gen() => Symmetry::Rotational(rng.gen_range(self.symmetry_orders.clone())),
+            true => Symmetry::Dihedral(rng.gen_range(self.symmetry_orders.clone())),
+        };
+
+        Genome { transforms, symmetry, palette: self.palette(rng), ..Genome::default() }
+    }
+
+    fn palette<R: Rng + ?Sized>(&self, rng: &mut R) -> Palette
+    {
+        if rng.gen_bool(self.gradient_probability)
+        {
+            let count = rng.gen_range(2..=5);
+            let stops: Vec<(f64, Rgb)> = (0..count)
+                .map(|index| (index as f64 / (count - 1) as f64, Rgb::new(rng.gen(), rng.gen(), rng.gen())))
+                .collect();
+
+            return Palette::from_gradient(&stops).expect("stops are evenly spread over [0, 1]");
+        }
+
+        let name = Palette::builtin_names().choose(rng).expect("there are builtin palettes");
+
+        Palette::builtin(name).expect("builtin names have a palette")
+    }
+
+    /// Renders a quick preview of `genome`, with its own camera, and checks it looks like a flame.
+    pub fn judge(&self, genome: &Genome, seed: u64) -> Result<(), Rejection>
+    {
+        let transforms = genome.transforms
+            .iter()
+            .chain(genome.symmetry.transforms().iter())
+            .filter(|t| t.weight > 0.)
+            .cloned()
+            .collect::<Vec<_>>();
+        let total_weight: f64 = transforms.iter().map(|t| t.weight).sum();
+        let mean_log_determinant: f64 = transforms
+            .iter()
+            .map(|t| t.weight * t.application.determinant().abs().ln())
+            .sum::<f64>() / total_weight;
+
+        if mean_log_determinant.is_nan() || mean_log_determinant >= 0.
+        {
+            return Err(Rejection::Expanding);
+        }
+
+        let mut preview = Simulator::with_seed(self.preview_dimensions, PREVIEW_PREPARATION_ROUNDS, SuperSamplingFactor::None, seed);
+
+        // Only invalid genomes fail here, and they are not interesting either.
+        preview.set_genome(genome.clone()).map_err(|_| Rejection::Expanding)?;
+        preview.iterate(progress::samples_for_quality(self.preview_quality, &self.preview_dimensions));
+
+        let in_frame = preview.plotted_count() as f64 / preview.sample_count() as f64;
+        let coverage = preview.coverage();
+
+        if in_frame < self.min_in_frame
+        {
+            Err(Rejection::Sparse { in_frame })
+        }
+        else if coverage < self.min_coverage
+        {
+            Err(Rejection::Small { coverage })
+        }
+        else if coverage > self.max_coverage
+        {
+            Err(Rejection::Blob { coverage })
+        }
+        else
+        {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::SeedableRng;
+
+    use super::{ Generator, Rejection };
+    use super::super::genome::Genome;
+    use super::super::math::AugmentedMat2x2;
+    use super::super::simulator::{ FlameRng, FunctionElement };
+
+    fn genome(affines: &[[[f64; 3]; 2]]) -> Genome
+    {
+        let transforms = affines.iter().map(|a| FunctionElement::new(AugmentedMat2x2::new(*a), 0.)).collect();
+
+        Genome { transforms, ..Genome::default() }
+    }
+
+    #[test]
+    fn test_judge()
+    {
+        let generator = Generator::default();
+        assert_eq!(generator.judge(&Genome::sierpinski(), 1), Ok(()));
+        assert_eq!(generator.judge(&genome(&[[[2., 0., 0.], [0., 2., 0.]]]), 1), Err(Rejection::Expanding));
+
+        // Converges to the fixed point (0.2, 0.2).
+        assert!(matches!(generator.judge(&genome(&[[[0.5, 0., 0.1], [0., 0.5, 0.1]]]), 1), Err(Rejection::Small { .. })));
+
+        // The same triangle, far out of the frame.
+        let far = genome(&[[[0.5, 0., 4.5], [0., 0.5, -0.5]], [[0.5, 0., 5.5], [0., 0.5, -0.5]], [[0.5, 0., 5.], [0., 0.5, 0.5]]]);
+
+        assert!(matches!(generator.judge(&far, 1), Err(Rejection::Sparse { .. })));
+    }
+
+    #[test]
+    fn test_generate()
+    {
+        let generator = Generator { transforms: 3..=4, ..Generator::default() };
+        let first = generator.generate(&mut FlameRng::seed_from_u64(5)).unwrap();
+
+        assert!(first.validate().is_ok());
+        assert!((3..=4).contains(&first.transforms.len()));
+        assert!(first.transforms.iter().all(|t| (1..=3).contains(&t.variations.len())));
+        assert_eq!(generator.generate(&mut FlameRng::seed_from_u64(5)).unwrap(), first);
+        assert!(first.transforms.iter().all(|t| t.weight == 1.));
+
+        let weighted = Generator { determinant_weights: true, ..generator }.candidate(&mut FlameRng::seed_from_u64(5));
+
+        assert!(weighted.transforms.iter().all(|t| t.weight == FunctionElement::determinant_weight(&t.application)));
+    }
+
+    #[test]
+    fn test_validate()
+    {
+        assert!(Generator::default().validate().is_ok());
+        assert!(Generator { transforms: 0..=3, ..Generator::default() }.validate().is_err());
+        assert!(Generator { gradient_probability: 1.5, ..Generator::default() }.validate().is_err());
+        assert!(Generator { min_coverage: 0.5, max_coverage: 0.4, ..Generator::default() }.validate().is_err());
+        assert!(Generator { max_attempts: 0, ..Generator::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,814 @@
+use std::fs;
+use std::path::Path;
+
+use quick_xml::escape::escape;
+use quick_xml::events::{ BytesStart, Event };
+use quick_xml::Reader;
+use serde::{ Deserialize, Serialize };
+
+use super::camera::Camera;
+use super::color::Rgb;
+use super::density::DensityEstimation;
+use super::filter::{ FilterKind, ReconstructionFilter };
+use super::image::Dimensions;
+use super::math::{ AugmentedMat2x2, Vec2D };
+use super::palette::{ self, Palette, PALETTE_SIZE };
+use super::sampling::AliasTable;
+use super::simulator::FunctionElement;
+use super::symmetry::Symmetry;
+use super::tonemap::ToneMapping;
+use super::variations::{ Variation, WeightedVariation };
+
+const IDENTITY: AugmentedMat2x2 = AugmentedMat2x2::IDENTITY;
+/// flam3 describes the view in pixels for a given frame size, while our camera does not depend on it.
+const FLAM3_SIZE: Dimensions = Dimensions { width: 512, height: 512 };
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GenomeFormat
+{
+    Json,
+    Flam3,
+}
+
+impl GenomeFormat
+{
+    pub fn from_path(path: &Path) -> Result<GenomeFormat, String>
+    {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref()
+        {
+            Some("json") => Ok(GenomeFormat::Json),
+            Some("flame") | Some("xml") => Ok(GenomeFormat::Flam3),
+            _ => Err(format!("Unsupported genome format for '{}' (expected .json, .flame or .xml)", path.display())),
+        }
+    }
+}
+
+/// Everything needed to reproduce a flame.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Genome
+{
+    #[serde(default)]
+    pub name: String,
+    pub transforms: Vec<FunctionElement>,
+    /// Applied to every point before plotting, without feeding back into the walk.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_transform: Option<FunctionElement>,
+    #[serde(default)]
+    pub symmetry: Symmetry,
+    #[serde(default)]
+    pub camera: Camera,
+    #[serde(default)]
+    pub palette: Palette,
+    #[serde(default)]
+    pub tone_mapping: ToneMapping,
+    #[serde(default)]
+    pub density_estimation: DensityEstimation,
+    #[serde(default)]
+    pub reconstruction_filter: ReconstructionFilter,
+}
+
+impl Genome
+{
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if self.transforms.is_empty()
+        {
+            return Err(String::from("A genome needs at least one transform"));
+        }
+
+        let in_unit_range = |v: f64| (0. ..=1.).contains(&v);
+
+        if let Some(t) = self.transforms.iter().chain(self.final_transform.iter()).find(|t| !in_unit_range(t.color) || !in_unit_range(t.color_speed))
+        {
+            return Err(format!("Invalid color {} with speed {}, both must be in [0, 1]", t.color, t.color_speed));
+        }
+
+        let weights: Vec<f64> = self.transforms.iter().map(|t| t.weight).collect();
+
+        AliasTable::new(&weights)?;
+        self.symmetry.validate()?;
+        self.tone_mapping.validate()?;
+        self.density_estimation.validate()?;
+        self.reconstruction_filter.validate()
+    }
+
+    /// Replaces every transform weight by the absolute determinant of its affine part.
+    pub fn use_determinant_weights(&mut self)
+    {
+        for transform in self.transforms.iter_mut()
+        {
+            transform.weight = FunctionElement::determinant_weight(&transform.application);
+        }
+    }
+
+    /// The Sierpinski triangle spanning [-0.5, 0.5]², with colors spread over the palette.
+    #[cfg(test)]
+    pub(crate) fn sierpinski() -> Genome
+    {
+        let transforms = [[[0.5, 0., -0.5], [0., 0.5, -0.5]], [[0.5, 0., 0.5], [0., 0.5, -0.5]], [[0.5, 0., 0.], [0., 0.5, 0.5]]]
+            .iter()
+            .enumerate()
+            .map(|(index, a)| FunctionElement::new(AugmentedMat2x2::new(*a), index as f64 / 2.))
+            .collect();
+
+        Genome { transforms, ..Genome::default() }
+    }
+
+    pub fn load(path: &Path) -> Result<Genome, String>
+    {
+        let format = GenomeFormat::from_path(path)?;
+        let content = fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+
+        match format
+        {
+            GenomeFormat::Json => Genome::from_json(&content),
+            GenomeFormat::Flam3 => Genome::from_flam3_xml(&content),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String>
+    {
+        let content = match GenomeFormat::from_path(path)?
+        {
+            GenomeFormat::Json => self.to_json()?,
+            GenomeFormat::Flam3 => self.to_flam3_xml(),
+        };
+
+        fs::write(path, content).map_err(|e| format!("Cannot write '{}': {}", path.display(), e))
+    }
+
+    // -- NATIVE --
+
+    pub fn from_json(content: &str) -> Result<Genome, String>
+    {
+        let genome: Genome = serde_json::from_str(content).map_err(|e| e.to_string())?;
+
+        genome.validate()?;
+        Ok(genome)
+    }
+
+    pub fn to_json(&self) -> Result<String, String>
+    {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+
+    // -- FLAM3 --
+
+    /// Reads the first `<flame>` of a flam3/Apophysis file. The palette is given either by `<color>`
+    /// entries or by a hex `<palette>` block; variations we do not know about are ignored,
+    /// and filter shapes we do not know about fall back to Gaussian.
+    pub fn from_flam3_xml(content: &str) -> Result<Genome, String>
+    {
+        let mut reader = Reader::from_str(content);
+        reader.trim_text(true);
+
+        let mut genome = Genome::default();
+        let mut palette: Vec<Option<Rgb>> = vec![None; PALETTE_SIZE];
+        let mut in_flame = false;
+        let mut in_palette = false;
+        let mut found_flame = false;
+
+        loop
+        {
+            match reader.read_event().map_err(|e| e.to_string())?
+            {
+                Event::Start(e) if !found_flame && e.name().as_ref() == b"flame" =>
+                {
+                    in_flame = true;
+                    found_flame = true;
+                    parse_flame(&e, &mut genome)?;
+                },
+                // An empty first flame has no content, the flames after it must not be merged into it.
+                Event::Empty(e) if !found_flame && e.name().as_ref() == b"flame" =>
+                {
+                    found_flame = true;
+                    parse_flame(&e, &mut genome)?;
+                    break;
+                },
+                Event::Start(e) | Event::Empty(e) if in_flame =>
+                {
+                    match e.name().as_ref()
+                    {
+                        b"xform" =>
+                        {
+                            genome.transforms.push(parse_xform(&e)?);
+                        },
+                        b"finalxform" => genome.final_transform = Some(parse_xform(&e)?),
+                        b"color" => parse_color_entry(&e, &mut palette)?,
+                        b"palette" => in_palette = true,
+                        _ => {},
+                    }
+                },
+                Event::Text(text) if in_palette =>
+                {
+                    parse_hex_palette(&text.unescape().map_err(|e| e.to_string())?, &mut palette)?;
+                },
+                Event::End(e) if in_flame =>
+                {
+                    match e.name().as_ref()
+                    {
+                        b"flame" => break,
+                        b"palette" => in_palette = false,
+                        _ => {},
+                    }
+                },
+                Event::Eof => break,
+                _ => {},
+            }
+        }
+
+        if !found_flame
+        {
+            return Err(String::from("No <flame> element found"));
+        }
+
+        // Entries missing from a partial palette fall back to a grey ramp.
+        if palette.iter().any(Option::is_some)
+        {
+            let colors: Vec<Rgb> = palette
+                .iter()
+                .enumerate()
+                .map(|(index, color)| color.unwrap_or(Rgb::new(index as u8, index as u8, index as u8)))
+                .collect();
+
+            genome.palette = Palette::from_colors(&colors)?;
+        }
+
+        genome.validate()?;
+        Ok(genome)
+    }
+
+    /// Writes a flam3 compatible `<flame>`.
+    pub fn to_flam3_xml(&self) -> String
+    {
+        let mut xml = format!
+        (
+            "<flame name=\"{}\" version=\"rustyflame\" size=\"{} {}\" center=\"{} {}\" scale=\"{}\" rotate=\"{}\"{}{}{}>\n",
+            escape(&self.name),
+            FLAM3_SIZE.width,
+            FLAM3_SIZE.height,
+            self.camera.center.x,
+            self.camera.center.y,
+            self.camera.pixels_per_unit(&FLAM3_SIZE),
+            self.camera.rotation.to_degrees(),
+            format_tone_mapping(&self.tone_mapping),
+            format_density_estimation(&self.density_estimation),
+            format_reconstruction_filter(&self.reconstruction_filter),
+        );
+
+        // flam3 has no symmetry setting, it stores the transforms it generates.
+        for transform in self.transforms.iter().chain(self.symmetry.transforms().iter())
+        {
+            xml.push_str(&format_xform("xform", transform));
+        }
+
+        if let Some(final_transform) = &self.final_transform
+        {
+            xml.push_str(&format_xform("finalxform", final_transform));
+        }
+
+        for (index, color) in self.palette.colors().iter().enumerate()
+        {
+            xml.push_str(&format!("   <color index=\"{}\" rgb=\"{} {} {}\"/>\n", index, color.r, color.g, color.b));
+        }
+
+        xml.push_str("</flame>\n");
+        xml
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, String>
+{
+    element
+        .try_get_attribute(name)
+        .map_err(|e| e.to_string())?
+        .map(|a| a.unescape_value().map(|v| v.into_owned()).map_err(|e| e.to_string()))
+        .transpose()
+}
+
+fn parse_float(value: &str, what: &str) -> Result<f64, String>
+{
+    value.trim().parse().map_err(|_| format!("Invalid {} '{}'", what, value))
+}
+
+fn parse_floats(value: &str) -> Result<Vec<f64>, String>
+{
+    value
+        .split_whitespace()
+        .map(|v| v.parse::<f64>().map_err(|_| format!("Invalid number '{}'", v)))
+        .collect()
+}
+
+// flam3 stores the affine map column-wise as "a d b e c f", for x' = ax + by + c and y' = dx + ey + f.
+fn parse_coefs(value: &str) -> Result<AugmentedMat2x2, String>
+{
+    match parse_floats(value)?[..]
+    {
+        [a, d, b, e, c, f] => Ok(AugmentedMat2x2::new([[a, b, c], [d, e, f]])),
+        _ => Err(format!("Expected 6 coefficients, got '{}'", value)),
+    }
+}
+
+fn format_coefs(mat: &AugmentedMat2x2) -> String
+{
+    let [[a, b, c], [d, e, f]] = mat.coeffs();
+
+    format!("{} {} {} {} {} {}", a, d, b, e, c, f)
+}
+
+/// Reads the attributes of a `<flame>` element, its children are read separately.
+fn parse_flame(element: &BytesStart, genome: &mut Genome) -> Result<(), String>
+{
+    genome.name = attribute(element, "name")?.unwrap_or_default();
+    genome.camera = parse_camera(element)?;
+    genome.tone_mapping = parse_tone_mapping(element)?;
+    genome.density_estimation = parse_density_estimation(element)?;
+    genome.reconstruction_filter = parse_reconstruction_filter(element)?;
+
+    Ok(())
+}
+
+/// `scale` is in pixels per unit for the frame `size`, further multiplied by 2^`zoom`; `rotate` is in degrees.
+fn parse_camera(element: &BytesStart) -> Result<Camera, String>
+{
+    let mut camera = Camera::default();
+    let size = match attribute(element, "size")?
+    {
+        Some(size) => match parse_floats(&size)?[..]
+        {
+            [width, height] if width >= 1. && height >= 1. => Dimensions { width: width as u32, height: height as u32 },
+            _ => return Err(format!("Invalid flame size '{}'", size)),
+        },
+        None => FLAM3_SIZE,
+    };
+
+    if let Some(center) = attribute(element, "center")?
+    {
+        camera.center = match parse_floats(&center)?[..]
+        {
+            [x, y] => Vec2D { x, y },
+            _ => return Err(format!("Invalid flame center '{}'", center)),
+        };
+    }
+
+    if let Some(scale) = attribute(element, "scale")?
+    {
+        let scale: f64 = scale.trim().parse().map_err(|_| format!("Invalid flame scale '{}'", scale))?;
+
+        camera.zoom = scale / Camera::default().pixels_per_unit(&size);
+    }
+
+    if let Some(zoom) = attribute(element, "zoom")?
+    {
+        let zoom: f64 = zoom.trim().parse().map_err(|_| format!("Invalid flame zoom '{}'", zoom))?;
+
+        camera.zoom_by(zoom.exp2());
+    }
+
+    if let Some(rotate) = attribute(element, "rotate")?
+    {
+        let rotate: f64 = rotate.trim().parse().map_err(|_| format!("Invalid flame rotation '{}'", rotate))?;
+
+        camera.rotation = rotate.to_radians();
+    }
+
+    if !(camera.zoom.is_finite() && camera.zoom > 0.)
+    {
+        return Err(format!("Invalid flame zoom {}", camera.zoom));
+    }
+
+    Ok(camera)
+}
+
+/// flam3 brightness is relative to the sample density rather than to the densest cell, so it is left out.
+fn parse_tone_mapping(element: &BytesStart) -> Result<ToneMapping, String>
+{
+    let mut tone_mapping = ToneMapping::default();
+    let fields: [(&str, &mut f64); 4] =
+    [
+        ("gamma", &mut tone_mapping.gamma),
+        ("gamma_threshold", &mut tone_mapping.gamma_threshold),
+        ("vibrancy", &mut tone_mapping.vibrancy),
+        ("highlight_power", &mut tone_mapping.highlight_power),
+    ];
+
+    for (name, field) in fields
+    {
+        if let Some(value) = attribute(element, name)?
+        {
+            *field = parse_float(&value, name)?;
+        }
+    }
+
+    if let Some(background) = attribute(element, "background")?
+    {
+        let channel = |c: f64| (c.clamp(0., 1.) * 255.).round() as u8;
+
+        tone_mapping.background = match parse_floats(&background)?[..]
+        {
+            [r, g, b] => Some(Rgb::new(channel(r), channel(g), channel(b))),
+            _ => return Err(format!("Invalid flame background '{}'", background)),
+        };
+    }
+
+    Ok(tone_mapping)
+}
+
+fn format_tone_mapping(tone_mapping: &ToneMapping) -> String
+{
+    let mut attributes = format!
+    (
+        " gamma=\"{}\" gamma_threshold=\"{}\" vibrancy=\"{}\" highlight_power=\"{}\"",
+        tone_mapping.gamma, tone_mapping.gamma_threshold, tone_mapping.vibrancy, tone_mapping.highlight_power
+    );
+
+    if let Some(bg) = tone_mapping.background
+    {
+        attributes.push_str(&format!(" background=\"{} {} {}\"", bg.r as f64 / 255., bg.g as f64 / 255., bg.b as f64 / 255.));
+    }
+
+    attributes
+}
+
+fn parse_density_estimation(element: &BytesStart) -> Result<DensityEstimation, String>
+{
+    let mut density_estimation = DensityEstimation::default();
+    let fields: [(&str, &mut f64); 3] =
+    [
+        ("estimator_radius", &mut density_estimation.max_radius),
+        ("estimator_minimum", &mut density_estimation.min_radius),
+        ("estimator_curve", &mut density_estimation.curve),
+    ];
+
+    for (name, field) in fields
+    {
+        if let Some(value) = attribute(element, name)?
+        {
+            *field = parse_float(&value, name)?;
+        }
+    }
+
+    Ok(density_estimation)
+}
+
+fn format_density_estimation(density_estimation: &DensityEstimation) -> String
+{
+    format!
+    (
+        " estimator_radius=\"{}\" estimator_minimum=\"{}\" estimator_curve=\"{}\"",
+        density_estimation.max_radius, density_estimation.min_radius, density_estimation.curve
+    )
+}
+
+/// flam3 knows more filter shapes, and Lanczos comes in two sizes there.
+fn parse_reconstruction_filter(element: &BytesStart) -> Result<ReconstructionFilter, String>
+{
+    let mut filter = ReconstructionFilter::default();
+
+    if let Some(radius) = attribute(element, "filter")?
+    {
+        filter.radius = parse_float(&radius, "filter")?;
+    }
+
+    if let Some(shape) = attribute(element, "filter_shape")?
+    {
+        filter.kind = match &shape[..]
+        {
+            "lanczos2" | "lanczos3" => FilterKind::Lanczos,
+            // Like unknown variations, shapes we do not implement are not fatal: they get flam3's default.
+            name => FilterKind::from_name(name).unwrap_or(FilterKind::Gaussian),
+        };
+    }
+
+    Ok(filter)
+}
+
+fn format_reconstruction_filter(filter: &ReconstructionFilter) -> String
+{
+    let shape = match filter.kind
+    {
+        FilterKind::Lanczos => "lanczos3",
+        kind => kind.name(),
+    };
+
+    format!(" filter=\"{}\" filter_shape=\"{}\"", filter.radius, shape)
+}
+
+fn parse_xform(element: &BytesStart) -> Result<FunctionElement, String>
+{
+    let mut transform = FunctionElement::new(IDENTITY, 0.);
+
+    transform.variations.clear();
+
+    for attr in element.attributes()
+    {
+        let attr = attr.map_err(|e| e.to_string())?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+        let value = attr.unescape_value().map_err(|e| e.to_string())?;
+
+        match &key[..]
+        {
+            "coefs" => transform.application = parse_coefs(&value)?,
+            "weight" => transform.weight = parse_float(&value, "xform weight")?,
+            "post" =>
+            {
+                let post_transform = parse_coefs(&value)?;
+
+                if post_transform != IDENTITY
+                {
+                    transform.post_transform = Some(post_transform);
+                }
+            },
+            // Older files store two color coordinates, only the first one is meaningful.
+            "color" => transform.color = parse_floats(&value)?.first().ok_or("Empty xform color")?.clamp(0., 1.),
+            "color_speed" => transform.color_speed = parse_float(&value, "color speed")?,
+            // Older files give the color speed as a symmetry in [-1, 1], 1 meaning the color never changes.
+            "symmetry" => transform.color_speed = (1. - parse_float(&value, "symmetry")?) / 2.,
+            _ =>
+            {
+                if let Some(variation) = Variation::from_name(&key)
+                {
+                    let weight = value.trim().parse::<f64>().map_err(|_| format!("Invalid weight for {}: '{}'", key, value))?;
+
+                    if weight != 0.
+                    {
+                        transform.variations.push(WeightedVariation { variation, weight });
+                    }
+                }
+            },
+        }
+    }
+
+    Ok(transform)
+}
+
+fn format_xform(tag: &str, transform: &FunctionElement) -> String
+{
+    let mut xml = format!
+    (
+        "   <{} weight=\"{}\" color=\"{}\" color_speed=\"{}\"",
+        tag, transform.weight, transform.color, transform.color_speed
+    );
+
+    let variations = if transform.variations.is_empty()
+    {
+        vec![WeightedVariation { variation: Variation::Linear, weight: 1. }]
+    }
+    else
+    {
+        transform.variations.clone()
+    };
+
+    for v in variations.iter()
+    {
+        xml.push_str(&format!(" {}=\"{}\"", v.variation.name(), v.weight));
+    }
+
+    xml.push_str(&format!(" coefs=\"{}\"", format_coefs(&transform.application)));
+
+    if let Some(post_transform) = transform.post_transform
+    {
+        xml.push_str(&format!(" post=\"{}\"", format_coefs(&post_transform)));
+    }
+
+    xml.push_str("/>\n");
+    xml
+}
+
+fn parse_rgb(value: &str) -> Result<Rgb, String>
+{
+    match parse_floats(value)?[..]
+    {
+        [r, g, b] => Ok(Rgb::new(r.round() as u8, g.round() as u8, b.round() as u8)),
+        _ => Err(format!("Expected 3 color components, got '{}'", value)),
+    }
+}
+
+fn parse_color_entry(element: &BytesStart, palette: &mut [Option<Rgb>]) -> Result<(), String>
+{
+    let index = attribute(element, "index")?.ok_or("Missing palette color index")?;
+    let index: usize = index.trim().parse().map_err(|_| format!("Invalid palette index '{}'", index))?;
+    let rgb = attribute(element, "rgb")?.ok_or("Missing palette color rgb")?;
+
+    if index >= PALETTE_SIZE
+    {
+        return Err(format!("Palette index {} out of range", index));
+    }
+
+    palette[index] = Some(parse_rgb(&rgb)?);
+    Ok(())
+}
+
+fn parse_hex_palette(text: &str, palette: &mut [Option<Rgb>]) -> Result<(), String>
+{
+    for (entry, color) in palette.iter_mut().zip(palette::parse_hex_colors(text)?)
+    {
+        *entry = Some(color);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::path::Path;
+
+    use super::{ Genome, GenomeFormat };
+    use super::super::camera::Camera;
+    use super::super::color::Rgb;
+    use super::super::density::DensityEstimation;
+    use super::super::filter::{ FilterKind, ReconstructionFilter };
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+    use super::super::palette::Palette;
+    use super::super::simulator::FunctionElement;
+    use super::super::symmetry::Symmetry;
+    use super::super::tonemap::ToneMapping;
+    use super::super::variations::{ Variation, WeightedVariation };
+
+    fn test_genome() -> Genome
+    {
+        let mut swirl = FunctionElement::new(AugmentedMat2x2::new([[0.5, -0.25, 0.125], [0.3, 0.7, -0.1]]), 0.5);
+        swirl.variations = vec!
+        [
+            WeightedVariation { variation: Variation::Swirl, weight: 0.75 },
+            WeightedVariation { variation: Variation::Julia, weight: 0.25 },
+        ];
+        swirl.post_transform = Some(AugmentedMat2x2::new([[1., 0., 0.5], [0., 1., 0.]]));
+        swirl.weight = 2.5;
+        swirl.color_speed = 0.125;
+
+        Genome
+        {
+            name: String::from("test <&> flame"),
+            transforms: vec!
+            [
+                FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., -0.5], [0., 0.5, -0.5]]), 0.),
+                swirl,
+                FunctionElement::new(AugmentedMat2x2::new([[0.5, 0., 0.], [0., 0.5, 0.5]]), 0.9),
+            ],
+            final_transform: Some(FunctionElement::new(AugmentedMat2x2::new([[0.9, 0.1, 0.], [-0.1, 0.9, 0.]]), 0.75)),
+            symmetry: Symmetry::None,
+            camera: Camera { center: Vec2D { x: 0.25, y: -0.5 }, zoom: 1.5, rotation: 0. },
+            palette: Palette::builtin("ocean").unwrap(),
+            tone_mapping: ToneMapping { gamma: 4., vibrancy: 0.5, highlight_power: 1., background: Some(Rgb::new(0, 51, 255)), ..ToneMapping::default() },
+            density_estimation: DensityEstimation { min_radius: 0.5, max_radius: 6., curve: 0.6 },
+            reconstruction_filter: ReconstructionFilter { kind: FilterKind::Lanczos, radius: 1.5 },
+        }
+    }
+
+    #[test]
+    fn test_format_from_path()
+    {
+        assert_eq!(GenomeFormat::from_path(Path::new("a.json")), Ok(GenomeFormat::Json));
+        assert_eq!(GenomeFormat::from_path(Path::new("a.flame")), Ok(GenomeFormat::Flam3));
+        assert_eq!(GenomeFormat::from_path(Path::new("a.XML")), Ok(GenomeFormat::Flam3));
+        assert!(GenomeFormat::from_path(Path::new("a.toml")).is_err());
+    }
+
+    #[test]
+    fn test_json_round_trip()
+    {
+        let genome = test_genome();
+
+        assert_eq!(Genome::from_json(&genome.to_json().unwrap()).unwrap(), genome);
+    }
+
+    #[test]
+    fn test_json_defaults_to_linear()
+    {
+        let genome = Genome::from_json(r#"{ "transforms": [ { "affine": [[1, 0, 0], [0, 1, 0]], "color": 0.25 } ] }"#).unwrap();
+
+        assert_eq!(genome.transforms[0].variations, vec![]);
+        assert_eq!(genome.transforms[0].post_transform, None);
+        assert_eq!(genome.transforms[0].weight, 1.);
+        assert_eq!(genome.transforms[0].color_speed, 0.5);
+        assert_eq!(genome.palette, Palette::default());
+        assert!(Genome::from_json(r#"{ "transforms": [] }"#).is_err());
+    }
+
+    #[test]
+    fn test_weights_validation()
+    {
+        let mut genome = test_genome();
+
+        genome.transforms[0].color = 1.5;
+        assert!(genome.validate().is_err());
+        genome.transforms[0].color = 0.;
+        genome.transforms[2].color_speed = -0.1;
+        assert!(genome.validate().is_err());
+        genome.transforms[2].color_speed = 0.5;
+
+        genome.transforms[1].weight = -1.;
+        assert!(genome.validate().is_err());
+
+        genome.use_determinant_weights();
+        assert!(genome.validate().is_ok());
+        assert_eq!(genome.transforms[0].weight, 0.25);
+        assert!((genome.transforms[1].weight - 0.425).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_flam3_round_trip()
+    {
+        let genome = test_genome();
+
+        assert_eq!(Genome::from_flam3_xml(&genome.to_flam3_xml()).unwrap(), genome);
+    }
+
+    #[test]
+    fn test_flam3_expands_symmetry()
+    {
+        let mut genome = test_genome();
+
+        genome.symmetry = Symmetry::Dihedral(3);
+
+        let parsed = Genome::from_flam3_xml(&genome.to_flam3_xml()).unwrap();
+
+        assert_eq!(parsed.symmetry, Symmetry::None);
+        assert_eq!(parsed.transforms.len(), 3 + 1 + 2);
+        assert_eq!(parsed.transforms[3..], Symmetry::Dihedral(3).transforms()[..]);
+        assert_eq!(parsed.final_transform, genome.final_transform);
+
+        genome.symmetry = Symmetry::Rotational(0);
+        assert!(genome.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_apophysis_flame()
+    {
+        let mut palette = String::new();
+
+        for i in 0..256
+        {
+            palette.push_str(&format!("{:02X}{:02X}{:02X}", i, 255 - i, 7));
+        }
+
+        let xml = format!(r#"<flames>
+            <flame name="apo" version="Apophysis 2.09" size="640 480" center="0.5 -1" scale="120" zoom="1" rotate="90" filter="0.8" filter_shape="lanczos2">
+                <xform weight="0.5" color="1" spherical="0.5" linear="0.5" julian_power="2" coefs="1 2 3 4 5 6" post="1 0 0 1 0 0" />
+                <xform weight="0.5" color="0 0" symmetry="0.5" bogus="1" coefs="-1 0 0 -1 0.5 0.25" />
+                <palette count="256" format="RGB">
+                    {}
+                </palette>
+            </flame>
+            <flame name="ignored"><xform coefs="1 0 0 1 0 0"/></flame>
+        </flames>"#, palette);
+
+        let genome = Genome::from_flam3_xml(&xml).unwrap();
+
+        assert_eq!(genome.name, "apo");
+        assert_eq!(genome.transforms.len(), 2);
+        assert_eq!(genome.camera.center, Vec2D { x: 0.5, y: -1. });
+        assert_eq!(genome.camera.zoom, 1.);
+        assert!((genome.camera.rotation - std::f64::consts::FRAC_PI_2).abs() < 1e-12);
+        assert_eq!(genome.reconstruction_filter, ReconstructionFilter { kind: FilterKind::Lanczos, radius: 0.8 });
+
+        let first = &genome.transforms[0];
+
+        assert_eq!(first.application, AugmentedMat2x2::new([[1., 3., 5.], [2., 4., 6.]]));
+        assert_eq!(first.post_transform, None);
+        assert_eq!(first.weight, 0.5);
+        assert_eq!(first.color, 1.);
+        assert_eq!(genome.palette.color(first.color), Rgb::new(255, 0, 7));
+        assert_eq!(first.variations, vec!
+        [
+            WeightedVariation { variation: Variation::Spherical, weight: 0.5 },
+            WeightedVariation { variation: Variation::Linear, weight: 0.5 },
+        ]);
+
+        let second = &genome.transforms[1];
+
+        assert_eq!(second.application, AugmentedMat2x2::new([[-1., 0., 0.5], [0., -1., 0.25]]));
+        assert_eq!(second.color, 0.);
+        assert_eq!(second.color_speed, 0.25);
+        assert_eq!(genome.palette.color(second.color), Rgb::new(0, 255, 7));
+        assert!(second.variations.is_empty());
+    }
+
+    #[test]
+    fn test_parse_unknown_filter_shape()
+    {
+        let genome = Genome::from_flam3_xml(r#"<flame filter="0.4" filter_shape="hermite"><xform coefs="1 0 0 1 0 0"/></flame>"#).unwrap();
+
+        assert_eq!(genome.reconstruction_filter, ReconstructionFilter { kind: FilterKind::Gaussian, radius: 0.4 });
+    }
+
+    #[test]
+    fn test_parse_invalid_flame()
+    {
+        assert!(Genome::from_flam3_xml("<flames></flames>").is_err());
+        assert!(Genome::from_flam3_xml("<flame></flame>").is_err());
+        assert!(Genome::from_flam3_xml(r#"<flame><xform coefs="1 2 3"/></flame>"#).is_err());
+        assert!(Genome::from_flam3_xml(r#"<flame><xform weight="0" coefs="1 0 0 1 0 0"/></flame>"#).is_err());
+        assert!(Genome::from_flam3_xml(r#"<flame scale="0"><xform coefs="1 0 0 1 0 0"/></flame>"#).is_err());
+        assert!(Genome::from_flam3_xml(r#"<flame size="640"><xform coefs="1 0 0 1 0 0"/></flame>"#).is_err());
+        assert!(Genome::from_flam3_xml(r#"<flame filter="-1"><xform coefs="1 0 0 1 0 0"/></flame>"#).is_err());
+        assert!(Genome::from_flam3_xml(r#"<flames><flame name="a"/><flame name="b"><xform coefs="1 0 0 1 0 0"/></flame></flames>"#).is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use serde::{ Deserialize, Serialize };
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dimensions
+{
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Dimensions
+{
+    pub fn area(&self) -> usize
+    {
+        self.width as usize * self.height as usize
+    }
+}
+
+pub const RGBA_CHANNELS: usize = 4;
+
+/// In-memory RGBA8 image, row major, with straight (non premultiplied) alpha.
+#[derive(Clone, Debug)]
+pub struct RgbaImage
+{
+    dimensions: Dimensions,
+    pixels: Vec<u8>,
+}
+
+impl RgbaImage
+{
+    pub fn new(dimensions: Dimensions) -> RgbaImage
+    {
+        RgbaImage
+        {
+            dimensions,
+            pixels: vec![0; dimensions.area() * RGBA_CHANNELS],
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimensions
+    {
+        self.dimensions
+    }
+
+    pub fn pitch(&self) -> usize
+    {
+        self.dimensions.width as usize * RGBA_CHANNELS
+    }
+
+    pub fn pixels(&self) -> &[u8]
+    {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u8]
+    {
+        &mut self.pixels
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; RGBA_CHANNELS]
+    {
+        let offset = self.offset(x, y);
+        let mut pixel = [0; RGBA_CHANNELS];
+
+        pixel.copy_from_slice(&self.pixels[offset..offset + RGBA_CHANNELS]);
+        pixel
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: [u8; RGBA_CHANNELS])
+    {
+        let offset = self.offset(x, y);
+
+        self.pixels[offset..offset + RGBA_CHANNELS].copy_from_slice(&pixel);
+    }
+
+    /// Fills the image with `source` stretched over it, nearest neighbour.
+    pub fn copy_scaled_from(&mut self, source: &RgbaImage)
+    {
+        let (width, height) = (self.dimensions.width, self.dimensions.height);
+        let source_dimensions = source.dimensions();
+
+        for y in 0..height
+        {
+            let source_y = (y as u64 * source_dimensions.height as u64 / height as u64) as u32;
+
+            for x in 0..width
+            {
+                let source_x = (x as u64 * source_dimensions.width as u64 / width as u64) as u32;
+
+                self.set_pixel(x, y, source.get_pixel(source_x, source_y));
+            }
+        }
+    }
+
+    /// Copies `source` with its top left corner at (`x`, `y`), clipped to the image.
+    pub fn paste(&mut self, source: &RgbaImage, x: u32, y: u32)
+    {
+        let source_dimensions = source.dimensions();
+        let width = source_dimensions.width.min(self.dimensions.width.saturating_sub(x));
+        let height = source_dimensions.height.min(self.dimensions.height.saturating_sub(y));
+
+        if width == 0
+        {
+            return;
+        }
+
+        for row in 0..height
+        {
+            let from = source.offset(0, row);
+            let to = self.offset(x, y + row);
+            let length = width as usize * RGBA_CHANNELS;
+
+            self.pixels[to..to + length].copy_from_slice(&source.pixels[from..from + length]);
+        }
+    }
+
+    fn offset(&self, x: u32, y: u32) -> usize
+    {
+        assert!(x < self.dimensions.width && y < self.dimensions.height, "pixel ({}, {}) out of bounds", x, y);
+
+        (y as usize * self.dimensions.width as usize + x as usize) * RGBA_CHANNELS
+    }
+}
+
+/// Premultiplied linear RGB and coverage, before tone mapping.
+pub type LinearPixel = [f64; RGBA_CHANNELS];
+
+/// Floating point image, row major, holding light as accumulated by the chaos game.
+#[derive(Clone, Debug)]
+pub struct LinearImage
+{
+    dimensions: Dimensions,
+    pixels: Vec<LinearPixel>,
+}
+
+impl LinearImage
+{
+    pub fn new(dimensions: Dimensions) -> LinearImage
+    {
+        LinearImage
+        {
+            dimensions,
+            pixels: vec![[0.; RGBA_CHANNELS]; dimensions.area()],
+        }
+    }
+
+    pub fn dimensions(&self) -> Dimensions
+    {
+        self.dimensions
+    }
+
+    pub fn pixels(&self) -> &[LinearPixel]
+    {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [LinearPixel]
+    {
+        &mut self.pixels
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ Dimensions, RgbaImage };
+
+    #[test]
+    fn test_new_image_is_transparent()
+    {
+        let image = RgbaImage::new(Dimensions { width: 3, height: 2 });
+
+        assert_eq!(image.pixels().len(), 3 * 2 * 4);
+        assert_eq!(image.pitch(), 12);
+        assert!(image.pixels().iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn test_set_get_pixel()
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 3, height: 2 });
+
+        image.set_pixel(2, 1, [1, 2, 3, 4]);
+
+        assert_eq!(image.get_pixel(2, 1), [1, 2, 3, 4]);
+        assert_eq!(&image.pixels()[20..24], &[1, 2, 3, 4]);
+        assert_eq!(image.get_pixel(1, 1), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_copy_scaled_from()
+    {
+        let mut small = RgbaImage::new(Dimensions { width: 2, height: 1 });
+        let mut large = RgbaImage::new(Dimensions { width: 4, height: 3 });
+
+        small.set_pixel(1, 0, [9, 9, 9, 9]);
+        large.copy_scaled_from(&small);
+
+        assert_eq!(large.get_pixel(1, 2), [0, 0, 0, 0]);
+        assert_eq!(large.get_pixel(2, 0), [9, 9, 9, 9]);
+        assert_eq!(large.get_pixel(3, 2), [9, 9, 9, 9]);
+    }
+
+    #[test]
+    fn test_paste_is_clipped()
+    {
+        let mut small = RgbaImage::new(Dimensions { width: 2, height: 2 });
+        let mut large = RgbaImage::new(Dimensions { width: 3, height: 3 });
+
+        small.pixels_mut().fill(7);
+        large.paste(&small, 2, 1);
+
+        assert_eq!(large.get_pixel(2, 1), [7, 7, 7, 7]);
+        assert_eq!(large.get_pixel(2, 2), [7, 7, 7, 7]);
+        assert_eq!(large.get_pixel(1, 1), [0, 0, 0, 0]);
+        assert_eq!(large.pixels().iter().filter(|c| **c == 7).count(), 2 * 4);
+
+        large.paste(&small, 3, 0);
+        assert_eq!(large.pixels().iter().filter(|c| **c == 7).count(), 2 * 4);
+    }
+}
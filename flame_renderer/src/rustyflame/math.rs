@@ -1,20 +1,52 @@
 use std::ops::{ Add, Sub, Mul };
 use std::cmp::{ PartialEq };
 
-use rand;
 use rand::Rng;
-use rand_distr::{ Normal, Distribution };
+use rand_distr::Normal;
+use serde::{ Deserialize, Serialize };
 
 use super::utils;
 
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Vec2D
 {
 	pub x: f64,
     pub y: f64
 }
 
+impl Vec2D
+{
+    pub fn dot(&self, other: Vec2D) -> f64
+    {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn length(&self) -> f64
+    {
+        self.x.hypot(self.y)
+    }
+
+    /// Same direction with a length of 1, None for the null vector.
+    pub fn normalize(&self) -> Option<Vec2D>
+    {
+        let length = self.length();
+
+        if length == 0. || !length.is_finite()
+        {
+            return None;
+        }
+
+        Some(Vec2D { x: self.x / length, y: self.y / length })
+    }
+
+    /// Equality up to `epsilon` on each coordinate, for results of float arithmetic.
+    pub fn approx_eq(&self, other: Vec2D, epsilon: f64) -> bool
+    {
+        (self.x - other.x).abs() <= epsilon && (self.y - other.y).abs() <= epsilon
+    }
+}
+
 impl Add for Vec2D
 {
     type Output = Self;
@@ -43,6 +75,30 @@ impl Sub for Vec2D
     }
 }
 
+impl Mul<f64> for Vec2D
+{
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self::Output
+    {
+        Self
+        {
+            x: scalar * self.x,
+            y: scalar * self.y,
+        }
+    }
+}
+
+impl Mul<Vec2D> for f64
+{
+    type Output = Vec2D;
+
+    fn mul(self, vec: Vec2D) -> Self::Output
+    {
+        vec * self
+    }
+}
+
 impl PartialEq for Vec2D
 {
     fn eq(&self, other: &Self) -> bool
@@ -52,7 +108,32 @@ impl PartialEq for Vec2D
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+/// An affine map split as a translation of a rotation of a shear of a scaling, the way editors
+/// present transforms. A reflection shows up as a negative vertical scale.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decomposition
+{
+    pub translation: Vec2D,
+    /// In radians.
+    pub rotation: f64,
+    /// How much x moves per unit of y, before rotating.
+    pub shear: f64,
+    pub scale: Vec2D,
+}
+
+impl Decomposition
+{
+    pub fn to_affine(&self) -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::translation(self.translation)
+            * AugmentedMat2x2::rotation(self.rotation)
+            * AugmentedMat2x2::shear(self.shear)
+            * AugmentedMat2x2::scale(self.scale.x, self.scale.y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct AugmentedMat2x2
 {
     coeffs: [[f64; 3]; 2],
@@ -60,15 +141,149 @@ pub struct AugmentedMat2x2
 
 impl AugmentedMat2x2
 {
+    pub const IDENTITY: AugmentedMat2x2 = AugmentedMat2x2 { coeffs: [[1., 0., 0.], [0., 1., 0.]] };
+
     pub fn new(coeffs: [[f64; 3]; 2]) -> AugmentedMat2x2
     {
-        AugmentedMat2x2 { coeffs: coeffs }
+        AugmentedMat2x2 { coeffs }
     }
 
-    pub fn rand(min: f64, max: f64) -> AugmentedMat2x2
+    pub fn coeffs(&self) -> [[f64; 3]; 2]
     {
-        let mut rnd = rand::thread_rng();
+        self.coeffs
+    }
+
+    /// Counterclockwise rotation around the origin, in radians.
+    pub fn rotation(angle: f64) -> AugmentedMat2x2
+    {
+        let (sin, cos) = angle.sin_cos();
+
+        AugmentedMat2x2::new([[cos, -sin, 0.], [sin, cos, 0.]])
+    }
+
+    pub fn scale(x: f64, y: f64) -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::new([[x, 0., 0.], [0., y, 0.]])
+    }
+
+    /// Moves x by `factor` times y.
+    pub fn shear(factor: f64) -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::new([[1., factor, 0.], [0., 1., 0.]])
+    }
+
+    pub fn translation(offset: Vec2D) -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::new([[1., 0., offset.x], [0., 1., offset.y]])
+    }
+
+    /// Where the origin goes.
+    pub fn offset(&self) -> Vec2D
+    {
+        Vec2D { x: self.coeffs[0][2], y: self.coeffs[1][2] }
+    }
+
+    /// None for degenerate maps, which flatten the plane and have no rotation or scale to speak of.
+    pub fn decompose(&self) -> Option<Decomposition>
+    {
+        let [[a, b, _], [c, d, _]] = self.coeffs;
+        let det = self.determinant();
+        let scale_x = a.hypot(c);
+
+        if det == 0. || !det.is_finite()
+        {
+            return None;
+        }
+
+        // The first column is the rotated horizontal scale, what remains is upper triangular.
+        let rotation = c.atan2(a);
+        let (sin, cos) = rotation.sin_cos();
+        let scale_y = det / scale_x;
+
+        Some(Decomposition
+        {
+            translation: self.offset(),
+            rotation,
+            shear: (cos * b + sin * d) / scale_y,
+            scale: Vec2D { x: scale_x, y: scale_y },
+        })
+    }
+
+    /// Equality up to `epsilon` on each coefficient, for results of float arithmetic.
+    pub fn approx_eq(&self, other: &AugmentedMat2x2, epsilon: f64) -> bool
+    {
+        self.coeffs.iter().flatten().zip(other.coeffs.iter().flatten()).all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    /// Determinant of the linear part, i.e. the area scaling factor of the map.
+    pub fn determinant(&self) -> f64
+    {
+        self.coeffs[0][0] * self.coeffs[1][1] - self.coeffs[0][1] * self.coeffs[1][0]
+    }
+
+    /// Singular values of the linear part, largest first: how much the map stretches along its
+    /// most and least stretched directions.
+    pub fn singular_values(&self) -> (f64, f64)
+    {
+        let [[a, b, _], [c, d, _]] = self.coeffs;
+        let rotation = (a + d).hypot(c - b);
+        let reflection = (a - d).hypot(b + c);
+
+        ((rotation + reflection) / 2., (rotation - reflection).abs() / 2.)
+    }
+
+    /// Whether the map brings any two points closer, so that iterating it converges.
+    pub fn is_contractive(&self) -> bool
+    {
+        self.singular_values().0 < 1.
+    }
+
+    /// The map undoing this one, if it is not degenerate.
+    pub fn inverse(&self) -> Option<AugmentedMat2x2>
+    {
+        let det = self.determinant();
+
+        if det == 0. || !det.is_finite()
+        {
+            return None;
+        }
+
+        let [[a, b, e], [c, d, f]] = self.coeffs;
+
+        Some(AugmentedMat2x2::new
+        (
+            [[d / det, -b / det, (b * f - d * e) / det],
+             [-c / det, a / det, (c * e - a * f) / det]]
+        ))
+    }
+
+    /// The map applying `inner` first, then this one.
+    pub fn compose(&self, inner: &AugmentedMat2x2) -> AugmentedMat2x2
+    {
+        let [[a, b, e], [c, d, f]] = self.coeffs;
+        let [[ia, ib, ie], [ic, id, iff]] = inner.coeffs;
 
+        AugmentedMat2x2::new
+        (
+            [[a * ia + b * ic, a * ib + b * id, a * ie + b * iff + e],
+             [c * ia + d * ic, c * ib + d * id, c * ie + d * iff + f]]
+        )
+    }
+
+    /// The point the map leaves in place, which iterating a contractive map converges to. None when
+    /// there is no single one, for instance for translations.
+    pub fn fixed_point(&self) -> Option<Vec2D>
+    {
+        let [[a, b, e], [c, d, f]] = self.coeffs;
+
+        // Inverse of the identity minus the linear part, applied to the translation.
+        AugmentedMat2x2::new([[1. - a, -b, 0.], [-c, 1. - d, 0.]])
+            .inverse()
+            .map(|m| m * Vec2D { x: e, y: f })
+    }
+
+    pub fn rand<R: Rng + ?Sized>(rnd: &mut R, min: f64, max: f64) -> AugmentedMat2x2
+    {
         AugmentedMat2x2::new
         (
             [[rnd.gen_range(min..max), rnd.gen_range(min..max), rnd.gen_range(min..max)],
@@ -76,9 +291,8 @@ impl AugmentedMat2x2
         )
     }
 
-    pub fn rand_std() -> AugmentedMat2x2
+    pub fn rand_std<R: Rng + ?Sized>(rnd: &mut R) -> AugmentedMat2x2
     {
-        let mut rnd = rand::thread_rng();
         let distrib = Normal::new(0.0, 0.5).unwrap();
 
         AugmentedMat2x2::new
@@ -103,6 +317,17 @@ impl Mul<Vec2D> for AugmentedMat2x2
     }
 }
 
+/// Composition, the right hand side map being applied first.
+impl Mul for AugmentedMat2x2
+{
+    type Output = Self;
+
+    fn mul(self, inner: Self) -> Self::Output
+    {
+        self.compose(&inner)
+    }
+}
+
 impl Mul<f64> for AugmentedMat2x2
 {
     type Output = AugmentedMat2x2;
@@ -189,8 +414,114 @@ impl PartialEq for AugmentedMat2x2
 #[cfg(test)]
 mod tests
 {
-    use super::Vec2D;
-    use super::AugmentedMat2x2;
+    use std::f64::consts::{ FRAC_PI_2, PI };
+
+    use proptest::prelude::*;
+
+    use super::{ AugmentedMat2x2, Decomposition, Vec2D };
+
+    const EPSILON: f64 = 1e-9;
+
+    fn vec2d() -> impl Strategy<Value = Vec2D>
+    {
+        (-10.0..10.0, -10.0..10.0).prop_map(|(x, y)| Vec2D { x, y })
+    }
+
+    fn mat() -> impl Strategy<Value = AugmentedMat2x2>
+    {
+        prop::array::uniform3(-2.0..2.0f64).prop_flat_map(|first| prop::array::uniform3(-2.0..2.0f64).prop_map(move |second| AugmentedMat2x2::new([first, second])))
+    }
+
+    proptest!
+    {
+        #[test]
+        fn prop_composition_is_associative(a in mat(), b in mat(), c in mat())
+        {
+            prop_assert!(((a * b) * c).approx_eq(&(a * (b * c)), EPSILON));
+        }
+
+        #[test]
+        fn prop_composition_applies_right_first(a in mat(), b in mat(), p in vec2d())
+        {
+            prop_assert!(((a * b) * p).approx_eq(a * (b * p), EPSILON));
+        }
+
+        #[test]
+        fn prop_identity_is_neutral(a in mat())
+        {
+            prop_assert_eq!(AugmentedMat2x2::IDENTITY * a, a);
+            prop_assert_eq!(a * AugmentedMat2x2::IDENTITY, a);
+        }
+
+        #[test]
+        fn prop_determinant_is_multiplicative(a in mat(), b in mat())
+        {
+            prop_assert!(((a * b).determinant() - a.determinant() * b.determinant()).abs() <= EPSILON);
+        }
+
+        #[test]
+        fn prop_inverse_undoes(a in mat())
+        {
+            prop_assume!(a.determinant().abs() > 1e-2);
+
+            let inverse = a.inverse().unwrap();
+
+            prop_assert!((a * inverse).approx_eq(&AugmentedMat2x2::IDENTITY, EPSILON));
+            prop_assert!((inverse * a).approx_eq(&AugmentedMat2x2::IDENTITY, EPSILON));
+        }
+
+        #[test]
+        fn prop_singular_values_bound_stretching(a in mat(), p in vec2d())
+        {
+            let (largest, smallest) = a.singular_values();
+            let stretched = (a * p - a * Vec2D { x: 0., y: 0. }).length();
+
+            prop_assert!(smallest <= largest);
+            prop_assert!((largest * smallest - a.determinant().abs()).abs() <= EPSILON);
+            prop_assert!(stretched <= largest * p.length() + EPSILON && stretched >= smallest * p.length() - EPSILON);
+        }
+
+        #[test]
+        fn prop_fixed_point_is_fixed(a in mat())
+        {
+            if let Some(p) = a.fixed_point().filter(|p| p.length() < 1e6)
+            {
+                prop_assert!((a * p).approx_eq(p, 1e-6));
+            }
+        }
+
+        #[test]
+        fn prop_decomposition_round_trips(a in mat())
+        {
+            prop_assume!(a.determinant().abs() > 1e-2);
+
+            prop_assert!(a.decompose().unwrap().to_affine().approx_eq(&a, EPSILON));
+        }
+
+        #[test]
+        fn prop_rotations_add_up(alpha in -PI..PI, beta in -PI..PI, p in vec2d())
+        {
+            let composed = AugmentedMat2x2::rotation(alpha) * AugmentedMat2x2::rotation(beta);
+
+            prop_assert!(composed.approx_eq(&AugmentedMat2x2::rotation(alpha + beta), EPSILON));
+            prop_assert!(((composed * p).length() - p.length()).abs() <= EPSILON);
+        }
+
+        #[test]
+        fn prop_vector_laws(u in vec2d(), v in vec2d(), k in -10.0..10.0f64)
+        {
+            prop_assert_eq!(u + v, v + u);
+            prop_assert_eq!(u.dot(v), v.dot(u));
+            prop_assert!((k * (u + v)).approx_eq(k * u + k * v, EPSILON));
+            prop_assert!((u.dot(u) - u.length() * u.length()).abs() <= EPSILON);
+
+            if let Some(n) = u.normalize()
+            {
+                prop_assert!((n.length() - 1.).abs() <= EPSILON);
+                prop_assert!((n.dot(u) - u.length()).abs() <= EPSILON);
+            }
+        }
+    }
 
     #[test]
     fn test_add_vec2d()
@@ -210,6 +541,15 @@ mod tests
         assert_eq!(x - y, Vec2D { x: -10., y: 0. });
     }
 
+    #[test]
+    fn test_vec2d_scalar_mul()
+    {
+        let x = Vec2D { x: 5., y: -10. };
+
+        assert_eq!(x * 2., Vec2D { x: 10., y: -20. });
+        assert_eq!(-0.5 * x, Vec2D { x: -2.5, y: 5. });
+    }
+
     #[test]
     fn test_augmented_mat_vec_mul()
     {
@@ -226,14 +566,150 @@ mod tests
     #[test]
     fn test_augmented_mat_scalar_mul()
     {
-        let x = Vec2D { x: 5., y: 10. };
         let m = AugmentedMat2x2::new
         (
             [[1., 2., 3.],
              [4., 5., 6.]]
         );
 
-        assert_eq!(m * x, Vec2D { x: 28., y: 76. });
+        assert_eq!(m * 2., AugmentedMat2x2::new
+        (
+            [[2., 4., 6.],
+             [8., 10., 12.]]
+        ));
+        assert_eq!(m * 0.5, 0.5 * m);
+    }
+
+    #[test]
+    fn test_vec2d_metric()
+    {
+        let x = Vec2D { x: 3., y: -4. };
+
+        assert_eq!(x.dot(Vec2D { x: 2., y: 1. }), 2.);
+        assert_eq!(x.length(), 5.);
+        assert_eq!(x.normalize(), Some(Vec2D { x: 0.6, y: -0.8 }));
+        assert_eq!(Vec2D { x: 0., y: 0. }.normalize(), None);
+        assert!(x.approx_eq(Vec2D { x: 3. + 1e-10, y: -4. }, 1e-9));
+        assert!(!x.approx_eq(Vec2D { x: 3.1, y: -4. }, 1e-9));
+    }
+
+    #[test]
+    fn test_augmented_mat_constructors()
+    {
+        let p = Vec2D { x: 1., y: 2. };
+
+        assert!((AugmentedMat2x2::rotation(FRAC_PI_2) * p).approx_eq(Vec2D { x: -2., y: 1. }, 1e-12));
+        assert_eq!(AugmentedMat2x2::scale(2., -1.) * p, Vec2D { x: 2., y: -2. });
+        assert_eq!(AugmentedMat2x2::shear(0.5) * p, Vec2D { x: 2., y: 2. });
+        assert_eq!(AugmentedMat2x2::translation(p) * p, Vec2D { x: 2., y: 4. });
+        assert_eq!(AugmentedMat2x2::translation(p).offset(), p);
+    }
+
+    #[test]
+    fn test_augmented_mat_mat_mul()
+    {
+        let scale = AugmentedMat2x2::scale(2., 3.);
+        let translation = AugmentedMat2x2::translation(Vec2D { x: 1., y: 1. });
+
+        // Translating then scaling also scales the translation.
+        assert_eq!(scale * translation, AugmentedMat2x2::new
+        (
+            [[2., 0., 2.],
+             [0., 3., 3.]]
+        ));
+        assert_eq!(translation * scale, AugmentedMat2x2::new
+        (
+            [[2., 0., 1.],
+             [0., 3., 1.]]
+        ));
+    }
+
+    #[test]
+    fn test_augmented_mat_decompose()
+    {
+        let decomposition = Decomposition { translation: Vec2D { x: 0.5, y: -1. }, rotation: 0.7, shear: 0.3, scale: Vec2D { x: 2., y: -0.5 } };
+        let recovered = decomposition.to_affine().decompose().unwrap();
+
+        assert!(recovered.translation.approx_eq(decomposition.translation, 1e-12));
+        assert!(recovered.scale.approx_eq(decomposition.scale, 1e-12));
+        assert!((recovered.rotation - 0.7).abs() < 1e-12 && (recovered.shear - 0.3).abs() < 1e-12);
+        assert_eq!(AugmentedMat2x2::scale(1., 0.).decompose(), None);
+    }
+
+    #[test]
+    fn test_augmented_mat_determinant()
+    {
+        let m = AugmentedMat2x2::new
+        (
+            [[1., 2., 3.],
+             [4., 5., 6.]]
+        );
+
+        assert_eq!(m.determinant(), -3.);
+        assert_eq!(AugmentedMat2x2::IDENTITY.determinant(), 1.);
+    }
+
+    #[test]
+    fn test_augmented_mat_singular_values()
+    {
+        let stretch = AugmentedMat2x2::new
+        (
+            [[3., 0., 1.],
+             [0., -2., 1.]]
+        );
+        let (sin, cos) = 0.3f64.sin_cos();
+        let rotation = AugmentedMat2x2::new
+        (
+            [[0.5 * cos, -0.5 * sin, 0.],
+             [0.5 * sin, 0.5 * cos, 0.]]
+        );
+
+        assert_eq!(stretch.singular_values(), (3., 2.));
+        assert!(!stretch.is_contractive());
+
+        let (largest, smallest) = rotation.singular_values();
+
+        assert!((largest - 0.5).abs() < 1e-12 && (smallest - 0.5).abs() < 1e-12);
+        assert!(rotation.is_contractive());
+    }
+
+    #[test]
+    fn test_augmented_mat_inverse_and_compose()
+    {
+        let m = AugmentedMat2x2::new
+        (
+            [[1., 2., 3.],
+             [4., 5., 6.]]
+        );
+        let translation = AugmentedMat2x2::new
+        (
+            [[1., 0., 1.],
+             [0., 1., -1.]]
+        );
+
+        assert_eq!(m.compose(&AugmentedMat2x2::IDENTITY), m);
+        assert_eq!(m.compose(&translation) * Vec2D { x: 0., y: 0. }, m * Vec2D { x: 1., y: -1. });
+
+        let inverse = m.inverse().unwrap();
+        let p = Vec2D { x: -0.5, y: 2. };
+        let round_trip = inverse * (m * p);
+
+        assert!((round_trip.x - p.x).abs() < 1e-12 && (round_trip.y - p.y).abs() < 1e-12);
+        assert_eq!(AugmentedMat2x2::new([[1., 2., 0.], [2., 4., 0.]]).inverse(), None);
+    }
+
+    #[test]
+    fn test_augmented_mat_fixed_point()
+    {
+        let m = AugmentedMat2x2::new
+        (
+            [[0.5, 0., 1.],
+             [0., 0.5, 2.]]
+        );
+
+        assert_eq!(m.fixed_point(), Some(Vec2D { x: 2., y: 4. }));
+        assert_eq!(m * Vec2D { x: 2., y: 4. }, Vec2D { x: 2., y: 4. });
+        assert_eq!(AugmentedMat2x2::new([[1., 0., 1.], [0., 1., 0.]]).fixed_point(), None);
     }
 
     #[test]
@@ -300,4 +776,4 @@ mod tests
              [3., 1., 6.]]
         ));
     }
-}
\ No newline at end of file
+}
\ No newline at end of file
@@ -1,4 +1,26 @@
+pub mod animation;
+pub mod batch;
+pub mod breeding;
+pub mod camera;
+pub mod checkpoint;
+pub mod color;
+pub mod density;
+pub mod editor;
+pub mod export;
+pub mod filter;
+pub mod generator;
+pub mod genome;
+pub mod image;
+#[cfg(feature = "sdl")]
 pub mod render;
 pub mod math;
+pub mod overlay;
+pub mod palette;
+pub mod progress;
+pub mod sampling;
 pub mod simulator;
-pub mod utils;
\ No newline at end of file
+pub mod stats;
+pub mod symmetry;
+pub mod tonemap;
+pub mod utils;
+pub mod variations;
@@ -0,0 +1,319 @@
+use super::color::Rgb;
+use super::image::RgbaImage;
+use super::math::Vec2D;
+
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+/// Glyph width plus spacing.
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 3;
+const PANEL_MARGIN: u32 = 4;
+
+/// 5x7 bitmap font, one byte per row with the leftmost pixel in bit 4. Lowercase letters are
+/// drawn uppercase, and anything missing as '?'.
+const GLYPHS: &[(char, [u8; 7])] =
+&[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('=', [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00]),
+    ('*', [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00]),
+    ('/', [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00]),
+    ('%', [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('[', [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E]),
+    (']', [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E]),
+    ('<', [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02]),
+    ('>', [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+];
+
+fn glyph(c: char) -> &'static [u8; 7]
+{
+    let c = c.to_ascii_uppercase();
+
+    GLYPHS
+        .iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .or_else(|| GLYPHS.iter().find(|(glyph_char, _)| *glyph_char == '?'))
+        .map(|(_, rows)| rows)
+        .expect("the font has a '?' glyph")
+}
+
+/// Width in pixels of `text` drawn on a single line.
+pub fn text_width(text: &str) -> u32
+{
+    (text.chars().count() as u32 * ADVANCE).saturating_sub(1)
+}
+
+/// Draws opaque text with its top left corner at `(x, y)`, clipped to the image.
+pub fn draw_text(image: &mut RgbaImage, x: u32, y: u32, text: &str, color: Rgb)
+{
+    let dimensions = image.dimensions();
+
+    for (index, c) in text.chars().enumerate()
+    {
+        // Glyphs starting past the right edge are skipped, as are all the following ones.
+        let left = match (index as u32).checked_mul(ADVANCE).and_then(|offset| x.checked_add(offset))
+        {
+            Some(left) if left < dimensions.width => left,
+            _ => break,
+        };
+
+        for (row, bits) in glyph(c).iter().enumerate()
+        {
+            for column in 0..GLYPH_WIDTH
+            {
+                let (px, py) = (left.saturating_add(column), y.saturating_add(row as u32));
+
+                if bits & (0x10 >> column) != 0 && px < dimensions.width && py < dimensions.height
+                {
+                    image.set_pixel(px, py, [color.r, color.g, color.b, 255]);
+                }
+            }
+        }
+    }
+}
+
+/// Darkens a rectangle, clipped to the image, so that text stays readable over the flame.
+pub fn shade_rect(image: &mut RgbaImage, x: u32, y: u32, width: u32, height: u32)
+{
+    let dimensions = image.dimensions();
+
+    for py in y..(y + height).min(dimensions.height)
+    {
+        for px in x..(x + width).min(dimensions.width)
+        {
+            let [r, g, b, a] = image.get_pixel(px, py);
+            // Straight alpha: scale colors by coverage so the shaded pixel can be opaque.
+            let shade = |c: u8| (c as u32 * a as u32 / (255 * 3)) as u8;
+
+            image.set_pixel(px, py, [shade(r), shade(g), shade(b), 255]);
+        }
+    }
+}
+
+/// One pixel wide segment between two points in pixel coordinates, clipped to the image.
+pub fn draw_line(image: &mut RgbaImage, from: Vec2D, to: Vec2D, color: Rgb)
+{
+    let dimensions = image.dimensions();
+    let (width, height) = (dimensions.width as f64, dimensions.height as f64);
+    let delta = to - from;
+    // Liang-Barsky: the part of the segment inside the image, as a range of its parameter.
+    let (mut start, mut end) = (0f64, 1f64);
+
+    for (p, q) in [(-delta.x, from.x), (delta.x, width - 1. - from.x), (-delta.y, from.y), (delta.y, height - 1. - from.y)].iter()
+    {
+        if *p == 0.
+        {
+            if *q < 0.
+            {
+                return;
+            }
+        }
+        else if *p < 0.
+        {
+            start = start.max(q / p);
+        }
+        else
+        {
+            end = end.min(q / p);
+        }
+    }
+
+    if start > end
+    {
+        return;
+    }
+
+    let (from, to) = (from + start * delta, from + end * delta);
+    let steps = (to.x - from.x).abs().max((to.y - from.y).abs()).ceil().max(1.) as u32;
+
+    for step in 0..=steps
+    {
+        let p = from + (step as f64 / steps as f64) * (to - from);
+        let (x, y) = (p.x.round(), p.y.round());
+
+        if x >= 0. && x < width && y >= 0. && y < height
+        {
+            image.set_pixel(x as u32, y as u32, [color.r, color.g, color.b, 255]);
+        }
+    }
+}
+
+/// Hollow square centered on a point in pixel coordinates, clipped to the image.
+pub fn draw_marker(image: &mut RgbaImage, center: Vec2D, half_size: f64, color: Rgb)
+{
+    let corners =
+    [
+        center + Vec2D { x: -half_size, y: -half_size },
+        center + Vec2D { x: half_size, y: -half_size },
+        center + Vec2D { x: half_size, y: half_size },
+        center + Vec2D { x: -half_size, y: half_size },
+    ];
+
+    for (index, corner) in corners.iter().enumerate()
+    {
+        draw_line(image, *corner, corners[(index + 1) % corners.len()], color);
+    }
+}
+
+/// Shaded box of text lines, anchored at the bottom left corner of the image.
+pub fn draw_panel(image: &mut RgbaImage, lines: &[String], color: Rgb)
+{
+    let width = lines.iter().map(|line| text_width(line)).max().unwrap_or(0) + 2 * PANEL_MARGIN;
+    let height = lines.len() as u32 * LINE_HEIGHT + 2 * PANEL_MARGIN - (LINE_HEIGHT - GLYPH_HEIGHT);
+    let top = image.dimensions().height.saturating_sub(height);
+
+    shade_rect(image, 0, top, width, height);
+
+    for (index, line) in lines.iter().enumerate()
+    {
+        draw_text(image, PANEL_MARGIN, top + PANEL_MARGIN + index as u32 * LINE_HEIGHT, line, color);
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ draw_line, draw_marker, draw_panel, draw_text, text_width, GLYPH_HEIGHT, LINE_HEIGHT };
+    use super::super::color::Rgb;
+    use super::super::image::{ Dimensions, RgbaImage };
+    use super::super::math::Vec2D;
+
+    fn lit(image: &RgbaImage) -> Vec<(u32, u32)>
+    {
+        let dimensions = image.dimensions();
+
+        (0..dimensions.height)
+            .flat_map(|y| (0..dimensions.width).map(move |x| (x, y)))
+            .filter(|(x, y)| image.get_pixel(*x, *y) == [255, 255, 255, 255])
+            .collect()
+    }
+
+    #[test]
+    fn test_draw_text()
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 12, height: 8 });
+
+        draw_text(&mut image, 1, 1, "-l", Rgb::WHITE);
+
+        // The dash is the middle row of the first glyph, the L the left column and bottom row of the second.
+        let mut expected: Vec<(u32, u32)> = (1..6).map(|x| (x, 4))
+            .chain((1..8).map(|y| (7, y)))
+            .chain((8..12).map(|x| (x, 7)))
+            .collect();
+
+        // Row major, like `lit`.
+        expected.sort_by_key(|(x, y)| (*y, *x));
+
+        assert_eq!(lit(&image), expected);
+        assert_eq!(text_width("-l"), 11);
+        assert_eq!(text_width(""), 0);
+    }
+
+    #[test]
+    fn test_unknown_glyphs_and_clipping()
+    {
+        let mut question = RgbaImage::new(Dimensions { width: 6, height: 8 });
+        let mut unknown = RgbaImage::new(Dimensions { width: 6, height: 8 });
+
+        draw_text(&mut question, 0, 0, "?", Rgb::WHITE);
+        draw_text(&mut unknown, 0, 0, "é", Rgb::WHITE);
+
+        assert_eq!(question.pixels(), unknown.pixels());
+
+        // Text running off the image is clipped rather than panicking.
+        draw_text(&mut unknown, 4, 5, "WWW", Rgb::WHITE);
+        draw_text(&mut unknown, u32::MAX - 3, u32::MAX, "WWW", Rgb::WHITE);
+    }
+
+    #[test]
+    fn test_draw_line_is_clipped()
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 8, height: 4 });
+
+        // Mostly outside the image, only the diagonal through it gets drawn.
+        draw_line(&mut image, Vec2D { x: -1e6, y: -1e6 }, Vec2D { x: 1e6, y: 1e6 }, Rgb::WHITE);
+
+        assert_eq!(lit(&image), vec![(0, 0), (1, 1), (2, 2), (3, 3)]);
+
+        let mut outside = RgbaImage::new(Dimensions { width: 8, height: 4 });
+
+        draw_line(&mut outside, Vec2D { x: -5., y: 10. }, Vec2D { x: 20., y: 10. }, Rgb::WHITE);
+        draw_line(&mut outside, Vec2D { x: f64::NAN, y: 0. }, Vec2D { x: 2., y: 2. }, Rgb::WHITE);
+        assert!(lit(&outside).is_empty());
+    }
+
+    #[test]
+    fn test_draw_marker()
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 8, height: 8 });
+
+        draw_marker(&mut image, Vec2D { x: 3., y: 3. }, 1., Rgb::WHITE);
+
+        let expected: Vec<(u32, u32)> = [(2, 2), (3, 2), (4, 2), (2, 3), (4, 3), (2, 4), (3, 4), (4, 4)].to_vec();
+
+        assert_eq!(lit(&image), expected);
+    }
+
+    #[test]
+    fn test_panel_is_opaque_and_readable()
+    {
+        let dimensions = Dimensions { width: 64, height: 32 };
+        let mut image = RgbaImage::new(dimensions);
+
+        image.pixels_mut().iter_mut().for_each(|c| *c = 255);
+        draw_panel(&mut image, &[String::from("A"), String::from("BB")], Rgb::new(255, 0, 0));
+
+        // Two lines plus margins, at the bottom left corner.
+        let top = dimensions.height - (2 * LINE_HEIGHT + 8 - (LINE_HEIGHT - GLYPH_HEIGHT));
+
+        assert_eq!(image.get_pixel(0, top), [85, 85, 85, 255]);
+        assert_eq!(image.get_pixel(0, top - 1), [255, 255, 255, 255]);
+        assert_eq!(image.get_pixel(4 + 2, top + 4), [255, 0, 0, 255]);
+        assert_eq!(image.get_pixel(20, dimensions.height - 1), [255, 255, 255, 255]);
+    }
+}
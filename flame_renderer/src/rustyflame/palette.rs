@@ -0,0 +1,404 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use serde::{ Deserialize, Serialize };
+
+use super::color::Rgb;
+
+pub const PALETTE_SIZE: usize = 256;
+
+/// Name and gradient stops of the palettes shipped with the renderer.
+const BUILTIN_PALETTES: [(&str, &[(f64, Rgb)]); 6] =
+[
+    ("rainbow", &[
+        (0., Rgb { r: 255, g: 0, b: 0 }),
+        (0.2, Rgb { r: 255, g: 255, b: 0 }),
+        (0.4, Rgb { r: 0, g: 255, b: 0 }),
+        (0.6, Rgb { r: 0, g: 255, b: 255 }),
+        (0.8, Rgb { r: 0, g: 0, b: 255 }),
+        (1., Rgb { r: 255, g: 0, b: 255 }),
+    ]),
+    ("fire", &[
+        (0., Rgb { r: 40, g: 0, b: 0 }),
+        (0.35, Rgb { r: 180, g: 20, b: 0 }),
+        (0.7, Rgb { r: 255, g: 160, b: 0 }),
+        (1., Rgb { r: 255, g: 255, b: 200 }),
+    ]),
+    ("ocean", &[
+        (0., Rgb { r: 0, g: 10, b: 60 }),
+        (0.5, Rgb { r: 0, g: 110, b: 160 }),
+        (1., Rgb { r: 180, g: 240, b: 255 }),
+    ]),
+    ("sunset", &[
+        (0., Rgb { r: 60, g: 0, b: 90 }),
+        (0.4, Rgb { r: 200, g: 40, b: 80 }),
+        (0.75, Rgb { r: 255, g: 150, b: 50 }),
+        (1., Rgb { r: 255, g: 240, b: 180 }),
+    ]),
+    ("neon", &[
+        (0., Rgb { r: 255, g: 0, b: 200 }),
+        (0.33, Rgb { r: 120, g: 0, b: 255 }),
+        (0.66, Rgb { r: 0, g: 255, b: 255 }),
+        (1., Rgb { r: 160, g: 255, b: 0 }),
+    ]),
+    ("grayscale", &[
+        (0., Rgb { r: 0, g: 0, b: 0 }),
+        (1., Rgb { r: 255, g: 255, b: 255 }),
+    ]),
+];
+
+/// The 256 colors indexed by the walker color coordinate, as in flam3. Serialized as a
+/// single hex string, like the `<palette>` block of flam3 files.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Palette
+{
+    colors: Vec<Rgb>,
+}
+
+impl Default for Palette
+{
+    fn default() -> Palette
+    {
+        Palette::builtin(BUILTIN_PALETTES[0].0).expect("the first builtin palette exists")
+    }
+}
+
+impl Palette
+{
+    pub fn builtin_names() -> impl Iterator<Item = &'static str>
+    {
+        BUILTIN_PALETTES.iter().map(|(name, _)| *name)
+    }
+
+    pub fn builtin(name: &str) -> Option<Palette>
+    {
+        BUILTIN_PALETTES
+            .iter()
+            .find(|(builtin, _)| *builtin == name)
+            .map(|(_, stops)| Palette::from_gradient(stops).expect("builtin gradients are valid"))
+    }
+
+    /// Resamples any number of colors to the palette size, interpolating between neighbours.
+    pub fn from_colors(colors: &[Rgb]) -> Result<Palette, String>
+    {
+        match colors.len()
+        {
+            0 => Err(String::from("A palette needs at least one color")),
+            PALETTE_SIZE => Ok(Palette { colors: colors.to_vec() }),
+            1 => Ok(Palette { colors: vec![colors[0]; PALETTE_SIZE] }),
+            count =>
+            {
+                let colors = (0..PALETTE_SIZE)
+                    .map(|index|
+                    {
+                        let position = index as f64 * (count - 1) as f64 / (PALETTE_SIZE - 1) as f64;
+                        let lower = (position as usize).min(count - 2);
+
+                        colors[lower].mix(colors[lower + 1], position - lower as f64)
+                    })
+                    .collect();
+
+                Ok(Palette { colors })
+            },
+        }
+    }
+
+    /// Linear gradient through `(position, color)` stops, positions being increasing in [0, 1].
+    /// The ends are extended with the first and last colors.
+    pub fn from_gradient(stops: &[(f64, Rgb)]) -> Result<Palette, String>
+    {
+        if stops.is_empty()
+        {
+            return Err(String::from("A gradient needs at least one stop"));
+        }
+
+        if stops.iter().any(|(position, _)| !(0. ..=1.).contains(position)) || stops.windows(2).any(|w| w[0].0 > w[1].0)
+        {
+            return Err(String::from("Gradient stops must be increasing positions in [0, 1]"));
+        }
+
+        let colors = (0..PALETTE_SIZE)
+            .map(|index|
+            {
+                let t = index as f64 / (PALETTE_SIZE - 1) as f64;
+
+                match stops.iter().position(|(position, _)| *position >= t)
+                {
+                    Some(0) => stops[0].1,
+                    Some(next) =>
+                    {
+                        let ((start, c1), (end, c2)) = (stops[next - 1], stops[next]);
+
+                        if end > start { c1.mix(c2, (t - start) / (end - start)) } else { c2 }
+                    },
+                    None => stops[stops.len() - 1].1,
+                }
+            })
+            .collect();
+
+        Ok(Palette { colors })
+    }
+
+    /// Reads a palette by file extension: `.map` (Fractint), `.gradient`, or a row of a `.png` image.
+    pub fn load(path: &Path) -> Result<Palette, String>
+    {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        let read_text = || fs::read_to_string(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e));
+
+        match extension.as_deref()
+        {
+            Some("map") => Palette::from_map(&read_text()?),
+            Some("gradient") => Palette::from_gradient_text(&read_text()?),
+            Some("png") =>
+            {
+                let file = fs::File::open(path).map_err(|e| format!("Cannot read '{}': {}", path.display(), e))?;
+
+                Palette::from_image_row(file, 0)
+            },
+            _ => Err(format!("Unsupported palette format for '{}' (expected .map, .gradient or .png)", path.display())),
+        }
+    }
+
+    /// Fractint map: one `r g b` triplet per line, anything after it is a comment.
+    pub fn from_map(text: &str) -> Result<Palette, String>
+    {
+        let colors = text
+            .lines()
+            .map(|line| line.split_whitespace().take(3).collect::<Vec<&str>>())
+            .filter(|fields| !fields.is_empty())
+            .map(|fields| match fields[..]
+            {
+                [r, g, b] => Ok(Rgb::new(parse_channel(r)?, parse_channel(g)?, parse_channel(b)?)),
+                _ => Err(format!("Expected 'r g b', got '{}'", fields.join(" "))),
+            })
+            .collect::<Result<Vec<Rgb>, String>>()?;
+
+        Palette::from_colors(&colors)
+    }
+
+    /// One stop per line, either `position r g b` or `position #rrggbb`. Lines starting with `;` are comments.
+    pub fn from_gradient_text(text: &str) -> Result<Palette, String>
+    {
+        let stops = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with(';'))
+            .map(|line|
+            {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let position = |p: &str| p.parse::<f64>().map_err(|_| format!("Invalid gradient position '{}'", p));
+
+                match fields[..]
+                {
+                    [p, hex] => Ok((position(p)?, parse_hex_color(hex.trim_start_matches('#'))?)),
+                    [p, r, g, b] => Ok((position(p)?, Rgb::new(parse_channel(r)?, parse_channel(g)?, parse_channel(b)?))),
+                    _ => Err(format!("Invalid gradient stop '{}'", line)),
+                }
+            })
+            .collect::<Result<Vec<(f64, Rgb)>, String>>()?;
+
+        Palette::from_gradient(&stops)
+    }
+
+    /// Takes the colors of one row of a PNG image, resampled to the palette size.
+    pub fn from_image_row<R: Read>(reader: R, row: usize) -> Result<Palette, String>
+    {
+        let mut decoder = png::Decoder::new(reader);
+
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+        let mut reader = decoder.read_info().map_err(|e| e.to_string())?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).map_err(|e| e.to_string())?;
+
+        if row >= info.height as usize
+        {
+            return Err(format!("Row {} is out of an image of height {}", row, info.height));
+        }
+
+        let channels = info.color_type.samples();
+        let pixels = &buffer[row * info.line_size..][..info.width as usize * channels];
+        let colors: Vec<Rgb> = pixels
+            .chunks_exact(channels)
+            .map(|p| if channels < 3 { Rgb::new(p[0], p[0], p[0]) } else { Rgb::new(p[0], p[1], p[2]) })
+            .collect();
+
+        Palette::from_colors(&colors)
+    }
+
+    pub fn from_hex(text: &str) -> Result<Palette, String>
+    {
+        Palette::from_colors(&parse_hex_colors(text)?)
+    }
+
+    pub fn to_hex(&self) -> String
+    {
+        self.colors.iter().map(|c| format!("{:02X}{:02X}{:02X}", c.r, c.g, c.b)).collect()
+    }
+
+    pub fn colors(&self) -> &[Rgb]
+    {
+        &self.colors
+    }
+
+    /// Color of a color coordinate in [0, 1].
+    pub fn color(&self, index: f64) -> Rgb
+    {
+        self.colors[palette_index(index)]
+    }
+}
+
+impl TryFrom<String> for Palette
+{
+    type Error = String;
+
+    fn try_from(hex: String) -> Result<Palette, String>
+    {
+        Palette::from_hex(&hex)
+    }
+}
+
+impl From<Palette> for String
+{
+    fn from(palette: Palette) -> String
+    {
+        palette.to_hex()
+    }
+}
+
+pub fn palette_index(color: f64) -> usize
+{
+    (color.clamp(0., 1.) * (PALETTE_SIZE - 1) as f64).round() as usize
+}
+
+/// Colors of a `RRGGBB` hex dump, whitespace being ignored.
+pub fn parse_hex_colors(text: &str) -> Result<Vec<Rgb>, String>
+{
+    let digits: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if !digits.len().is_multiple_of(6)
+    {
+        return Err(format!("Hex palette length {} is not a multiple of 6", digits.len()));
+    }
+
+    (0..digits.len()).step_by(6).map(|start| parse_hex_color(digits.get(start..start + 6).unwrap_or_default())).collect()
+}
+
+fn parse_hex_color(hex: &str) -> Result<Rgb, String>
+{
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("Invalid hex color '{}'", hex))?;
+
+    Ok(Rgb::new((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+fn parse_channel(value: &str) -> Result<u8, String>
+{
+    value.parse().map_err(|_| format!("Invalid color channel '{}'", value))
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{ Palette, PALETTE_SIZE };
+    use super::super::color::Rgb;
+    use super::super::export;
+    use super::super::image::{ Dimensions, RgbaImage };
+
+    #[test]
+    fn test_builtin_palettes()
+    {
+        for name in Palette::builtin_names()
+        {
+            assert_eq!(Palette::builtin(name).unwrap().colors().len(), PALETTE_SIZE);
+        }
+
+        let grayscale = Palette::builtin("grayscale").unwrap();
+
+        assert_eq!(grayscale.color(0.), Rgb::BLACK);
+        assert_eq!(grayscale.color(0.5), Rgb::new(128, 128, 128));
+        assert_eq!(grayscale.color(1.), Rgb::new(255, 255, 255));
+        assert_eq!(grayscale.color(7.), Rgb::new(255, 255, 255));
+        assert!(Palette::builtin("plaid").is_none());
+    }
+
+    #[test]
+    fn test_gradient()
+    {
+        let palette = Palette::from_gradient(&[(0.2, Rgb::new(200, 0, 0)), (0.6, Rgb::new(0, 0, 100))]).unwrap();
+
+        assert_eq!(palette.color(0.), Rgb::new(200, 0, 0));
+        assert_eq!(palette.color(0.2), Rgb::new(200, 0, 0));
+        assert_eq!(palette.colors()[102], Rgb::new(100, 0, 50));
+        assert_eq!(palette.color(0.6), Rgb::new(0, 0, 100));
+        assert_eq!(palette.color(1.), Rgb::new(0, 0, 100));
+
+        assert!(Palette::from_gradient(&[]).is_err());
+        assert!(Palette::from_gradient(&[(0.5, Rgb::BLACK), (0.1, Rgb::BLACK)]).is_err());
+        assert!(Palette::from_gradient(&[(1.5, Rgb::BLACK)]).is_err());
+    }
+
+    #[test]
+    fn test_gradient_text()
+    {
+        let palette = Palette::from_gradient_text("; a comment\n0 #FF0000\n\n1 0 0 255\n").unwrap();
+
+        assert_eq!(palette.color(0.), Rgb::new(255, 0, 0));
+        assert_eq!(palette.color(1.), Rgb::new(0, 0, 255));
+        assert!(Palette::from_gradient_text("0 #FF00\n").is_err());
+        assert!(Palette::from_gradient_text("zero 1 2 3\n").is_err());
+    }
+
+    #[test]
+    fn test_map_is_resampled()
+    {
+        let palette = Palette::from_map("0 0 0 black\n255 255 255 white\n").unwrap();
+
+        assert_eq!(palette.colors()[0], Rgb::BLACK);
+        assert_eq!(palette.colors()[51], Rgb::new(51, 51, 51));
+        assert_eq!(palette.colors()[255], Rgb::new(255, 255, 255));
+        assert!(Palette::from_map("0 0\n").is_err());
+        assert!(Palette::from_map("0 0 256\n").is_err());
+        assert!(Palette::from_map("").is_err());
+    }
+
+    #[test]
+    fn test_image_row()
+    {
+        let mut image = RgbaImage::new(Dimensions { width: 256, height: 2 });
+        let mut png = vec![];
+
+        for x in 0..256
+        {
+            image.set_pixel(x, 1, [x as u8, 0, 255 - x as u8, 255]);
+        }
+
+        export::write_png(&image, &mut png).unwrap();
+
+        let palette = Palette::from_image_row(&png[..], 1).unwrap();
+
+        assert_eq!(palette.colors()[10], Rgb::new(10, 0, 245));
+        assert_eq!(Palette::from_image_row(&png[..], 0).unwrap().color(0.5), Rgb::BLACK);
+        assert!(Palette::from_image_row(&png[..], 2).is_err());
+    }
+
+    #[test]
+    fn test_hex_round_trip()
+    {
+        let palette = Palette::builtin("sunset").unwrap();
+        let json = serde_json::to_string(&palette).unwrap();
+
+        assert_eq!(json.len(), PALETTE_SIZE * 6 + 2);
+        assert_eq!(serde_json::from_str::<Palette>(&json).unwrap(), palette);
+        assert!(serde_json::from_str::<Palette>("\"12345\"").is_err());
+        assert!(serde_json::from_str::<Palette>("\"GG0000\"").is_err());
+    }
+}
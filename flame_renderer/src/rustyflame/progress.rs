@@ -0,0 +1,189 @@
+use std::time::{ Duration, Instant };
+
+use super::image::Dimensions;
+
+/// Samples needed for `quality` samples per output pixel, flam3's measure of how converged a render is.
+pub fn samples_for_quality(quality: f64, dimensions: &Dimensions) -> usize
+{
+    (quality * dimensions.area() as f64).ceil() as usize
+}
+
+pub fn quality(samples: usize, dimensions: &Dimensions) -> f64
+{
+    samples as f64 / dimensions.area().max(1) as f64
+}
+
+/// Time left to reach `budget` samples at the rate of the `done` ones, unknown before any sample
+/// or when too long to tell.
+pub fn estimate_remaining(done: usize, budget: usize, elapsed: Duration) -> Option<Duration>
+{
+    if done == 0
+    {
+        return None;
+    }
+
+    Duration::try_from_secs_f64(elapsed.as_secs_f64() * budget.saturating_sub(done) as f64 / done as f64).ok()
+}
+
+/// Short human readable count, like 12.3M.
+pub fn format_count(count: usize) -> String
+{
+    let units = [(1e9, "G"), (1e6, "M"), (1e3, "k")];
+
+    match units.iter().find(|(scale, _)| count as f64 >= *scale)
+    {
+        Some((scale, unit)) => format!("{:.1}{}", count as f64 / scale, unit),
+        None => count.to_string(),
+    }
+}
+
+/// Rounded to the second, as m:ss or h:mm:ss.
+pub fn format_duration(duration: Duration) -> String
+{
+    let seconds = duration.as_secs_f64().round() as u64;
+
+    match seconds / 3600
+    {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+/// Follows an accumulation towards its sample budget. The count going backwards means the
+/// accumulation restarted, so the clock restarts with it.
+pub struct Progress
+{
+    budget: Option<usize>,
+    dimensions: Dimensions,
+    start: Instant,
+    samples: usize,
+}
+
+impl Progress
+{
+    pub fn new(budget: Option<usize>, dimensions: Dimensions) -> Progress
+    {
+        Progress { budget, dimensions, start: Instant::now(), samples: 0 }
+    }
+
+    pub fn update(&mut self, samples: usize)
+    {
+        if samples < self.samples
+        {
+            self.start = Instant::now();
+        }
+
+        self.samples = samples;
+    }
+
+    pub fn samples(&self) -> usize
+    {
+        self.samples
+    }
+
+    /// Samples left before the budget is reached, unlimited without a budget.
+    pub fn remaining(&self) -> Option<usize>
+    {
+        self.budget.map(|budget| budget.saturating_sub(self.samples))
+    }
+
+    pub fn is_done(&self) -> bool
+    {
+        self.remaining() == Some(0)
+    }
+
+    pub fn eta(&self) -> Option<Duration>
+    {
+        estimate_remaining(self.samples, self.budget?, self.start.elapsed())
+    }
+
+    pub fn status(&self) -> String
+    {
+        let mut status = format!("{} samples  {:.1} spp", format_count(self.samples), quality(self.samples, &self.dimensions));
+
+        match (self.budget, self.eta())
+        {
+            (Some(_), _) if self.is_done() => status.push_str("  done"),
+            (Some(budget), Some(eta)) =>
+            {
+                status.push_str(&format!("  {:.0}%  ETA {}", 100. * self.samples as f64 / budget as f64, format_duration(eta)));
+            },
+            _ =>
+            {
+                let elapsed = self.start.elapsed().as_secs_f64();
+
+                if elapsed > 0.
+                {
+                    status.push_str(&format!("  {}/s", format_count((self.samples as f64 / elapsed) as usize)));
+                }
+            },
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+
+    use super::{ estimate_remaining, format_count, format_duration, quality, samples_for_quality, Progress };
+    use super::super::image::Dimensions;
+
+    const DIMENSIONS: Dimensions = Dimensions { width: 40, height: 25 };
+
+    #[test]
+    fn test_quality()
+    {
+        assert_eq!(samples_for_quality(2.5, &DIMENSIONS), 2500);
+        assert_eq!(samples_for_quality(0.0001, &DIMENSIONS), 1);
+        assert_eq!(quality(500, &DIMENSIONS), 0.5);
+    }
+
+    #[test]
+    fn test_estimate_remaining()
+    {
+        assert_eq!(estimate_remaining(0, 100, Duration::from_secs(1)), None);
+        assert_eq!(estimate_remaining(25, 100, Duration::from_secs(2)), Some(Duration::from_secs(6)));
+        assert_eq!(estimate_remaining(200, 100, Duration::from_secs(2)), Some(Duration::ZERO));
+        assert_eq!(estimate_remaining(1, usize::MAX, Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_formatting()
+    {
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(12_345_678), "12.3M");
+        assert_eq!(format_count(2_000_000_000), "2.0G");
+        assert_eq!(format_duration(Duration::from_millis(62_400)), "1:02");
+        assert_eq!(format_duration(Duration::from_secs(3 * 3600 + 5)), "3:00:05");
+    }
+
+    #[test]
+    fn test_progress()
+    {
+        let mut progress = Progress::new(Some(1000), DIMENSIONS);
+
+        assert_eq!(progress.remaining(), Some(1000));
+        assert!(progress.eta().is_none());
+
+        progress.update(400);
+
+        assert_eq!(progress.remaining(), Some(600));
+        assert!(progress.status().starts_with("400 samples  0.4 spp  40%"));
+
+        progress.update(1200);
+
+        assert!(progress.is_done());
+        assert!(progress.status().ends_with("done"));
+
+        let mut unlimited = Progress::new(None, DIMENSIONS);
+
+        unlimited.update(5000);
+
+        assert_eq!(unlimited.remaining(), None);
+        assert!(!unlimited.is_done());
+        assert!(unlimited.status().starts_with("5.0k samples  5.0 spp"));
+    }
+}
@@ -1,22 +1,13 @@
-use std::marker::Copy;
-
 use sdl2::Sdl;
 use sdl2::VideoSubsystem;
 use sdl2::video::{ Window, WindowContext };
-use sdl2::render::{ Canvas, Texture, TextureCreator, RendererInfo };
+use sdl2::render::{ BlendMode, Canvas, Texture, TextureCreator, RendererInfo };
 use sdl2::pixels::PixelFormatEnum;
 
-use contracts::ensures;
+use super::image::{ Dimensions, RgbaImage };
 
 type RenderCanvas = Canvas<Window>;
 
-#[derive(Copy, Clone)]
-pub struct Dimensions
-{
-    pub width: u32,
-    pub height: u32,
-}
-
 pub struct RenderConfig
 {
     pub window_title: String,
@@ -40,14 +31,14 @@ impl RenderTargetCreator
         }
     }
 
-    pub fn create_render_target(&self, pixel_format: PixelFormatEnum, dimensions: &Dimensions) -> Result<Texture, String>
+    pub fn create_render_target(&self, pixel_format: PixelFormatEnum, dimensions: &Dimensions) -> Result<Texture<'_>, String>
     {
         self.texture_creator
             .create_texture_streaming(pixel_format, dimensions.width, dimensions.height)
             .map_err(|e| e.to_string())
     }
 
-    pub fn create_screen_render_target(&self, pixel_format: PixelFormatEnum) -> Result<Texture, String>
+    pub fn create_screen_render_target(&self, pixel_format: PixelFormatEnum) -> Result<Texture<'_>, String>
     {
         self.create_render_target(pixel_format, &self.window_dimensions)
     }
@@ -57,7 +48,7 @@ impl RenderTargetCreator
 pub struct Renderer
 {
     pub config: RenderConfig,
-    video_subsystem: VideoSubsystem,
+    _video_subsystem: VideoSubsystem,
     pub canvas: RenderCanvas,
 }
 
@@ -67,16 +58,16 @@ impl Renderer
 
     pub fn new(sdl_context: &Sdl, config: RenderConfig) -> Result<Renderer, String>
     {
-        let video_subsystem = Renderer::init_video_subsystem(&sdl_context)?;
+        let video_subsystem = Renderer::init_video_subsystem(sdl_context)?;
         let canvas = Renderer::init_canvas(&video_subsystem, &config)?;
 
         Ok
         (
             Renderer 
             {
-                config: config,
-                video_subsystem: video_subsystem,
-                canvas: canvas,
+                config,
+                _video_subsystem: video_subsystem,
+                canvas,
             }
         )
     }
@@ -98,7 +89,7 @@ impl Renderer
 
     fn init_canvas(video_subsystem: &VideoSubsystem, config: &RenderConfig) -> Result<RenderCanvas, String>
     {
-        let window = Renderer::init_sdl_window(&video_subsystem, &config)?;
+        let window = Renderer::init_sdl_window(video_subsystem, config)?;
 
         window
             .into_canvas()
@@ -115,7 +106,7 @@ impl Renderer
 
     pub fn submit(&mut self, texture: &Texture) -> Result<(), String>
     {
-        self.canvas.copy(&texture, None, None)
+        self.canvas.copy(texture, None, None)
     }
 
     pub fn render(&mut self)
@@ -123,9 +114,26 @@ impl Renderer
         self.canvas.present();
     }
 
+    /// Uploads an offscreen image to the window, blended over the cleared canvas.
+    pub fn display(&mut self, image: &RgbaImage) -> Result<(), String>
+    {
+        let render_target_creator = RenderTargetCreator::new(self);
+        let mut render_target = render_target_creator.create_render_target(PixelFormatEnum::RGBA32, &image.dimensions())?;
+
+        render_target
+            .update(None, image.pixels(), image.pitch())
+            .map_err(|e| e.to_string())?;
+        render_target.set_blend_mode(BlendMode::Blend);
+
+        self.clear();
+        self.submit(&render_target)?;
+        self.render();
+
+        Ok(())
+    }
+
     pub fn get_info(&self) -> RendererInfo
     {
         self.canvas.info()
     }
 }
-
@@ -0,0 +1,141 @@
+use rand::Rng;
+
+/// Walker/Vose alias table: O(n) to build, O(1) per draw whatever the weights.
+#[derive(Clone, Debug)]
+pub struct AliasTable
+{
+    probabilities: Vec<f64>,
+    aliases: Vec<usize>,
+}
+
+impl AliasTable
+{
+    pub fn new(weights: &[f64]) -> Result<AliasTable, String>
+    {
+        if let Some(w) = weights.iter().find(|w| !w.is_finite() || **w < 0.)
+        {
+            return Err(format!("Invalid weight {}, weights must be finite and positive", w));
+        }
+
+        let total: f64 = weights.iter().sum();
+
+        if total <= 0.
+        {
+            return Err(String::from("At least one weight must be strictly positive"));
+        }
+
+        let count = weights.len();
+        let mut scaled: Vec<f64> = weights.iter().map(|w| w * count as f64 / total).collect();
+        let mut probabilities = vec![1.; count];
+        let mut aliases: Vec<usize> = (0..count).collect();
+        let (mut small, mut large): (Vec<usize>, Vec<usize>) = (0..count).partition(|i| scaled[*i] < 1.);
+
+        while let (Some(&s), Some(&l)) = (small.last(), large.last())
+        {
+            small.pop();
+
+            probabilities[s] = scaled[s];
+            aliases[s] = l;
+            scaled[l] -= 1. - scaled[s];
+
+            if scaled[l] < 1.
+            {
+                large.pop();
+                small.push(l);
+            }
+        }
+
+        // Whatever is left only differs from 1 by rounding errors.
+        for i in small.into_iter().chain(large)
+        {
+            probabilities[i] = 1.;
+        }
+
+        Ok(AliasTable { probabilities, aliases })
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.probabilities.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.probabilities.is_empty()
+    }
+
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> usize
+    {
+        let column = rng.gen_range(0..self.probabilities.len());
+
+        if rng.gen::<f64>() < self.probabilities[column]
+        {
+            column
+        }
+        else
+        {
+            self.aliases[column]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::SeedableRng;
+    use rand_pcg::Pcg64;
+
+    use super::AliasTable;
+
+    fn frequencies(table: &AliasTable, draws: usize) -> Vec<f64>
+    {
+        let mut rng = Pcg64::seed_from_u64(3);
+        let mut counts = vec![0usize; table.len()];
+
+        for _ in 0..draws
+        {
+            counts[table.sample(&mut rng)] += 1;
+        }
+
+        counts.iter().map(|c| *c as f64 / draws as f64).collect()
+    }
+
+    #[test]
+    fn test_sampling_follows_weights()
+    {
+        let weights = [1., 2., 0., 5., 0.5];
+        let total: f64 = weights.iter().sum();
+        let table = AliasTable::new(&weights).unwrap();
+
+        for (frequency, weight) in frequencies(&table, 200000).iter().zip(weights.iter())
+        {
+            assert!((frequency - weight / total).abs() < 0.005, "{} vs {}", frequency, weight / total);
+        }
+    }
+
+    #[test]
+    fn test_zero_weight_is_never_drawn()
+    {
+        let table = AliasTable::new(&[0., 3., 0.]).unwrap();
+
+        assert_eq!(frequencies(&table, 10000), vec![0., 1., 0.]);
+    }
+
+    #[test]
+    fn test_uniform_weights()
+    {
+        let table = AliasTable::new(&[2.; 4]).unwrap();
+
+        assert!(frequencies(&table, 100000).iter().all(|f| (f - 0.25).abs() < 0.005));
+    }
+
+    #[test]
+    fn test_invalid_weights()
+    {
+        assert!(AliasTable::new(&[]).is_err());
+        assert!(AliasTable::new(&[0., 0.]).is_err());
+        assert!(AliasTable::new(&[1., -1.]).is_err());
+        assert!(AliasTable::new(&[1., f64::NAN]).is_err());
+        assert!(AliasTable::new(&[1., f64::INFINITY]).is_err());
+    }
+}
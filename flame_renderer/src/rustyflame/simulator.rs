@@ -1,33 +1,50 @@
-use rand::Rng;
-use sdl2::pixels::{ PixelFormatEnum, Color };
+use std::convert::TryFrom;
+use std::io::{ Read, Write };
+use std::path::Path;
+use std::thread;
+use std::time::{ Duration, Instant };
 
-use super::render::{ Dimensions, Renderer, RenderTargetCreator };
+use rand::{ Rng, SeedableRng };
+use rand_pcg::Pcg64;
+use serde::{ Deserialize, Serialize };
+
+use super::batch::{ Walkers, BATCH_WALKERS };
+use super::camera::Camera;
+use super::checkpoint::{ self, Reader, Writer };
+use super::color::Rgb;
+use super::density::DensityEstimation;
+use super::filter::ReconstructionFilter;
+use super::generator::Generator;
+use super::genome::Genome;
+use super::image::{ Dimensions, LinearImage, RgbaImage };
 use super::math::{ AugmentedMat2x2, Vec2D };
+use super::palette::Palette;
+use super::sampling::AliasTable;
+use super::stats::Stats;
+use super::symmetry::Symmetry;
+use super::tonemap::ToneMapping;
 use super::utils;
+use super::variations::{ self, Variation, WeightedVariation };
+
+/// The simulator's only source of randomness. PCG is portable and stable across platforms
+/// and crate versions, so a seed always replays the same render.
+pub type FlameRng = Pcg64;
 
-fn generate_random_rgb() -> Color 
+/// Walkers further away than this are considered gone to infinity, as in flam3.
+pub const ESCAPE_BOUND: f64 = 1e10;
+
+/// Whether `p` went past the escape bound, written so that NaN coordinates escape too.
+pub fn escaped(p: Vec2D) -> bool
 {
-    let mut rnd = rand::thread_rng();
-
-    Color::RGB
-    ( 
-        rnd.gen_range(0..255),
-        rnd.gen_range(0..255),
-        rnd.gen_range(0..255)
-    )
+    !(p.x.abs() < ESCAPE_BOUND && p.y.abs() < ESCAPE_BOUND)
 }
 
-fn mix_rgb(c1: Color, c2: Color, factor: f64) -> Color
+pub fn random_seed() -> u64
 {
-    Color::RGB
-    (
-        ((u8::max(c1.r, c2.r) - u8::min(c1.r, c2.r)) as f64 * factor) as u8 + u8::min(c1.r, c2.r),
-        ((u8::max(c1.g, c2.g) - u8::min(c1.g, c2.g)) as f64 * factor) as u8 + u8::min(c1.g, c2.g),
-        ((u8::max(c1.b, c2.b) - u8::min(c1.b, c2.b)) as f64 * factor) as u8 + u8::min(c1.b, c2.b),
-    )
+    rand::thread_rng().gen()
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SuperSamplingFactor
 {
     None = 1,
@@ -36,265 +53,1678 @@ pub enum SuperSamplingFactor
     X8 = 8,
 }
 
+impl SuperSamplingFactor
+{
+    pub fn from_factor(factor: usize) -> Option<SuperSamplingFactor>
+    {
+        match factor
+        {
+            1 => Some(SuperSamplingFactor::None),
+            2 => Some(SuperSamplingFactor::X2),
+            4 => Some(SuperSamplingFactor::X4),
+            8 => Some(SuperSamplingFactor::X8),
+            _ => None,
+        }
+    }
+}
+
+/// How the chaos game is run by `iterate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kernel
+{
+    /// One walker per thread, moved a sample at a time like `step` does.
+    Scalar,
+    /// Many walkers per thread, moved together by `batch::Walkers` with a faster random generator.
+    Batched,
+}
+
+/// Sum of the walker colors of every sample which landed in a histogram cell.
+type ColorSum = [f64; 3];
+
 struct State
 {
     current_coordinates: Vec2D,
-    current_color: Color,
+    /// Color coordinate of the walker, looked up in the palette when plotting.
+    current_color: f64,
     density_histogram: Vec<usize>,
-    color_histogram: Vec<Color>,
+    color_histogram: Vec<ColorSum>,
     plot_dimensions: Dimensions,
+    plot_transform: AugmentedMat2x2,
     iter_count: usize,
+    /// Iteration the walker was last sent back to a random point, after which it needs preparation rounds again.
+    reseeded_at: usize,
+    /// Iterations that left the walker, or its point through the final transform, at an infinite, NaN or escaped point.
+    bad_samples: usize,
+    /// Samples past the preparation rounds which missed the plot.
+    out_of_frame: usize,
+    /// Times each function of the IFS was picked.
+    function_hits: Vec<usize>,
+    /// Time spent iterating into this state.
+    elapsed: Duration,
     super_sampling_factor: SuperSamplingFactor,
+    /// Walkers of the batched kernel, created when it first runs.
+    walkers: Option<Walkers>,
 }
 
 impl State
 {
-    fn new(render_target_dimensions: &Dimensions, super_sampling_factor: SuperSamplingFactor) -> State 
+    fn new<R: Rng + ?Sized>(render_target_dimensions: &Dimensions, super_sampling_factor: SuperSamplingFactor, camera: &Camera, rng: &mut R) -> State 
     {
-        let mut thread_rng = rand::thread_rng();
-
         let plot_dimensions = Dimensions
         { 
             height: render_target_dimensions.height * super_sampling_factor as u32, 
             width: render_target_dimensions.width * super_sampling_factor as u32, 
         };
         
-        let factor = super_sampling_factor as usize;
-        
         State
         {
-            current_coordinates: Vec2D{ x: thread_rng.gen_range(-1.0..1.0), y: thread_rng.gen_range(-1.0..1.0) },
-            current_color: generate_random_rgb(),
-            density_histogram: vec![0; plot_dimensions.height as usize * factor * plot_dimensions.width as usize * factor],
-            color_histogram: vec![Color::RGB(0, 0, 0); plot_dimensions.height as usize * factor * plot_dimensions.width as usize * factor],
-            plot_dimensions: plot_dimensions,
+            current_coordinates: Vec2D{ x: rng.gen_range(-1.0..1.0), y: rng.gen_range(-1.0..1.0) },
+            current_color: rng.gen(),
+            density_histogram: vec![0; plot_dimensions.area()],
+            color_histogram: vec![[0.; 3]; plot_dimensions.area()],
+            plot_transform: camera.world_to_frame(&plot_dimensions),
+            plot_dimensions,
             iter_count: 0,
-            super_sampling_factor: super_sampling_factor,
+            reseeded_at: 0,
+            bad_samples: 0,
+            out_of_frame: 0,
+            function_hits: vec![],
+            elapsed: Duration::ZERO,
+            super_sampling_factor,
+            walkers: None,
         }
     }
 
-    fn plot_current(&mut self)
+    fn advance<R: Rng + ?Sized>(&mut self, ifs: &Ifs, palette: &Palette, preparation_rounds: usize, rng: &mut R)
     {
-        let height = self.plot_dimensions.height as f64;
-        let width = self.plot_dimensions.width as f64;
+        self.iter_count += 1;
+        let index = ifs.selector.sample(rng);
+        let function = &ifs.functions[index];
 
-        let x = ((self.current_coordinates.x + 1.) * height / 2.) as usize;
-        let y = ((self.current_coordinates.y + 1.) * width / 2.) as usize;
+        self.count_hits(index, 1);
 
-        if x < self.plot_dimensions.height as usize && y < self.plot_dimensions.width as usize
+        self.current_coordinates = function.apply(self.current_coordinates, rng);
+        self.current_color = function.blend_color(self.current_color);
+
+        if escaped(self.current_coordinates)
         {
-            let idx = x * width as usize + y;
+            self.bad_samples += 1;
+            self.reseeded_at = self.iter_count;
+            self.current_coordinates = Vec2D { x: rng.gen_range(-1.0..1.0), y: rng.gen_range(-1.0..1.0) };
+
+            return;
+        }
 
-            if idx > 0 && idx < self.density_histogram.len()
+        if self.iter_count - self.reseeded_at > preparation_rounds
+        {
+            // The final transform only changes what gets plotted, the walker carries on from its own point.
+            match &ifs.final_transform
             {
-                self.density_histogram[idx] += 1;
-                self.color_histogram[idx] = self.current_color;
+                Some(last) =>
+                {
+                    let p = last.apply(self.current_coordinates, rng);
+
+                    // The walker itself is fine, so it is not sent back, but the sample is lost all the same.
+                    if escaped(p)
+                    {
+                        self.bad_samples += 1;
+                    }
+                    else
+                    {
+                        self.plot(p, palette.color(last.blend_color(self.current_color)));
+                    }
+                },
+                None => self.plot(self.current_coordinates, palette.color(self.current_color)),
             }
         }
     }
 
-    fn compute_subsampled_histogram(&self) -> (Vec<usize>, Vec<Color>)
+    /// Runs `samples` iterations with the batched kernel, whose walkers are independent from the scalar one.
+    fn advance_batch<R: Rng + ?Sized>(&mut self, samples: usize, ifs: &Ifs, palette: &Palette, preparation_rounds: usize, rng: &mut R)
     {
-        let subsampled_dims = Dimensions 
-        { 
-            height: self.plot_dimensions.height / self.super_sampling_factor as u32, 
-            width: self.plot_dimensions.width / self.super_sampling_factor as u32,
-        };
+        let mut walkers = self.walkers.take().unwrap_or_else(|| Walkers::new(BATCH_WALKERS, rng));
+        let mut remaining = samples;
+
+        while remaining > 0
+        {
+            let count = remaining.min(walkers.len());
 
-        let mut histogram = vec![0; (subsampled_dims.height * subsampled_dims.width) as usize];
-        let mut color_histogram = vec![Color::RGB(0, 0, 0); (subsampled_dims.height * subsampled_dims.width) as usize];
-        let factor = self.super_sampling_factor as u32;
+            self.bad_samples += walkers.advance(count, &ifs.functions, &ifs.selector, ifs.final_transform.as_ref(), preparation_rounds, |p, color|
+            {
+                self.plot(p, palette.color(color));
+            });
+            self.iter_count += count;
+
+            for (index, hits) in walkers.group_sizes().enumerate()
+            {
+                self.count_hits(index, hits);
+            }
+            remaining -= count;
+        }
+
+        self.walkers = Some(walkers);
+    }
+
+    fn count_hits(&mut self, function: usize, hits: usize)
+    {
+        if function >= self.function_hits.len()
+        {
+            self.function_hits.resize(function + 1, 0);
+        }
+
+        self.function_hits[function] += hits;
+    }
+
+    /// Takes over the samples of `other`, histogram and counts, leaving the walker of this state as it is.
+    fn absorb(&mut self, other: &mut State)
+    {
+        self.merge_histogram(other);
+        self.iter_count += other.iter_count;
+        // The walker keeps the same number of rounds behind it.
+        self.reseeded_at += other.iter_count;
+        self.bad_samples += other.bad_samples;
+        self.out_of_frame += other.out_of_frame;
+
+        for (index, hits) in other.function_hits.iter().enumerate()
+        {
+            self.count_hits(index, *hits);
+        }
+    }
+
+    /// Saves the walkers and counts, without the histogram.
+    fn write_walker<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), String>
+    {
+        writer.f64(self.current_coordinates.x)?;
+        writer.f64(self.current_coordinates.y)?;
+        writer.f64(self.current_color)?;
+        writer.usize(self.iter_count)?;
+        writer.usize(self.reseeded_at)?;
+        writer.usize(self.bad_samples)?;
+        writer.usize(self.out_of_frame)?;
+        writer.usizes(&self.function_hits)?;
+        writer.u64(u64::try_from(self.elapsed.as_nanos()).unwrap_or(u64::MAX))?;
 
-        for x in 0..subsampled_dims.height
+        match &self.walkers
         {
-            for y in 0..subsampled_dims.width
+            Some(walkers) =>
             {
-                let idx = (x * factor * subsampled_dims.width) + (y * factor);
+                writer.u8(1)?;
+                walkers.write(writer)
+            },
+            None => writer.u8(0),
+        }
+    }
 
-                let mut val = 0;
-                let mut area_colors = vec![];
+    fn read_walker<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<(), String>
+    {
+        self.current_coordinates = Vec2D { x: reader.f64()?, y: reader.f64()? };
+        self.current_color = reader.f64()?;
+        self.iter_count = reader.usize()?;
+        self.reseeded_at = reader.usize()?;
+        self.bad_samples = reader.usize()?;
+        self.out_of_frame = reader.usize()?;
+        self.function_hits = reader.usizes()?;
+        self.elapsed = Duration::from_nanos(reader.u64()?);
+        self.walkers = match reader.u8()?
+        {
+            0 => None,
+            1 => Some(Walkers::read(reader)?),
+            flag => return Err(format!("Invalid walker flag {} in checkpoint", flag)),
+        };
 
-                for offset_x in 0..factor
-                {
-                    for offset_y in 0..factor
-                    {
-                        if x + offset_x < self.plot_dimensions.height && y + offset_y < self.plot_dimensions.width
-                        {
-                            let offset_idx = (idx + offset_x * self.plot_dimensions.width + offset_y) as usize;
+        Ok(())
+    }
 
-                            val += self.density_histogram[offset_idx];
-                            area_colors.push(self.color_histogram[offset_idx]);
-                        }
-                    }
+    fn write_histogram<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), String>
+    {
+        writer.usizes(&self.density_histogram)?;
+        writer.f64s(&self.color_histogram.concat())
+    }
+
+    fn read_histogram<R: Read>(&mut self, reader: &mut Reader<R>) -> Result<(), String>
+    {
+        let density_histogram = reader.usizes()?;
+        let colors = reader.f64s()?;
+
+        if density_histogram.len() != self.density_histogram.len() || colors.len() != 3 * self.color_histogram.len()
+        {
+            return Err(String::from("The checkpoint histogram does not match its dimensions"));
+        }
+
+        self.density_histogram = density_histogram;
+        self.color_histogram = colors.chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+
+        Ok(())
+    }
+
+    /// Accumulates the histogram of `other` into this one and clears it, keeping both walkers as they are.
+    fn merge_histogram(&mut self, other: &mut State)
+    {
+        let cells = utils::zip((self.density_histogram.iter_mut(), self.color_histogram.iter_mut()));
+        let other_cells = utils::zip((other.density_histogram.iter_mut(), other.color_histogram.iter_mut()));
+
+        for ((density, color), (other_density, other_color)) in cells.zip(other_cells)
+        {
+            if *other_density > 0
+            {
+                *density += *other_density;
+                *other_density = 0;
+
+                for (c, other_c) in color.iter_mut().zip(other_color.iter_mut())
+                {
+                    *c += *other_c;
+                    *other_c = 0.;
                 }
+            }
+        }
+    }
+
+    /// Maps `p` through the camera onto the supersampled plot.
+    fn plot(&mut self, p: Vec2D, color: Rgb)
+    {
+        let width = self.plot_dimensions.width as f64;
+        let height = self.plot_dimensions.height as f64;
+
+        let Vec2D { x, y } = self.plot_transform * p;
+
+        // Written so that NaN coordinates fail the test too.
+        if x >= 0. && x < width && y >= 0. && y < height
+        {
+            let idx = y as usize * self.plot_dimensions.width as usize + x as usize;
+            let sum = &mut self.color_histogram[idx];
+
+            self.density_histogram[idx] += 1;
+            sum[0] += color.r as f64;
+            sum[1] += color.g as f64;
+            sum[2] += color.b as f64;
+        }
+        else
+        {
+            self.out_of_frame += 1;
+        }
+    }
 
-                let subsampled_hist_idx = (x * subsampled_dims.width + y) as usize;
-                histogram[subsampled_hist_idx] = val;
+    /// Light of each supersampled cell. `intensity` maps the density of a cell to its brightness,
+    /// which goes in alpha, while the color holds the average color of its samples, premultiplied.
+    fn compute_cells<F: Fn(usize) -> f64>(&self, intensity: F) -> LinearImage
+    {
+        let mut cells = LinearImage::new(self.plot_dimensions);
 
-                let mut float_rgb = (0., 0., 0.);
+        for (cell, (density, color)) in cells.pixels_mut().iter_mut().zip(utils::zip((self.density_histogram.iter(), self.color_histogram.iter())))
+        {
+            if *density > 0
+            {
+                let cell_intensity = intensity(*density);
 
-                for c in area_colors.iter()
+                for (c, sum) in cell.iter_mut().zip(color.iter())
                 {
-                    float_rgb.0 += c.r as f64;
-                    float_rgb.1 += c.g as f64;
-                    float_rgb.2 += c.b as f64;
+                    *c = cell_intensity * sum / (*density as f64 * 255.);
                 }
 
-                color_histogram[subsampled_hist_idx] = Color::RGB
-                (
-                    (float_rgb.0 / area_colors.len() as f64) as u8, 
-                    (float_rgb.1 / area_colors.len() as f64) as u8, 
-                    (float_rgb.2 / area_colors.len() as f64) as u8,
-                );
+                cell[3] = cell_intensity;
             }
         }
 
-        (histogram, color_histogram)
+        cells
+    }
+
+    /// Brings supersampled cells down to the output resolution. Filtering brightness rather than
+    /// densities is what antialiases edges.
+    fn downsample(&self, cells: &LinearImage, filter: &ReconstructionFilter) -> LinearImage
+    {
+        filter.downsample(cells, self.super_sampling_factor as usize)
+    }
+
+    /// Light of each output pixel: the filtered intensity of its cells in alpha, and the colors of
+    /// its samples weighted by that intensity, premultiplied.
+    fn compute_subsampled_histogram<F: Fn(usize) -> f64>(&self, intensity: F, filter: &ReconstructionFilter) -> LinearImage
+    {
+        self.downsample(&self.compute_cells(intensity), filter)
+    }
+
+    fn max_density(&self) -> usize
+    {
+        self.density_histogram.iter().copied().max().unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FunctionElement
+{
+    #[serde(rename = "affine")]
+    pub application: AugmentedMat2x2,
+    /// Relative probability of picking this function in the chaos game.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default)]
+    pub variations: Vec<WeightedVariation>,
+    #[serde(rename = "post", default, skip_serializing_if = "Option::is_none")]
+    pub post_transform: Option<AugmentedMat2x2>,
+    /// Position in the palette the walker is pulled towards when this function is picked.
+    #[serde(default)]
+    pub color: f64,
+    /// How far towards `color` the walker moves, 0 keeping its color and 1 jumping to it.
+    #[serde(default = "default_color_speed")]
+    pub color_speed: f64,
+}
+
+fn default_weight() -> f64
+{
+    1.
+}
+
+fn default_color_speed() -> f64
+{
+    0.5
+}
+
+impl FunctionElement
+{
+    const MIN_DETERMINANT_WEIGHT: f64 = 1e-3;
+
+    pub fn new(application: AugmentedMat2x2, color: f64) -> FunctionElement
+    {
+        FunctionElement
+        {
+            application,
+            weight: default_weight(),
+            variations: vec![WeightedVariation { variation: Variation::Linear, weight: 1. }],
+            post_transform: None,
+            color,
+            color_speed: default_color_speed(),
+        }
+    }
+
+    fn random<R: Rng + ?Sized>(application: AugmentedMat2x2, rng: &mut R) -> FunctionElement
+    {
+        FunctionElement
+        {
+            variations: variations::random_blend(rng, 2),
+            ..FunctionElement::new(application, rng.gen())
+        }
+    }
+
+    /// A map shrinking areas by |det| gets picked proportionally, so that every part of the
+    /// attractor receives about the same density. Degenerate maps keep a small weight.
+    pub fn determinant_weight(application: &AugmentedMat2x2) -> f64
+    {
+        application.determinant().abs().max(FunctionElement::MIN_DETERMINANT_WEIGHT)
+    }
+
+    /// Color coordinate of a walker at `color` once this function is applied.
+    pub fn blend_color(&self, color: f64) -> f64
+    {
+        color + (self.color - color) * self.color_speed
+    }
+
+    pub fn apply<R: Rng + ?Sized>(&self, p: Vec2D, rng: &mut R) -> Vec2D
+    {
+        let transformed = self.application * p;
+        let varied = variations::apply_blend(&self.variations, transformed, &self.application, rng);
+
+        match self.post_transform
+        {
+            Some(post_transform) => post_transform * varied,
+            None => varied,
+        }
+    }
+}
+
+/// What the walkers run: the genome transforms followed by the generated symmetry ones, picked
+/// according to their weights, and the final transform points go through on their way to the plot.
+struct Ifs
+{
+    functions: Vec<FunctionElement>,
+    transform_count: usize,
+    symmetry: Symmetry,
+    final_transform: Option<FunctionElement>,
+    selector: AliasTable,
+}
+
+impl Ifs
+{
+    fn new(mut transforms: Vec<FunctionElement>, symmetry: Symmetry, final_transform: Option<FunctionElement>) -> Result<Ifs, String>
+    {
+        let transform_count = transforms.len();
+
+        transforms.extend(symmetry.transforms());
+
+        let weights: Vec<f64> = transforms.iter().map(|f| f.weight).collect();
+
+        Ok(Ifs
+        {
+            selector: AliasTable::new(&weights)?,
+            functions: transforms,
+            transform_count,
+            symmetry,
+            final_transform,
+        })
+    }
+
+    fn from_transforms(transforms: Vec<FunctionElement>) -> Ifs
+    {
+        Ifs::new(transforms, Symmetry::None, None).expect("generated weights are always valid")
+    }
+
+    /// The genome transforms, without the symmetry ones.
+    fn transforms(&self) -> &[FunctionElement]
+    {
+        &self.functions[..self.transform_count]
     }
 }
 
-struct FunctionElement
+/// Saves the exact state of `rng`, leaving its stream untouched.
+fn write_rng<W: Write>(rng: &FlameRng, writer: &mut Writer<W>) -> Result<(), String>
+{
+    writer.str(&serde_json::to_string(rng).map_err(|e| e.to_string())?)
+}
+
+fn read_rng<R: Read>(reader: &mut Reader<R>) -> Result<FlameRng, String>
+{
+    serde_json::from_str(&reader.str()?).map_err(|e| format!("Invalid random generator in checkpoint: {}", e))
+}
+
+/// A chaos game walker with its own random stream and thread-local histogram.
+struct Worker
 {
-    application: AugmentedMat2x2,
-    associated_color: Color,
-    
+    state: State,
+    rng: FlameRng,
 }
 
 pub struct Simulator
 {
     state: State,
-    renderer: Renderer,
-    render_target_creator: RenderTargetCreator,
+    dimensions: Dimensions,
     preparation_rounds: usize,
-    ifs: Vec<FunctionElement>,
+    ifs: Ifs,
     super_sampling_factor: SuperSamplingFactor,
+    camera: Camera,
+    palette: Palette,
+    tone_mapping: ToneMapping,
+    density_estimation: DensityEstimation,
+    reconstruction_filter: ReconstructionFilter,
+    seed: u64,
+    rng: FlameRng,
+    thread_count: usize,
+    workers: Vec<Worker>,
+    kernel: Kernel,
 }
 
 impl Simulator
 {
-	pub fn new(renderer: Renderer, preparation_rounds: usize, super_sampling_factor: SuperSamplingFactor) -> Result<Simulator, String>
-	{
-        let render_target_creator = RenderTargetCreator::new(&renderer);
+    pub fn new(dimensions: Dimensions, preparation_rounds: usize, super_sampling_factor: SuperSamplingFactor) -> Simulator
+    {
+        Simulator::with_seed(dimensions, preparation_rounds, super_sampling_factor, random_seed())
+    }
 
-        Ok(Simulator 
+    /// Same seed, same IFS and same histogram, bit for bit.
+    pub fn with_seed(dimensions: Dimensions, preparation_rounds: usize, super_sampling_factor: SuperSamplingFactor, seed: u64) -> Simulator
+    {
+        let mut rng = FlameRng::seed_from_u64(seed);
+        let ifs = Ifs::from_transforms(Simulator::generate_norm_ifs(&mut rng));
+        let camera = Camera::default();
+
+        Simulator 
         { 
-            state: State::new(&renderer.config.window_dimensions, super_sampling_factor),
-            renderer: renderer,
-            render_target_creator: render_target_creator,
-            preparation_rounds: preparation_rounds,
-            ifs: Simulator::generate_norm_ifs(),
-            super_sampling_factor: super_sampling_factor,
-        })
+            state: State::new(&dimensions, super_sampling_factor, &camera, &mut rng),
+            ifs,
+            dimensions,
+            preparation_rounds,
+            super_sampling_factor,
+            camera,
+            palette: Palette::default(),
+            tone_mapping: ToneMapping::default(),
+            density_estimation: DensityEstimation::default(),
+            reconstruction_filter: ReconstructionFilter::default(),
+            seed,
+            rng,
+            thread_count: 1,
+            workers: vec![],
+            kernel: Kernel::Scalar,
+        }
     }
 
-    fn generate_ifs() -> Vec<FunctionElement>
+    /// The genome `with_seed` starts from.
+    pub fn random_genome(seed: u64) -> Genome
     {
-        vec![
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-        ]
+        let mut rng = FlameRng::seed_from_u64(seed);
+
+        Genome { transforms: Simulator::generate_norm_ifs(&mut rng), ..Genome::default() }
     }
 
-    fn generate_ifs2() -> Vec<FunctionElement>
+    pub fn from_genome(dimensions: Dimensions, preparation_rounds: usize, super_sampling_factor: SuperSamplingFactor, genome: Genome) -> Result<Simulator, String>
     {
-        vec![
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-        ]
+        let mut simulator = Simulator::new(dimensions, preparation_rounds, super_sampling_factor);
+
+        simulator.set_genome(genome)?;
+        Ok(simulator)
     }
 
-    fn generate_norm_ifs() -> Vec<FunctionElement>
+    pub fn dimensions(&self) -> Dimensions
     {
-        vec![
-            FunctionElement{ application: AugmentedMat2x2::rand_std(), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand(-1., 1.), associated_color: generate_random_rgb() },
-            FunctionElement{ application: AugmentedMat2x2::rand_std(), associated_color: generate_random_rgb() },
-        ]
+        self.dimensions
     }
 
-    pub fn reset(&mut self)
+    pub fn seed(&self) -> u64
     {
-        self.state = State::new(&self.renderer.config.window_dimensions, self.super_sampling_factor);
-        self.ifs = Simulator::generate_ifs2();
+        self.seed
     }
 
-    pub fn step(&mut self)
+    /// Iterations run since the accumulation last restarted, over all walkers.
+    pub fn sample_count(&self) -> usize
     {
-        /*let fs = vec![
-            AugmentedMat2x2::new(
-                [[1./2., 0., 0.],
-                 [0., 1./2., 0.]]
-            ),
-            AugmentedMat2x2::new(
-                [[1./2., 0., 1./2.],
-                 [0., 1./2., 0.]]
-            ),
-            AugmentedMat2x2::new(
-                [[1./2., 0., 0.],
-                 [0., 1./2., 1./2.]]
-            )
-        ];*/
+        self.state.iter_count + self.workers.iter().map(|w| w.state.iter_count).sum::<usize>()
+    }
+
+    /// Iterations that sent a walker, or its point through the final transform, to infinity or NaN,
+    /// since the accumulation last restarted. Such walkers are sent back to a random point and go
+    /// through preparation rounds again, while the final transform only loses the sample.
+    pub fn bad_sample_count(&self) -> usize
+    {
+        self.state.bad_samples + self.workers.iter().map(|w| w.state.bad_samples).sum::<usize>()
+    }
+
+    /// Samples that landed in the frame since the accumulation last restarted. The worker
+    /// histograms are merged into the main one after every `iterate`.
+    pub fn plotted_count(&self) -> usize
+    {
+        self.state.density_histogram.iter().sum()
+    }
+
+    /// Counts gathered since the accumulation last restarted, over all walkers.
+    pub fn stats(&self) -> Stats
+    {
+        let states = || Some(&self.state).into_iter().chain(self.workers.iter().map(|w| &w.state));
+        let mut function_hits = vec![0; self.ifs.functions.len()];
 
-        self.state.iter_count += 1;
-        let choice = rand::thread_rng().gen_range(0..self.ifs.len());
-        let FunctionElement{ application, associated_color} = self.ifs[choice];
+        for state in states()
+        {
+            for (total, hits) in function_hits.iter_mut().zip(state.function_hits.iter())
+            {
+                *total += hits;
+            }
+        }
 
-        self.state.current_coordinates = application * self.state.current_coordinates;
-        self.state.current_color = mix_rgb(self.state.current_color, associated_color, 0.5);
+        let plotted = self.plotted_count();
+        let filled = self.state.density_histogram.iter().filter(|d| **d > 0).count();
 
-        if self.state.iter_count > self.preparation_rounds
+        Stats
         {
-            self.state.plot_current();
+            samples: self.sample_count(),
+            plotted,
+            out_of_frame: states().map(|s| s.out_of_frame).sum(),
+            bad: self.bad_sample_count(),
+            symmetry_hits: function_hits[self.ifs.transform_count..].iter().sum(),
+            transform_hits: function_hits[..self.ifs.transform_count].to_vec(),
+            max_density: self.state.max_density(),
+            mean_density: if filled > 0 { plotted as f64 / filled as f64 } else { 0. },
+            filled_ratio: self.coverage(),
+            elapsed: self.state.elapsed,
         }
+    }
+
+    /// Fraction of the histogram cells reached by at least one sample.
+    pub fn coverage(&self) -> f64
+    {
+        self.state.density_histogram.iter().filter(|d| **d > 0).count() as f64 / self.state.density_histogram.len() as f64
+    }
 
+    pub fn thread_count(&self) -> usize
+    {
+        self.thread_count
     }
 
-    pub fn render(&mut self) -> Result<(), String>
+    /// Number of independent walkers used by `iterate`. With a single thread and the scalar
+    /// kernel, `iterate` is the same as calling `step` repeatedly.
+    pub fn set_thread_count(&mut self, thread_count: usize)
     {
-        let mut render_target = self.render_target_creator.create_screen_render_target(PixelFormatEnum::ARGB8888)?;
+        let thread_count = thread_count.max(1);
 
-        render_target.with_lock(None,
-            |buffer: &mut [u8], _: usize|
+        if thread_count != self.thread_count
+        {
+            // The samples of the workers stay, only their walkers go.
+            for mut worker in self.workers.drain(..)
             {
-                let (histogram, colors) = self.state.compute_subsampled_histogram();
-                let max_density = *histogram.iter().max().unwrap();
-                let max_log_density = (max_density as f64).log10();
+                self.state.absorb(&mut worker.state);
+            }
+        }
 
-                for (idx, (d, c)) in utils::zip((histogram.iter(), colors)).enumerate()
-                {
-                    if *d > 0
-                    {
-                        let gamma_factor = 1./2.2;
-                        let r = (c.r as f64 * ((*d as f64).log10() / max_log_density).powf(gamma_factor)) as u8;
-                        let g = (c.g as f64 * ((*d as f64).log10() / max_log_density).powf(gamma_factor)) as u8;
-                        let b = (c.b as f64 * ((*d as f64).log10() / max_log_density).powf(gamma_factor)) as u8;
+        self.thread_count = thread_count;
+    }
 
-                        let offset = idx * 4;
+    pub fn kernel(&self) -> Kernel
+    {
+        self.kernel
+    }
 
-                        buffer[offset]     = r;
-                        buffer[offset + 1] = g;
-                        buffer[offset + 2] = b;
-                        buffer[offset + 3] = 0;
-                    }
-               }
-            }
-        )?;
+    /// Picks how `iterate` runs from now on. Samples already accumulated are kept.
+    pub fn set_kernel(&mut self, kernel: Kernel)
+    {
+        self.kernel = kernel;
+    }
+
+    pub fn camera(&self) -> Camera
+    {
+        self.camera
+    }
+
+    /// Moves the view. Samples were binned through the previous camera, so the accumulation restarts.
+    pub fn set_camera(&mut self, camera: Camera)
+    {
+        self.camera = camera;
+        self.state = State::new(&self.dimensions, self.super_sampling_factor, &self.camera, &mut self.rng);
+        self.workers.clear();
+    }
+
+    pub fn palette(&self) -> &Palette
+    {
+        &self.palette
+    }
+
+    /// Colors are looked up when plotting, so the accumulation restarts with the new palette.
+    pub fn set_palette(&mut self, palette: Palette)
+    {
+        self.palette = palette;
+        self.state = State::new(&self.dimensions, self.super_sampling_factor, &self.camera, &mut self.rng);
+        self.workers.clear();
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping
+    {
+        self.tone_mapping
+    }
+
+    /// Only affects how the histogram is displayed, the accumulation goes on.
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) -> Result<(), String>
+    {
+        tone_mapping.validate()?;
+        self.tone_mapping = tone_mapping;
+
+        Ok(())
+    }
 
+    pub fn density_estimation(&self) -> DensityEstimation
+    {
+        self.density_estimation
+    }
 
-        self.renderer.clear();
-        self.renderer.submit(&render_target)?;
-        self.renderer.render();
+    /// Like the tone mapping, only changes how the histogram is displayed.
+    pub fn set_density_estimation(&mut self, density_estimation: DensityEstimation) -> Result<(), String>
+    {
+        density_estimation.validate()?;
+        self.density_estimation = density_estimation;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    pub fn reconstruction_filter(&self) -> ReconstructionFilter
+    {
+        self.reconstruction_filter
+    }
+
+    /// Only changes how the supersampled histogram is brought down to the output resolution.
+    pub fn set_reconstruction_filter(&mut self, reconstruction_filter: ReconstructionFilter) -> Result<(), String>
+    {
+        reconstruction_filter.validate()?;
+        self.reconstruction_filter = reconstruction_filter;
+
+        Ok(())
+    }
+
+    pub fn genome(&self) -> Genome
+    {
+        Genome
+        {
+            transforms: self.ifs.transforms().to_vec(),
+            final_transform: self.ifs.final_transform.clone(),
+            symmetry: self.ifs.symmetry,
+            camera: self.camera,
+            palette: self.palette.clone(),
+            tone_mapping: self.tone_mapping,
+            density_estimation: self.density_estimation,
+            reconstruction_filter: self.reconstruction_filter,
+            ..Genome::default()
+        }
+    }
+
+    /// Replaces the IFS and restarts the accumulation from scratch.
+    pub fn set_genome(&mut self, genome: Genome) -> Result<(), String>
+    {
+        genome.validate()?;
+
+        self.ifs = Ifs::new(genome.transforms, genome.symmetry, genome.final_transform)?;
+        self.camera = genome.camera;
+        self.palette = genome.palette;
+        self.tone_mapping = genome.tone_mapping;
+        self.density_estimation = genome.density_estimation;
+        self.reconstruction_filter = genome.reconstruction_filter;
+        self.state = State::new(&self.dimensions, self.super_sampling_factor, &self.camera, &mut self.rng);
+        self.workers.clear();
+
+        Ok(())
+    }
+
+    /// Swaps the IFS and palette but keeps the accumulation going, so that the samples of several
+    /// genomes add up in one image, as motion blur does. The view and display settings are left alone.
+    pub fn morph(&mut self, genome: &Genome) -> Result<(), String>
+    {
+        genome.validate()?;
+
+        self.ifs = Ifs::new(genome.transforms.clone(), genome.symmetry, genome.final_transform.clone())?;
+        self.palette = genome.palette.clone();
+
+        Ok(())
+    }
+
+    pub fn load_genome(&mut self, path: &Path) -> Result<(), String>
+    {
+        self.set_genome(Genome::load(path)?)
+    }
+
+    pub fn save_genome(&self, path: &Path) -> Result<(), String>
+    {
+        self.genome().save(path)
+    }
+
+    /// Saves everything needed to carry on accumulating later: the genome, the histogram, the
+    /// walkers and the exact state of their random streams, so that a simulator loaded back carries
+    /// on with the same samples this one would have drawn.
+    pub fn save_checkpoint(&self, path: &Path) -> Result<(), String>
+    {
+        checkpoint::save(path, |writer| self.write_checkpoint(writer))
+    }
+
+    /// Simulator saved by `save_checkpoint`, with as many threads as it had then and the scalar kernel.
+    pub fn load_checkpoint(path: &Path) -> Result<Simulator, String>
+    {
+        checkpoint::load(path, Simulator::read_checkpoint)
+    }
+
+    /// The worker histograms are merged after every `iterate`, so only their walkers are saved.
+    pub fn write_checkpoint<W: Write>(&self, writer: &mut Writer<W>) -> Result<(), String>
+    {
+        writer.str(&self.genome().to_json()?)?;
+        writer.u32(self.dimensions.width)?;
+        writer.u32(self.dimensions.height)?;
+        writer.u32(self.super_sampling_factor as u32)?;
+        writer.usize(self.preparation_rounds)?;
+        writer.u64(self.seed)?;
+        write_rng(&self.rng, writer)?;
+        self.state.write_walker(writer)?;
+        self.state.write_histogram(writer)?;
+        writer.usize(self.workers.len())?;
+
+        for worker in self.workers.iter()
+        {
+            write_rng(&worker.rng, writer)?;
+            worker.state.write_walker(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn read_checkpoint<R: Read>(reader: &mut Reader<R>) -> Result<Simulator, String>
+    {
+        let genome = Genome::from_json(&reader.str()?)?;
+        let dimensions = Dimensions { width: reader.u32()?, height: reader.u32()? };
+        let factor = reader.u32()?;
+        let super_sampling_factor = SuperSamplingFactor::from_factor(factor as usize).ok_or(format!("Invalid supersampling {} in checkpoint", factor))?;
+        let preparation_rounds = reader.usize()?;
+        let seed = reader.u64()?;
+
+        if dimensions.area() == 0
+        {
+            return Err(String::from("Empty dimensions in checkpoint"));
+        }
+
+        let mut simulator = Simulator::with_seed(dimensions, preparation_rounds, super_sampling_factor, seed);
+
+        simulator.set_genome(genome)?;
+        simulator.rng = read_rng(reader)?;
+        simulator.state.read_walker(reader)?;
+        simulator.state.read_histogram(reader)?;
+
+        for _ in 0..reader.usize()?
+        {
+            let rng = read_rng(reader)?;
+            // The walker is overwritten, so the stream picking its start is thrown away.
+            let mut state = State::new(&dimensions, super_sampling_factor, &simulator.camera, &mut FlameRng::seed_from_u64(0));
+
+            state.read_walker(reader)?;
+            simulator.workers.push(Worker { state, rng });
+        }
+
+        simulator.thread_count = simulator.workers.len().max(1);
+
+        Ok(simulator)
+    }
+
+    #[allow(dead_code)]
+    fn generate_ifs<R: Rng + ?Sized>(rng: &mut R) -> Vec<FunctionElement>
+    {
+        vec![
+            FunctionElement::random(AugmentedMat2x2::rand(rng, -1., 1.), rng),
+            FunctionElement::random(AugmentedMat2x2::rand(rng, -1., 1.), rng),
+            FunctionElement::random(AugmentedMat2x2::rand(rng, -1., 1.), rng),
+        ]
+    }
+
+    fn generate_norm_ifs<R: Rng + ?Sized>(rng: &mut R) -> Vec<FunctionElement>
+    {
+        vec![
+            FunctionElement::random(AugmentedMat2x2::rand_std(rng), rng),
+            FunctionElement::random(AugmentedMat2x2::rand(rng, -1., 1.), rng),
+            FunctionElement::random(AugmentedMat2x2::rand_std(rng), rng),
+        ]
+    }
+
+    /// Switches to a new random flame from the generator, keeping the view and display settings.
+    pub fn reset(&mut self)
+    {
+        match Generator::default().generate(&mut self.rng)
+        {
+            Ok(genome) =>
+            {
+                self.ifs = Ifs::new(genome.transforms, genome.symmetry, genome.final_transform).expect("generated genomes are valid");
+                self.palette = genome.palette;
+            },
+            Err(_) => self.ifs = Ifs::from_transforms(Simulator::generate_norm_ifs(&mut self.rng)),
+        }
+
+        self.state = State::new(&self.dimensions, self.super_sampling_factor, &self.camera, &mut self.rng);
+        self.workers.clear();
+    }
+
+    pub fn step(&mut self)
+    {
+        /*let fs = vec![
+            AugmentedMat2x2::new(
+                [[1./2., 0., 0.],
+                 [0., 1./2., 0.]]
+            ),
+            AugmentedMat2x2::new(
+                [[1./2., 0., 1./2.],
+                 [0., 1./2., 0.]]
+            ),
+            AugmentedMat2x2::new(
+                [[1./2., 0., 0.],
+                 [0., 1./2., 1./2.]]
+            )
+        ];*/
+
+        self.state.advance(&self.ifs, &self.palette, self.preparation_rounds, &mut self.rng);
+    }
+
+    /// Runs `samples` iterations of the chaos game, spread over the worker threads. Each worker
+    /// keeps its walker between calls and accumulates into its own histogram, merged afterwards.
+    pub fn iterate(&mut self, samples: usize)
+    {
+        let start = Instant::now();
+
+        self.iterate_with_kernel(samples);
+        self.state.elapsed += start.elapsed();
+    }
+
+    fn iterate_with_kernel(&mut self, samples: usize)
+    {
+        if self.thread_count <= 1
+        {
+            match self.kernel
+            {
+                Kernel::Scalar => (0..samples).for_each(|_| self.step()),
+                Kernel::Batched => self.state.advance_batch(samples, &self.ifs, &self.palette, self.preparation_rounds, &mut self.rng),
+            }
+
+            return;
+        }
+
+        while self.workers.len() < self.thread_count
+        {
+            // Worker streams are derived from the simulator one, so a seed still replays the same render.
+            let mut rng = FlameRng::seed_from_u64(self.rng.gen());
+
+            self.workers.push(Worker
+            {
+                state: State::new(&self.dimensions, self.super_sampling_factor, &self.camera, &mut rng),
+                rng,
+            });
+        }
+
+        let worker_count = self.workers.len();
+        let (ifs, palette, preparation_rounds, kernel) = (&self.ifs, &self.palette, self.preparation_rounds, self.kernel);
+        let workers = &mut self.workers;
+
+        thread::scope(|scope|
+        {
+            for (idx, worker) in workers.iter_mut().enumerate()
+            {
+                let worker_samples = samples / worker_count + usize::from(idx < samples % worker_count);
+
+                scope.spawn(move || match kernel
+                {
+                    Kernel::Scalar =>
+                    {
+                        for _ in 0..worker_samples
+                        {
+                            worker.state.advance(ifs, palette, preparation_rounds, &mut worker.rng);
+                        }
+                    },
+                    Kernel::Batched => worker.state.advance_batch(worker_samples, ifs, palette, preparation_rounds, &mut worker.rng),
+                });
+            }
+        });
+
+        for worker in self.workers.iter_mut()
+        {
+            self.state.merge_histogram(&mut worker.state);
+        }
+    }
+
+    pub fn render(&self) -> RgbaImage
+    {
+        let mut image = RgbaImage::new(self.dimensions);
+
+        self.render_into(&mut image);
+        image
+    }
+
+    /// Tone maps the accumulated histogram into `image`, which must match the simulator dimensions.
+    /// Color goes in the RGB channels and the log density in alpha, so compositing over black
+    /// yields the usual flame look.
+    pub fn render_into(&self, image: &mut RgbaImage)
+    {
+        assert_eq!(image.dimensions(), self.dimensions, "render target dimensions mismatch");
+
+        let max_density = self.state.max_density();
+        let cells = self.state.compute_cells(|d| self.tone_mapping.log_density(d, max_density));
+        let cells = self.density_estimation.apply(cells, &self.state.density_histogram, self.super_sampling_factor as usize);
+        let light = self.state.downsample(&cells, &self.reconstruction_filter);
+
+        self.tone_mapping.apply(&light, image);
+    }
+
+    /// The histogram before tone mapping: average density per pixel in alpha and the matching
+    /// color sums, in [0, 1] per sample, in RGB.
+    pub fn render_linear(&self) -> LinearImage
+    {
+        self.state.compute_subsampled_histogram(|d| d as f64, &self.reconstruction_filter)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::convert::TryInto;
+    use std::time::Duration;
+
+    use rand::rngs::mock::StepRng;
+    use rand::SeedableRng;
+
+    use super::{ FlameRng, FunctionElement, Kernel, Simulator, State, SuperSamplingFactor };
+    use super::super::batch::BATCH_WALKERS;
+    use super::super::camera::Camera;
+    use super::super::checkpoint::{ Reader, Writer };
+    use super::super::color::Rgb;
+    use super::super::density::DensityEstimation;
+    use super::super::filter::{ FilterKind, ReconstructionFilter };
+    use super::super::genome::Genome;
+    use super::super::image::Dimensions;
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+    use super::super::palette::Palette;
+    use super::super::stats::Stats;
+    use super::super::symmetry::Symmetry;
+    use super::super::variations::{ Variation, WeightedVariation };
+
+    fn sierpinski_simulator(dimensions: Dimensions) -> Simulator
+    {
+        let mut simulator = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 42);
+        let palette = Palette::from_colors(&[Rgb::new(200, 100, 50)]).unwrap();
+
+        simulator.set_genome(Genome { palette, ..Genome::sierpinski() }).unwrap();
+
+        simulator
+    }
+
+    #[test]
+    fn test_function_element_apply()
+    {
+        let mut rng = StepRng::new(0, 0);
+        let mut function = FunctionElement::new(AugmentedMat2x2::new([[2., 0., 0.], [0., 2., 0.]]), 0.);
+        let p = Vec2D { x: 0.15, y: 0.2 };
+
+        assert_eq!(function.apply(p, &mut rng), Vec2D { x: 0.3, y: 0.4 });
+
+        function.variations = vec![WeightedVariation { variation: Variation::Spherical, weight: 0.5 }];
+        let varied = function.apply(p, &mut rng);
+
+        assert!((varied.x - 0.6).abs() < 1e-12 && (varied.y - 0.8).abs() < 1e-12);
+
+        function.post_transform = Some(AugmentedMat2x2::new([[1., 0., 1.], [0., -1., 0.]]));
+        let post = function.apply(p, &mut rng);
+
+        assert!((post.x - 1.6).abs() < 1e-12 && (post.y + 0.8).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_color_coordinate_follows_color_speed()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        genome.transforms.truncate(1);
+        genome.transforms[0].color = 1.;
+        genome.transforms[0].color_speed = 0.25;
+        simulator.set_genome(genome).unwrap();
+        simulator.state.current_color = 0.;
+
+        simulator.step();
+        assert_eq!(simulator.state.current_color, 0.25);
+        simulator.step();
+        assert_eq!(simulator.state.current_color, 0.4375);
+    }
+
+    #[test]
+    fn test_plot_uses_palette()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let palette = Palette::from_gradient(&[(0., Rgb::new(255, 0, 0)), (1., Rgb::new(0, 0, 255))]).unwrap();
+
+        simulator.set_palette(palette.clone());
+        assert_eq!(simulator.genome().palette, palette);
+
+        simulator.preparation_rounds = 0;
+        simulator.state.current_coordinates = Vec2D { x: 0., y: 0. };
+        simulator.state.current_color = 0.;
+        simulator.step();
+
+        // Whichever function was picked, the walker moved halfway to its color before being plotted.
+        let color = simulator.state.color_histogram.iter().find(|c| c.iter().sum::<f64>() > 0.).unwrap();
+        let expected: Vec<[f64; 3]> = [0., 0.25, 0.5]
+            .iter()
+            .map(|c| palette.color(*c))
+            .map(|c| [c.r as f64, c.g as f64, c.b as f64])
+            .collect();
+
+        assert!(expected.contains(color), "{:?}", color);
+    }
+
+    #[test]
+    fn test_genome_round_trip()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let genome = simulator.genome();
+
+        for _ in 0..100
+        {
+            simulator.step();
+        }
+
+        simulator.reset();
+        assert_ne!(simulator.genome(), genome);
+
+        simulator.set_genome(genome.clone()).unwrap();
+        assert_eq!(simulator.genome(), genome);
+        assert_eq!(simulator.state.iter_count, 0);
+        assert!(simulator.set_genome(Genome::default()).is_err());
+    }
+
+    #[test]
+    fn test_final_transform_only_moves_plotted_points()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        // Pushes every plotted point out of the frame, on the far right.
+        genome.final_transform = Some(FunctionElement::new(AugmentedMat2x2::new([[1., 0., 10.], [0., 1., 0.]]), 0.));
+        simulator.set_genome(genome.clone()).unwrap();
+        assert_eq!(simulator.genome(), genome);
+
+        simulator.iterate(1000);
+
+        assert!(simulator.state.density_histogram.iter().all(|d| *d == 0));
+        // The walker is still on the Sierpinski triangle.
+        assert!(simulator.state.current_coordinates.x.abs() <= 1.);
+    }
+
+    #[test]
+    fn test_symmetry_adds_transforms()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        genome.symmetry = Symmetry::Dihedral(3);
+        simulator.set_genome(genome.clone()).unwrap();
+
+        assert_eq!(simulator.ifs.functions.len(), 3 + 1 + 2);
+        assert_eq!(simulator.genome(), genome);
+
+        genome.symmetry = Symmetry::Rotational(0);
+        assert!(simulator.set_genome(genome).is_err());
+    }
+
+    #[test]
+    fn test_morph_keeps_accumulating()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        simulator.iterate(1000);
+        genome.transforms.truncate(2);
+        genome.camera.zoom = 2.;
+        simulator.morph(&genome).unwrap();
+        simulator.iterate(1000);
+
+        assert_eq!(simulator.sample_count(), 2000);
+        assert_eq!(simulator.genome().transforms, genome.transforms);
+        assert_eq!(simulator.camera(), Camera::default());
+        assert!(simulator.morph(&Genome::default()).is_err());
+    }
+
+    #[test]
+    fn test_escaped_walker_is_reseeded()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        // Mostly the triangle, but the last map blows up the walker now and then.
+        genome.transforms[2].application = AugmentedMat2x2::new([[1e6, 0., 0.], [0., 1e6, 0.]]);
+        genome.transforms[2].weight = 0.2;
+        simulator.set_genome(genome).unwrap();
+        simulator.set_thread_count(2);
+        simulator.iterate(20000);
+
+        assert!(simulator.bad_sample_count() > 0);
+        assert!(simulator.plotted_count() > 0);
+        assert!(simulator.workers.iter().all(|w| w.state.current_coordinates.x.is_finite()));
+
+        simulator.set_camera(Camera::default());
+        assert_eq!(simulator.bad_sample_count(), 0);
+    }
+
+    #[test]
+    fn test_set_camera_restarts_accumulation()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let camera = Camera { center: Vec2D { x: 0.5, y: 0.5 }, zoom: 2., rotation: 0.3 };
+
+        simulator.iterate(1000);
+        simulator.set_camera(camera);
+
+        assert_eq!(simulator.camera(), camera);
+        assert_eq!(simulator.genome().camera, camera);
+        assert_eq!(simulator.state.iter_count, 0);
+        assert!(simulator.state.density_histogram.iter().all(|d| *d == 0));
+
+        // Zoomed twice on the top right quarter, plotting (1, 1) lands in the last cell.
+        simulator.set_camera(Camera { rotation: 0., ..camera });
+        simulator.state.plot(Vec2D { x: 0.99, y: 0.99 }, Rgb::BLACK);
+
+        assert_eq!(simulator.state.density_histogram[63], 1);
+    }
+
+    #[test]
+    fn test_same_seed_gives_identical_histograms()
+    {
+        let dimensions = Dimensions { width: 40, height: 30 };
+        let mut first = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 1234);
+        let mut second = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 1234);
+        let mut other = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 4321);
+
+        assert_eq!(first.genome(), second.genome());
+        assert_ne!(first.genome(), other.genome());
+
+        for _ in 0..50000
+        {
+            first.step();
+            second.step();
+            other.step();
+        }
+
+        assert_eq!(first.state.density_histogram, second.state.density_histogram);
+        assert_eq!(first.state.color_histogram, second.state.color_histogram);
+        assert_eq!(first.render().pixels(), second.render().pixels());
+        assert_ne!(first.state.density_histogram, other.state.density_histogram);
+        assert_eq!(Simulator::random_genome(1234), first.genome());
+
+        first.reset();
+        second.reset();
+
+        assert_eq!(first.genome(), second.genome());
+    }
+
+    #[test]
+    fn test_weighted_selection()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        // Only the first map is ever picked, so the walker converges to its fixed point (-1, -1).
+        genome.transforms[1].weight = 0.;
+        genome.transforms[2].weight = 0.;
+        simulator.set_genome(genome.clone()).unwrap();
+
+        for _ in 0..200
+        {
+            simulator.step();
+        }
+
+        assert!((simulator.state.current_coordinates.x + 1.).abs() < 1e-9);
+        assert!((simulator.state.current_coordinates.y + 1.).abs() < 1e-9);
+
+        genome.transforms[0].weight = 0.;
+        assert!(simulator.set_genome(genome).is_err());
+    }
+
+    #[test]
+    fn test_determinant_weight()
+    {
+        assert_eq!(FunctionElement::determinant_weight(&AugmentedMat2x2::new([[0.5, 0., 3.], [0., -0.5, 1.]])), 0.25);
+        assert_eq!(FunctionElement::determinant_weight(&AugmentedMat2x2::new([[1., 2., 0.], [2., 4., 0.]])), 1e-3);
+    }
+
+    #[test]
+    fn test_parallel_iterate()
+    {
+        let dimensions = Dimensions { width: 40, height: 30 };
+        let mut first = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 99);
+        let mut second = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 99);
+
+        first.set_thread_count(4);
+        second.set_thread_count(4);
+
+        first.iterate(20000);
+        first.iterate(20001);
+        second.iterate(40001);
+
+        let first_total: usize = first.state.density_histogram.iter().sum();
+
+        // Only samples past the preparation rounds of each walker land in the histogram.
+        assert!(first_total > 0 && first_total <= 40001 - 4 * 10);
+        assert!(first.workers.iter().all(|w| w.state.density_histogram.iter().all(|d| *d == 0)));
+        assert_eq!(first.workers.iter().map(|w| w.state.iter_count).sum::<usize>(), 40001);
+        assert_eq!(second.workers.iter().map(|w| w.state.iter_count).sum::<usize>(), 40001);
+        // Batches do not change what each walker does, only when its histogram gets merged.
+        assert_eq!(first.state.density_histogram, second.state.density_histogram);
+    }
+
+    #[test]
+    fn test_parallel_iterate_is_reproducible()
+    {
+        let dimensions = Dimensions { width: 40, height: 30 };
+        let mut first = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 5);
+        let mut second = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 5);
+
+        first.set_thread_count(3);
+        second.set_thread_count(3);
+        first.iterate(30000);
+        second.iterate(30000);
+
+        assert_eq!(first.state.density_histogram, second.state.density_histogram);
+        assert_eq!(first.state.color_histogram, second.state.color_histogram);
+        assert_eq!(first.sample_count(), 30000);
+
+        first.set_camera(Camera::default());
+
+        assert_eq!(first.sample_count(), 0);
+    }
+
+    #[test]
+    fn test_batched_kernel_matches_scalar()
+    {
+        let dimensions = Dimensions { width: 64, height: 64 };
+        let mut scalar = sierpinski_simulator(dimensions);
+        let mut batched = sierpinski_simulator(dimensions);
+
+        batched.set_kernel(Kernel::Batched);
+        scalar.iterate(200_000);
+        batched.iterate(150_000);
+        batched.iterate(50_000);
+
+        assert_eq!(batched.sample_count(), 200_000);
+        assert!(batched.plotted_count() <= 200_000 - BATCH_WALKERS * 10);
+        assert!((batched.coverage() - scalar.coverage()).abs() < 0.02, "{} vs {}", batched.coverage(), scalar.coverage());
+
+        // Both spread their samples the same way over the triangle.
+        let distance: f64 = scalar.state.density_histogram
+            .iter()
+            .zip(batched.state.density_histogram.iter())
+            .map(|(s, b)| (*s as f64 / scalar.plotted_count() as f64 - *b as f64 / batched.plotted_count() as f64).abs())
+            .sum();
+
+        assert!(distance < 0.1, "histograms differ by {}", distance);
+        assert_eq!(batched.bad_sample_count(), 0);
+    }
+
+    #[test]
+    fn test_parallel_batched_kernel_is_reproducible()
+    {
+        let dimensions = Dimensions { width: 40, height: 30 };
+        let run = ||
+        {
+            let mut simulator = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::None, 5);
+
+            simulator.set_kernel(Kernel::Batched);
+            simulator.set_thread_count(3);
+            simulator.iterate(30000);
+            simulator
+        };
+        let (first, second) = (run(), run());
+
+        assert_eq!(first.state.density_histogram, second.state.density_histogram);
+        assert_eq!(first.state.color_histogram, second.state.color_histogram);
+        assert_eq!(first.sample_count(), 30000);
+    }
+
+    #[test]
+    fn test_batched_kernel_reseeds_escaped_walkers()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+        let mut genome = simulator.genome();
+
+        genome.transforms[2].application = AugmentedMat2x2::new([[1e6, 0., 0.], [0., 1e6, 0.]]);
+        genome.transforms[2].weight = 0.2;
+        simulator.set_genome(genome).unwrap();
+        simulator.set_kernel(Kernel::Batched);
+        simulator.iterate(20000);
+
+        assert!(simulator.bad_sample_count() > 0);
+        assert!(simulator.plotted_count() > 0);
+    }
+
+    #[test]
+    fn test_stats_account_for_every_sample()
+    {
+        for kernel in [Kernel::Scalar, Kernel::Batched].iter()
+        {
+            let mut simulator = sierpinski_simulator(Dimensions { width: 32, height: 32 });
+            let mut genome = simulator.genome();
+
+            // Only the lower right corner of the triangle stays in view.
+            genome.camera = Camera { center: Vec2D { x: 0.5, y: -0.5 }, zoom: 4., rotation: 0. };
+            genome.symmetry = Symmetry::Rotational(2);
+            simulator.set_genome(genome).unwrap();
+            simulator.set_kernel(*kernel);
+            simulator.set_thread_count(2);
+            simulator.iterate(60000);
+
+            let stats = simulator.stats();
+
+            assert_eq!(stats.samples, 60000);
+            assert_eq!(stats.plotted, simulator.plotted_count());
+            assert!(stats.plotted > 0 && stats.out_of_frame > stats.plotted, "{:?}", stats);
+            assert_eq!(stats.bad, 0);
+            assert_eq!(stats.plotted + stats.out_of_frame + stats.preparation(), stats.samples);
+            assert_eq!(stats.transform_hits.len(), 3);
+            assert_eq!(stats.transform_hits.iter().sum::<usize>() + stats.symmetry_hits, stats.samples);
+            assert!(stats.transform_hits.iter().all(|hits| (*hits as f64 / 60000. - 0.25).abs() < 0.02), "{:?}", stats.transform_hits);
+            assert!(stats.max_density as f64 >= stats.mean_density && stats.mean_density >= 1.);
+            assert_eq!(stats.filled_ratio, simulator.coverage());
+            assert!(stats.elapsed > Duration::ZERO);
+
+            simulator.set_camera(Camera::default());
+            assert_eq!(simulator.stats().samples, 0);
+            assert_eq!(simulator.stats().transform_hits, vec![0; 3]);
+        }
+    }
+
+    #[test]
+    fn test_final_transform_escapes_are_bad()
+    {
+        for kernel in [Kernel::Scalar, Kernel::Batched].iter()
+        {
+            let mut simulator = sierpinski_simulator(Dimensions { width: 16, height: 16 });
+            let far = FunctionElement::new(AugmentedMat2x2::new([[1., 0., 1e11], [0., 1., 0.]]), 0.);
+
+            simulator.set_genome(Genome { final_transform: Some(far), ..simulator.genome() }).unwrap();
+            simulator.set_kernel(*kernel);
+            simulator.iterate(20000);
+
+            let stats = simulator.stats();
+
+            assert_eq!(stats.plotted, 0);
+            assert_eq!(stats.out_of_frame, 0);
+            assert!(stats.bad > 0);
+            assert_eq!(stats.bad + stats.preparation(), stats.samples);
+        }
+    }
+
+    fn checkpoint(simulator: &Simulator) -> Vec<u8>
+    {
+        let mut writer = Writer::new(vec![]).unwrap();
+
+        simulator.write_checkpoint(&mut writer).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn test_resumed_checkpoint_carries_on()
+    {
+        for (kernel, threads) in [(Kernel::Scalar, 1), (Kernel::Scalar, 3), (Kernel::Batched, 1), (Kernel::Batched, 2)].iter()
+        {
+            let mut simulator = sierpinski_simulator(Dimensions { width: 24, height: 16 });
+            let mut unsaved = sierpinski_simulator(Dimensions { width: 24, height: 16 });
+
+            for simulator in [&mut simulator, &mut unsaved].iter_mut()
+            {
+                simulator.set_kernel(*kernel);
+                simulator.set_thread_count(*threads);
+                simulator.iterate(20000);
+            }
+
+            let bytes = checkpoint(&simulator);
+            let mut resumed = Simulator::read_checkpoint(&mut Reader::new(&bytes[..]).unwrap()).unwrap();
+
+            assert_eq!(resumed.genome(), simulator.genome());
+            assert_eq!(resumed.state.density_histogram, simulator.state.density_histogram);
+
+            resumed.set_kernel(*kernel);
+            simulator.iterate(30000);
+            resumed.iterate(30000);
+            unsaved.iterate(30000);
+
+            // Saving leaves the random streams alone, so a seeded render does not depend on when checkpoints happen.
+            assert_eq!(unsaved.state.density_histogram, simulator.state.density_histogram);
+
+            assert_eq!(resumed.state.density_histogram, simulator.state.density_histogram);
+            assert_eq!(resumed.state.color_histogram, simulator.state.color_histogram);
+            assert_eq!(resumed.render().pixels(), simulator.render().pixels());
+            assert_eq!(Stats { elapsed: Duration::ZERO, ..resumed.stats() }, Stats { elapsed: Duration::ZERO, ..simulator.stats() });
+        }
+    }
+
+    #[test]
+    fn test_corrupted_checkpoint_fails()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 8, height: 8 });
+
+        simulator.iterate(100);
+
+        let bytes = checkpoint(&simulator);
+        let read = |bytes: &[u8]| Reader::new(bytes).and_then(|mut reader| Simulator::read_checkpoint(&mut reader)).err();
+
+        assert_eq!(read(&bytes[..bytes.len() - 1]), Some(String::from("Truncated checkpoint")));
+
+        // The width, just after the genome.
+        let width = 20 + u64::from_le_bytes(bytes[12..20].try_into().unwrap()) as usize;
+        let mut wider = bytes.clone();
+
+        wider[width] = 9;
+        assert_eq!(read(&wider), Some(String::from("The checkpoint histogram does not match its dimensions")));
+    }
+
+    #[test]
+    fn test_thread_count_change_keeps_samples()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 16, height: 16 });
+
+        simulator.set_thread_count(2);
+        simulator.iterate(10000);
+        simulator.set_thread_count(1);
+        simulator.iterate(5000);
+
+        let stats = simulator.stats();
+
+        assert_eq!(stats.samples, 15000);
+        assert_eq!(stats.transform_hits.iter().sum::<usize>(), 15000);
+        assert_eq!(stats.plotted, simulator.plotted_count());
+    }
+
+    fn plot(state: &mut State, x: f64, y: f64, color: Rgb)
+    {
+        state.plot(Vec2D { x, y }, color);
+    }
+
+    #[test]
+    fn test_plot_non_square()
+    {
+        let mut state = State::new(&Dimensions { width: 4, height: 2 }, SuperSamplingFactor::X2, &Camera::default(), &mut FlameRng::seed_from_u64(0));
+
+        assert_eq!(state.plot_dimensions, Dimensions { width: 8, height: 4 });
+        assert_eq!(state.density_histogram.len(), 32);
+
+        // The default camera fits [-1, 1] in the height, so the wider plot shows x in [-2, 2].
+        plot(&mut state, -2., -1., Rgb::new(1, 2, 3));
+        plot(&mut state, 1.9, -0.9, Rgb::new(10, 0, 0));
+        plot(&mut state, 1.9, -0.9, Rgb::new(20, 0, 4));
+        plot(&mut state, -1.9, 0.9, Rgb::BLACK);
+        plot(&mut state, 2., 0., Rgb::BLACK);
+        plot(&mut state, 0., -1.01, Rgb::BLACK);
+        plot(&mut state, f64::NAN, 0., Rgb::BLACK);
+
+        assert_eq!(state.density_histogram.iter().sum::<usize>(), 4);
+        assert_eq!(state.density_histogram[0], 1);
+        assert_eq!(state.color_histogram[0], [1., 2., 3.]);
+        assert_eq!(state.density_histogram[7], 2);
+        assert_eq!(state.color_histogram[7], [30., 0., 4.]);
+        assert_eq!(state.density_histogram[3 * 8], 1);
+    }
+
+    #[test]
+    fn test_subsampled_histogram_non_square()
+    {
+        let mut state = State::new(&Dimensions { width: 3, height: 2 }, SuperSamplingFactor::X2, &Camera::default(), &mut FlameRng::seed_from_u64(0));
+        let red = Rgb::new(255, 0, 0);
+        let blue = Rgb::new(0, 0, 255);
+
+        // Plot is 6x4 and the default camera keeps the aspect ratio, so cells are half a unit wide and high.
+        plot(&mut state, -1.4, -0.9, red);
+        plot(&mut state, -1.4, -0.9, red);
+        plot(&mut state, -0.75, -0.4, blue);
+        plot(&mut state, 1.4, 0.9, blue);
+
+        let light = state.compute_subsampled_histogram(|d| d as f64, &ReconstructionFilter::default());
+
+        assert_eq!(light.dimensions(), Dimensions { width: 3, height: 2 });
+        assert_eq!(light.pixels()[0], [0.5, 0., 0.25, 0.75]);
+        assert_eq!(light.pixels()[1], [0.; 4]);
+        assert_eq!(light.pixels()[5], [0., 0., 0.25, 0.25]);
+
+        // Brightness, not density, is averaged over the block: a lone sample only covers a quarter of its pixel.
+        let coverage: Vec<f64> = state.compute_subsampled_histogram(|_| 1., &ReconstructionFilter::default()).pixels().iter().map(|p| p[3]).collect();
+
+        assert_eq!(coverage, vec![0.5, 0., 0., 0., 0., 0.25]);
+    }
+
+    #[test]
+    fn test_merge_sums_colors()
+    {
+        let dimensions = Dimensions { width: 2, height: 2 };
+        let mut state = State::new(&dimensions, SuperSamplingFactor::None, &Camera::default(), &mut FlameRng::seed_from_u64(0));
+        let mut other = State::new(&dimensions, SuperSamplingFactor::None, &Camera::default(), &mut FlameRng::seed_from_u64(1));
+
+        plot(&mut state, -0.5, -0.5, Rgb::new(100, 0, 0));
+        plot(&mut other, -0.5, -0.5, Rgb::new(0, 50, 0));
+        plot(&mut other, 0.5, 0.5, Rgb::new(0, 0, 10));
+
+        state.merge_histogram(&mut other);
+
+        assert_eq!(state.density_histogram, vec![2, 0, 0, 1]);
+        assert_eq!(state.color_histogram[0], [100., 50., 0.]);
+        assert_eq!(state.color_histogram[3], [0., 0., 10.]);
+        assert_eq!(other.density_histogram, vec![0; 4]);
+        assert_eq!(other.color_histogram, vec![[0.; 3]; 4]);
+    }
+
+    #[test]
+    fn test_supersampled_render()
+    {
+        let dimensions = Dimensions { width: 24, height: 16 };
+        let mut simulator = Simulator::with_seed(dimensions, 10, SuperSamplingFactor::X4, 8);
+
+        simulator.iterate(200000);
+
+        let image = simulator.render();
+
+        assert_eq!(image.dimensions(), dimensions);
+        assert!(image.pixels().chunks_exact(4).any(|p| p[3] > 0));
+    }
+
+    /// Pixels lit by a sparse render, before and after `change`, which can only widen the splats.
+    fn sparse_lit_pixels<F: FnOnce(&mut Simulator)>(change: F) -> (usize, usize)
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 32, height: 32 });
+        let lit_pixels = |simulator: &Simulator| simulator.render().pixels().chunks_exact(4).filter(|p| p[3] > 0).count();
+
+        simulator.iterate(200);
+
+        let before = lit_pixels(&simulator);
+
+        change(&mut simulator);
+        (before, lit_pixels(&simulator))
+    }
+
+    #[test]
+    fn test_density_estimation_smooths_sparse_render()
+    {
+        let (sharp, smoothed) = sparse_lit_pixels(|simulator|
+        {
+            simulator.set_density_estimation(DensityEstimation { min_radius: 0., max_radius: 3., curve: 0.4 }).unwrap();
+            assert_eq!(simulator.genome().density_estimation.max_radius, 3.);
+            assert!(simulator.set_density_estimation(DensityEstimation { min_radius: 0., max_radius: -1., curve: 0.4 }).is_err());
+        });
+
+        assert!(smoothed > sharp);
+    }
+
+    #[test]
+    fn test_reconstruction_filter_widens_render()
+    {
+        let gaussian = ReconstructionFilter { kind: FilterKind::Gaussian, radius: 1.5 };
+        let (sharp, filtered) = sparse_lit_pixels(|simulator|
+        {
+            simulator.set_reconstruction_filter(gaussian).unwrap();
+            assert_eq!(simulator.genome().reconstruction_filter, gaussian);
+            assert!(simulator.set_reconstruction_filter(ReconstructionFilter { kind: FilterKind::Box, radius: -1. }).is_err());
+        });
+
+        assert!(filtered > sharp);
+    }
+
+    #[test]
+    fn test_render_without_samples_is_transparent()
+    {
+        let simulator = Simulator::new(Dimensions { width: 16, height: 8 }, 10, SuperSamplingFactor::None);
+        let image = simulator.render();
+
+        assert_eq!(image.dimensions(), Dimensions { width: 16, height: 8 });
+        assert!(image.pixels().iter().all(|c| *c == 0));
+    }
+
+    #[test]
+    fn test_headless_render()
+    {
+        let mut simulator = sierpinski_simulator(Dimensions { width: 32, height: 32 });
+
+        for _ in 0..20000
+        {
+            simulator.step();
+        }
+
+        let image = simulator.render();
+        let lit_pixels: Vec<&[u8]> = image.pixels().chunks_exact(4).filter(|p| p[3] > 0).collect();
+
+        assert!(!lit_pixels.is_empty());
+        assert!(lit_pixels.len() < 32 * 32);
+        assert!(lit_pixels.iter().all(|p| p[..3] == [200, 100, 50]));
+    }
+}
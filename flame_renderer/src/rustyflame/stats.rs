@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use super::progress;
+
+/// What went into an accumulation, from the time it last restarted.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stats
+{
+    /// Iterations run, over all walkers.
+    pub samples: usize,
+    /// Samples which landed in the frame.
+    pub plotted: usize,
+    /// Samples past the preparation rounds of their walker which missed the frame.
+    pub out_of_frame: usize,
+    /// Samples which sent their walker, or its point through the final transform, to infinity or NaN.
+    pub bad: usize,
+    /// Times each transform of the genome was picked, in genome order.
+    pub transform_hits: Vec<usize>,
+    /// Times any of the transforms added by the symmetry was picked.
+    pub symmetry_hits: usize,
+    /// Samples in the densest histogram cell.
+    pub max_density: usize,
+    /// Mean samples of the histogram cells reached at least once.
+    pub mean_density: f64,
+    /// Fraction of the histogram cells reached at least once.
+    pub filled_ratio: f64,
+    /// Time spent iterating.
+    pub elapsed: Duration,
+}
+
+impl Stats
+{
+    /// Samples of walkers still in their preparation rounds, neither plotted nor missed.
+    pub fn preparation(&self) -> usize
+    {
+        self.samples.saturating_sub(self.plotted + self.out_of_frame + self.bad)
+    }
+
+    pub fn samples_per_second(&self) -> f64
+    {
+        match self.elapsed.as_secs_f64()
+        {
+            seconds if seconds > 0. => self.samples as f64 / seconds,
+            _ => 0.,
+        }
+    }
+
+    /// A few lines for humans.
+    pub fn report(&self) -> Vec<String>
+    {
+        let share = |count: usize| 100. * count as f64 / self.samples.max(1) as f64;
+        let hits: Vec<String> = self.transform_hits
+            .iter()
+            .enumerate()
+            .map(|(index, hits)| format!("{}: {:.1}%", index + 1, share(*hits)))
+            .chain((self.symmetry_hits > 0).then(|| format!("symmetry: {:.1}%", share(self.symmetry_hits))))
+            .collect();
+
+        vec!
+        [
+            format!
+            (
+                "Samples: {}, {:.1}% plotted, {:.1}% out of frame, {:.1}% preparing, {} bad",
+                progress::format_count(self.samples), share(self.plotted), share(self.out_of_frame), share(self.preparation()), self.bad
+            ),
+            format!("Transforms picked: {}", hits.join("  ")),
+            format!("Density: max {}, mean {:.1} over {:.1}% of the cells", self.max_density, self.mean_density, 100. * self.filled_ratio),
+            format!("Speed: {} samples/s", progress::format_count(self.samples_per_second() as usize)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::time::Duration;
+
+    use super::Stats;
+
+    #[test]
+    fn test_report()
+    {
+        let stats = Stats
+        {
+            samples: 2000,
+            plotted: 1500,
+            out_of_frame: 300,
+            bad: 100,
+            transform_hits: vec![1000, 500],
+            symmetry_hits: 500,
+            max_density: 12,
+            mean_density: 3.25,
+            filled_ratio: 0.5,
+            elapsed: Duration::from_millis(500),
+        };
+
+        assert_eq!(stats.preparation(), 100);
+        assert_eq!(stats.samples_per_second(), 4000.);
+        assert_eq!
+        (
+            stats.report(),
+            vec!
+            [
+                "Samples: 2.0k, 75.0% plotted, 15.0% out of frame, 5.0% preparing, 100 bad",
+                "Transforms picked: 1: 50.0%  2: 25.0%  symmetry: 25.0%",
+                "Density: max 12, mean 3.2 over 50.0% of the cells",
+                "Speed: 4.0k samples/s",
+            ]
+        );
+        assert_eq!(Stats::default().samples_per_second(), 0.);
+    }
+}
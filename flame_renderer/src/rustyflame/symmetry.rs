@@ -0,0 +1,144 @@
+use std::f64::consts::PI;
+
+use serde::{ Deserialize, Serialize };
+
+use super::math::AugmentedMat2x2;
+use super::simulator::FunctionElement;
+
+/// More copies than this are indistinguishable from a continuous rotation anyway.
+const MAX_ORDER: u32 = 360;
+
+/// Symmetry added to a flame by extra transforms, generated like flam3 does: rotations by
+/// multiples of a full turn over the order, plus a mirror across the y axis when dihedral.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Symmetry
+{
+    #[default]
+    None,
+    Rotational(u32),
+    Dihedral(u32),
+}
+
+impl Symmetry
+{
+    /// flam3 convention: n > 1 is n-fold rotational, n < 0 is |n|-fold dihedral, 0 and 1 are none.
+    pub fn from_flam3(symmetry: i32) -> Symmetry
+    {
+        match symmetry
+        {
+            s if s > 1 => Symmetry::Rotational(s as u32),
+            s if s < 0 => Symmetry::Dihedral(s.unsigned_abs()),
+            _ => Symmetry::None,
+        }
+    }
+
+    pub fn to_flam3(&self) -> i32
+    {
+        match self
+        {
+            Symmetry::None => 1,
+            Symmetry::Rotational(order) => *order as i32,
+            Symmetry::Dihedral(order) => -(*order as i32),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String>
+    {
+        match self
+        {
+            Symmetry::Rotational(0) | Symmetry::Dihedral(0) => Err(String::from("Symmetry order must be at least 1")),
+            Symmetry::Rotational(order) | Symmetry::Dihedral(order) if *order > MAX_ORDER =>
+            {
+                Err(format!("Symmetry order {} is too large, at most {} is supported", order, MAX_ORDER))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Transforms to add to the IFS. They have a null color speed, so that symmetric copies keep
+    /// the colors of the original, and a weight of 1 like in flam3.
+    pub fn transforms(&self) -> Vec<FunctionElement>
+    {
+        let (order, mirrored) = match *self
+        {
+            Symmetry::None => return vec![],
+            Symmetry::Rotational(order) => (order, false),
+            Symmetry::Dihedral(order) => (order, true),
+        };
+
+        let symmetric = |application: AugmentedMat2x2, color: f64| FunctionElement { color_speed: 0., ..FunctionElement::new(application, color) };
+        let mut transforms = vec![];
+
+        if mirrored
+        {
+            transforms.push(symmetric(AugmentedMat2x2::scale(-1., 1.), 1.));
+        }
+
+        for k in 1..order
+        {
+            let color = if order < 3 { 0. } else { (k - 1) as f64 / (order - 2) as f64 };
+
+            transforms.push(symmetric(AugmentedMat2x2::rotation(2. * PI * k as f64 / order as f64), color));
+        }
+
+        transforms
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::Symmetry;
+    use super::super::math::Vec2D;
+
+    #[test]
+    fn test_flam3_convention()
+    {
+        for (flam3, symmetry) in [(1, Symmetry::None), (4, Symmetry::Rotational(4)), (-3, Symmetry::Dihedral(3)), (-1, Symmetry::Dihedral(1))].iter()
+        {
+            assert_eq!(Symmetry::from_flam3(*flam3), *symmetry);
+            assert_eq!(symmetry.to_flam3(), *flam3);
+        }
+
+        assert_eq!(Symmetry::from_flam3(0), Symmetry::None);
+        assert!(Symmetry::Rotational(0).validate().is_err());
+        assert!(Symmetry::Rotational(1000).validate().is_err());
+        assert!(Symmetry::Dihedral(2).validate().is_ok());
+    }
+
+    #[test]
+    fn test_rotational_transforms()
+    {
+        let transforms = Symmetry::Rotational(4).transforms();
+        let mut rng = rand::rngs::mock::StepRng::new(0, 0);
+        let p = Vec2D { x: 1., y: 0. };
+
+        assert_eq!(transforms.len(), 3);
+        assert!(transforms.iter().all(|t| t.color_speed == 0. && t.weight == 1.));
+        assert_eq!(transforms.iter().map(|t| t.color).collect::<Vec<f64>>(), vec![0., 0.5, 1.]);
+
+        // Quarter, half and three quarter turns.
+        let images: Vec<Vec2D> = transforms.iter().map(|t| t.apply(p, &mut rng)).collect();
+
+        for (image, expected) in images.iter().zip([(0., 1.), (-1., 0.), (0., -1.)].iter())
+        {
+            assert!((image.x - expected.0).abs() < 1e-12 && (image.y - expected.1).abs() < 1e-12, "{:?}", image);
+        }
+
+        assert!(Symmetry::None.transforms().is_empty());
+        assert!(Symmetry::Rotational(1).transforms().is_empty());
+    }
+
+    #[test]
+    fn test_dihedral_adds_mirror()
+    {
+        let transforms = Symmetry::Dihedral(2).transforms();
+        let mut rng = rand::rngs::mock::StepRng::new(0, 0);
+
+        assert_eq!(transforms.len(), 2);
+        assert_eq!(transforms[0].apply(Vec2D { x: 0.5, y: 0.25 }, &mut rng), Vec2D { x: -0.5, y: 0.25 });
+        assert_eq!(transforms[0].color, 1.);
+        assert_eq!(transforms[1].color, 0.);
+    }
+}
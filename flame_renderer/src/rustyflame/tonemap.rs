@@ -0,0 +1,264 @@
+use serde::{ Deserialize, Serialize };
+
+use super::color::Rgb;
+use super::image::{ LinearImage, LinearPixel, RgbaImage };
+
+/// Turns accumulated light into displayable colors, following flam3: densities are log scaled
+/// per supersampled cell, then each output pixel goes through gamma, vibrancy and highlight handling.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToneMapping
+{
+    /// Scale of the log density, the densest cell having exactly this brightness.
+    pub brightness: f64,
+    pub gamma: f64,
+    /// Below this brightness the gamma curve is blended with a line, so that noise in sparse
+    /// areas is not blown up by the infinite slope of the curve at 0.
+    pub gamma_threshold: f64,
+    /// 1 applies gamma to the brightness only, keeping colors saturated; 0 applies it to each channel.
+    pub vibrancy: f64,
+    /// How much overexposed colors fade to white, negative values simply clipping each channel.
+    pub highlight_power: f64,
+    /// Composited under the flame when set, otherwise the image keeps its alpha channel.
+    pub background: Option<Rgb>,
+}
+
+impl Default for ToneMapping
+{
+    fn default() -> ToneMapping
+    {
+        ToneMapping
+        {
+            brightness: 1.,
+            gamma: 2.2,
+            gamma_threshold: 0.01,
+            vibrancy: 1.,
+            highlight_power: -1.,
+            background: None,
+        }
+    }
+}
+
+impl ToneMapping
+{
+    pub fn validate(&self) -> Result<(), String>
+    {
+        if !(self.brightness.is_finite() && self.brightness > 0.)
+        {
+            return Err(format!("Invalid brightness {}, it must be strictly positive", self.brightness));
+        }
+
+        if !(self.gamma.is_finite() && self.gamma > 0.)
+        {
+            return Err(format!("Invalid gamma {}, it must be strictly positive", self.gamma));
+        }
+
+        if !(self.gamma_threshold.is_finite() && self.gamma_threshold >= 0.)
+        {
+            return Err(format!("Invalid gamma threshold {}, it must be positive", self.gamma_threshold));
+        }
+
+        if !(0. ..=1.).contains(&self.vibrancy)
+        {
+            return Err(format!("Invalid vibrancy {}, it must be in [0, 1]", self.vibrancy));
+        }
+
+        if !self.highlight_power.is_finite()
+        {
+            return Err(format!("Invalid highlight power {}", self.highlight_power));
+        }
+
+        Ok(())
+    }
+
+    /// Brightness of a cell hit `density` times, `max_density` being the densest cell of the plot.
+    /// Single hits stay visible, unlike with a plain logarithm.
+    pub fn log_density(&self, density: usize, max_density: usize) -> f64
+    {
+        if density == 0
+        {
+            return 0.;
+        }
+
+        self.brightness * (density as f64).ln_1p() / (max_density.max(1) as f64).ln_1p()
+    }
+
+    pub fn apply(&self, linear: &LinearImage, image: &mut RgbaImage)
+    {
+        assert_eq!(image.dimensions(), linear.dimensions(), "tone mapping dimensions mismatch");
+
+        for (pixel, light) in image.pixels_mut().chunks_exact_mut(4).zip(linear.pixels())
+        {
+            pixel.copy_from_slice(&self.map_pixel(light));
+        }
+    }
+
+    /// Maps a premultiplied pixel, whose alpha is its log scaled brightness, to straight RGBA8.
+    pub fn map_pixel(&self, light: &LinearPixel) -> [u8; 4]
+    {
+        let alpha = light[3];
+
+        if alpha.is_nan() || alpha <= 0.
+        {
+            return match self.background
+            {
+                Some(bg) => [bg.r, bg.g, bg.b, 255],
+                None => [0; 4],
+            };
+        }
+
+        let inv_gamma = 1. / self.gamma;
+        let gamma_alpha = if alpha < self.gamma_threshold
+        {
+            let frac = alpha / self.gamma_threshold;
+
+            (1. - frac) * alpha * self.gamma_threshold.powf(inv_gamma - 1.) + frac * alpha.powf(inv_gamma)
+        }
+        else
+        {
+            alpha.powf(inv_gamma)
+        };
+
+        let scale = self.vibrancy * gamma_alpha / alpha;
+        let rgb = self.clip_highlights([0, 1, 2].map(|c| scale * light[c] + (1. - self.vibrancy) * light[c].max(0.).powf(inv_gamma)));
+        let coverage = gamma_alpha.min(1.);
+        let to_u8 = |c: f64| (c.clamp(0., 1.) * 255.).round() as u8;
+
+        match self.background
+        {
+            Some(bg) =>
+            {
+                let [r, g, b] = [(rgb[0], bg.r), (rgb[1], bg.g), (rgb[2], bg.b)].map(|(c, bg)| to_u8(c + (1. - coverage) * bg as f64 / 255.));
+
+                [r, g, b, 255]
+            },
+            None =>
+            {
+                let [r, g, b] = rgb.map(|c| to_u8(c / coverage));
+
+                [r, g, b, to_u8(coverage)]
+            },
+        }
+    }
+
+    /// Brings channels above 1 back in range. With a positive highlight power the color keeps its
+    /// hue and loses saturation as it gets more overexposed, like film does.
+    fn clip_highlights(&self, rgb: [f64; 3]) -> [f64; 3]
+    {
+        let max = rgb.iter().copied().fold(0., f64::max);
+
+        if max <= 1. || self.highlight_power < 0.
+        {
+            return rgb.map(|c| c.min(1.));
+        }
+
+        let scale = 1. / max;
+        let saturation = scale.powf(self.highlight_power);
+
+        // Scaling HSV saturation at constant value moves each channel towards the maximum one.
+        rgb.map(|c| 1. - (1. - c * scale) * saturation)
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::ToneMapping;
+    use super::super::color::Rgb;
+    use super::super::image::{ Dimensions, LinearImage, RgbaImage };
+
+    fn gamma(gamma: f64) -> ToneMapping
+    {
+        ToneMapping { gamma, gamma_threshold: 0., ..ToneMapping::default() }
+    }
+
+    #[test]
+    fn test_empty_pixel()
+    {
+        assert_eq!(ToneMapping::default().map_pixel(&[0.; 4]), [0; 4]);
+
+        let with_background = ToneMapping { background: Some(Rgb::new(10, 20, 30)), ..ToneMapping::default() };
+
+        assert_eq!(with_background.map_pixel(&[0.; 4]), [10, 20, 30, 255]);
+    }
+
+    #[test]
+    fn test_gamma_keeps_color()
+    {
+        assert_eq!(gamma(2.).map_pixel(&[0.25, 0.125, 0., 0.25]), [255, 128, 0, 128]);
+        assert_eq!(gamma(1.).map_pixel(&[0.25, 0.125, 0., 0.25]), [255, 128, 0, 64]);
+    }
+
+    #[test]
+    fn test_gamma_threshold_blends_linearly()
+    {
+        let mapping = ToneMapping { gamma: 2., gamma_threshold: 0.04, ..ToneMapping::default() };
+        let alpha = |a: f64| mapping.map_pixel(&[a, a, a, a])[3];
+
+        // Pure gamma would give 0.01^0.5 = 0.1, the threshold brings it down towards 0.01 * 0.04^-0.5 = 0.05.
+        assert_eq!(alpha(0.01), 16);
+        assert_eq!(alpha(0.04), gamma(2.).map_pixel(&[0.04; 4])[3]);
+        assert_eq!(alpha(0.25), 128);
+    }
+
+    #[test]
+    fn test_vibrancy()
+    {
+        let light = [0.04, 0.16, 0., 0.25];
+
+        assert_eq!(gamma(2.).map_pixel(&light), [41, 163, 0, 128]);
+        assert_eq!(ToneMapping { vibrancy: 0., ..gamma(2.) }.map_pixel(&light), [102, 204, 0, 128]);
+    }
+
+    #[test]
+    fn test_highlights()
+    {
+        let overexposed = [2., 1., 0., 2.];
+
+        assert_eq!(gamma(1.).map_pixel(&overexposed), [255, 255, 0, 255]);
+        assert_eq!(ToneMapping { highlight_power: 0., ..gamma(1.) }.map_pixel(&overexposed), [255, 128, 0, 255]);
+        assert_eq!(ToneMapping { highlight_power: 1., ..gamma(1.) }.map_pixel(&overexposed), [255, 191, 128, 255]);
+    }
+
+    #[test]
+    fn test_background_composites_partial_coverage()
+    {
+        let mapping = ToneMapping { background: Some(Rgb::new(0, 0, 255)), ..gamma(1.) };
+
+        assert_eq!(mapping.map_pixel(&[0.5, 0., 0., 0.5]), [128, 0, 128, 255]);
+    }
+
+    #[test]
+    fn test_log_density()
+    {
+        let mapping = ToneMapping { brightness: 2., ..ToneMapping::default() };
+
+        assert_eq!(mapping.log_density(0, 100), 0.);
+        assert!(mapping.log_density(1, 100) > 0.);
+        assert_eq!(mapping.log_density(100, 100), 2.);
+        assert_eq!(mapping.log_density(1, 1), 2.);
+    }
+
+    #[test]
+    fn test_apply()
+    {
+        let dimensions = Dimensions { width: 2, height: 1 };
+        let mut linear = LinearImage::new(dimensions);
+        let mut image = RgbaImage::new(dimensions);
+
+        linear.pixels_mut()[1] = [0., 0.25, 0., 0.25];
+        gamma(2.).apply(&linear, &mut image);
+
+        assert_eq!(image.pixels(), &[0, 0, 0, 0, 0, 255, 0, 128]);
+    }
+
+    #[test]
+    fn test_validate()
+    {
+        assert!(ToneMapping::default().validate().is_ok());
+        assert!(ToneMapping { gamma: 0., ..ToneMapping::default() }.validate().is_err());
+        assert!(ToneMapping { brightness: f64::NAN, ..ToneMapping::default() }.validate().is_err());
+        assert!(ToneMapping { vibrancy: 1.5, ..ToneMapping::default() }.validate().is_err());
+        assert!(ToneMapping { gamma_threshold: -1., ..ToneMapping::default() }.validate().is_err());
+    }
+}
@@ -6,5 +6,5 @@ where
     B: IntoIterator,
     A: IntoIterator, 
 {
-    t.0.into_iter().zip(t.1.into_iter())
-}
\ No newline at end of file
+    t.0.into_iter().zip(t.1)
+}
@@ -0,0 +1,653 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+use serde::{ Deserialize, Serialize };
+
+use super::math::{ AugmentedMat2x2, Vec2D };
+use super::utils;
+
+const EPSILON: f64 = 1e-10;
+
+/// The classic variations from Draves & Reckase, "The Fractal Flame Algorithm".
+/// The names follow the paper's appendix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Variation
+{
+    Linear,
+    Sinusoidal,
+    Spherical,
+    Swirl,
+    Horseshoe,
+    Polar,
+    Handkerchief,
+    Heart,
+    Disc,
+    Spiral,
+    Hyperbolic,
+    Diamond,
+    Ex,
+    Julia,
+    Bent,
+    Waves,
+    Fisheye,
+    Popcorn,
+    Exponential,
+    Power,
+    Cosine,
+    Rings,
+    Fan,
+    Eyefish,
+    Bubble,
+    Cylinder,
+}
+
+impl Variation
+{
+    pub const ALL: [Variation; 26] =
+    [
+        Variation::Linear,
+        Variation::Sinusoidal,
+        Variation::Spherical,
+        Variation::Swirl,
+        Variation::Horseshoe,
+        Variation::Polar,
+        Variation::Handkerchief,
+        Variation::Heart,
+        Variation::Disc,
+        Variation::Spiral,
+        Variation::Hyperbolic,
+        Variation::Diamond,
+        Variation::Ex,
+        Variation::Julia,
+        Variation::Bent,
+        Variation::Waves,
+        Variation::Fisheye,
+        Variation::Popcorn,
+        Variation::Exponential,
+        Variation::Power,
+        Variation::Cosine,
+        Variation::Rings,
+        Variation::Fan,
+        Variation::Eyefish,
+        Variation::Bubble,
+        Variation::Cylinder,
+    ];
+
+    pub fn name(&self) -> &'static str
+    {
+        match self
+        {
+            Variation::Linear => "linear",
+            Variation::Sinusoidal => "sinusoidal",
+            Variation::Spherical => "spherical",
+            Variation::Swirl => "swirl",
+            Variation::Horseshoe => "horseshoe",
+            Variation::Polar => "polar",
+            Variation::Handkerchief => "handkerchief",
+            Variation::Heart => "heart",
+            Variation::Disc => "disc",
+            Variation::Spiral => "spiral",
+            Variation::Hyperbolic => "hyperbolic",
+            Variation::Diamond => "diamond",
+            Variation::Ex => "ex",
+            Variation::Julia => "julia",
+            Variation::Bent => "bent",
+            Variation::Waves => "waves",
+            Variation::Fisheye => "fisheye",
+            Variation::Popcorn => "popcorn",
+            Variation::Exponential => "exponential",
+            Variation::Power => "power",
+            Variation::Cosine => "cosine",
+            Variation::Rings => "rings",
+            Variation::Fan => "fan",
+            Variation::Eyefish => "eyefish",
+            Variation::Bubble => "bubble",
+            Variation::Cylinder => "cylinder",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Variation>
+    {
+        Variation::ALL.iter().copied().find(|v| v.name() == name)
+    }
+
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Variation
+    {
+        Variation::ALL[rng.gen_range(0..Variation::ALL.len())]
+    }
+
+    /// Applies the variation to a point which already went through `affine`.
+    /// The affine coefficients are only read by the "dependent" variations (waves, popcorn, rings, fan).
+    pub fn apply<R: Rng + ?Sized>(&self, p: Vec2D, affine: &AugmentedMat2x2, rng: &mut R) -> Vec2D
+    {
+        let Vec2D { x, y } = p;
+        let r2 = x * x + y * y;
+        let r = r2.sqrt().max(EPSILON);
+        // The paper defines theta as arctan(x / y), which is *not* the usual polar angle.
+        let theta = x.atan2(y);
+        let [[_, b, c], [_, e, f]] = affine.coeffs();
+
+        match self
+        {
+            Variation::Linear => p,
+            Variation::Sinusoidal => Vec2D { x: x.sin(), y: y.sin() },
+            Variation::Spherical => (1. / r2.max(EPSILON)) * p,
+            Variation::Swirl =>
+            {
+                let (sin_r2, cos_r2) = r2.sin_cos();
+
+                Vec2D { x: x * sin_r2 - y * cos_r2, y: x * cos_r2 + y * sin_r2 }
+            },
+            Variation::Horseshoe => (1. / r) * Vec2D { x: (x - y) * (x + y), y: 2. * x * y },
+            Variation::Polar => Vec2D { x: theta / PI, y: r - 1. },
+            Variation::Handkerchief => r * Vec2D { x: (theta + r).sin(), y: (theta - r).cos() },
+            Variation::Heart => r * Vec2D { x: (theta * r).sin(), y: -(theta * r).cos() },
+            Variation::Disc => (theta / PI) * Vec2D { x: (PI * r).sin(), y: (PI * r).cos() },
+            Variation::Spiral => (1. / r) * Vec2D { x: theta.cos() + r.sin(), y: theta.sin() - r.cos() },
+            Variation::Hyperbolic => Vec2D { x: theta.sin() / r, y: r * theta.cos() },
+            Variation::Diamond => Vec2D { x: theta.sin() * r.cos(), y: theta.cos() * r.sin() },
+            Variation::Ex =>
+            {
+                let p0 = (theta + r).sin().powi(3);
+                let p1 = (theta - r).cos().powi(3);
+
+                r * Vec2D { x: p0 + p1, y: p0 - p1 }
+            },
+            Variation::Julia =>
+            {
+                let omega = if rng.gen::<bool>() { PI } else { 0. };
+                let angle = theta / 2. + omega;
+
+                r.sqrt() * Vec2D { x: angle.cos(), y: angle.sin() }
+            },
+            Variation::Bent => Vec2D
+            {
+                x: if x < 0. { 2. * x } else { x },
+                y: if y < 0. { y / 2. } else { y },
+            },
+            Variation::Waves => Vec2D
+            {
+                x: x + b * (y / (c * c).max(EPSILON)).sin(),
+                y: y + e * (x / (f * f).max(EPSILON)).sin(),
+            },
+            Variation::Fisheye => (2. / (r + 1.)) * Vec2D { x: y, y: x },
+            Variation::Popcorn => Vec2D
+            {
+                x: x + c * (3. * y).tan().sin(),
+                y: y + f * (3. * x).tan().sin(),
+            },
+            Variation::Exponential => (x - 1.).exp() * Vec2D { x: (PI * y).cos(), y: (PI * y).sin() },
+            Variation::Power => r.powf(theta.sin()) * Vec2D { x: theta.cos(), y: theta.sin() },
+            Variation::Cosine => Vec2D { x: (PI * x).cos() * y.cosh(), y: -(PI * x).sin() * y.sinh() },
+            Variation::Rings =>
+            {
+                let c2 = (c * c).max(EPSILON);
+                let factor = (r + c2).rem_euclid(2. * c2) - c2 + r * (1. - c2);
+
+                factor * Vec2D { x: theta.cos(), y: theta.sin() }
+            },
+            Variation::Fan =>
+            {
+                let t = (PI * c * c).max(EPSILON);
+                let angle = if (theta + f).rem_euclid(t) > t / 2. { theta - t / 2. } else { theta + t / 2. };
+
+                r * Vec2D { x: angle.cos(), y: angle.sin() }
+            },
+            Variation::Eyefish => (2. / (r + 1.)) * p,
+            Variation::Bubble => (4. / (r2 + 4.)) * p,
+            Variation::Cylinder => Vec2D { x: x.sin(), y },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeightedVariation
+{
+    pub variation: Variation,
+    pub weight: f64,
+}
+
+/// Weighted sum of the variations, as in the paper. An empty blend behaves like linear.
+pub fn apply_blend<R: Rng + ?Sized>(blend: &[WeightedVariation], p: Vec2D, affine: &AugmentedMat2x2, rng: &mut R) -> Vec2D
+{
+    if blend.is_empty()
+    {
+        return p;
+    }
+
+    blend
+        .iter()
+        .fold(Vec2D { x: 0., y: 0. }, |acc, v| acc + v.weight * v.variation.apply(p, affine, rng))
+}
+
+/// `apply_blend` over many points at once, written to `out_x` and `out_y`. The variation is
+/// picked once per slice, so that the simple ones compile to tight loops over the coordinates.
+pub fn apply_blend_batch<R: Rng + ?Sized>(blend: &[WeightedVariation], affine: &AugmentedMat2x2, xs: &[f64], ys: &[f64], out_x: &mut [f64], out_y: &mut [f64], rng: &mut R)
+{
+    if blend.is_empty()
+    {
+        out_x.copy_from_slice(xs);
+        out_y.copy_from_slice(ys);
+
+        return;
+    }
+
+    out_x.fill(0.);
+    out_y.fill(0.);
+
+    for v in blend.iter()
+    {
+        accumulate_variation(v.variation, v.weight, affine, xs, ys, out_x, out_y, rng);
+    }
+}
+
+/// Adds `weight` times the variation of each point. Matches `Variation::apply`, formula for formula.
+#[allow(clippy::too_many_arguments)]
+fn accumulate_variation<R: Rng + ?Sized>(variation: Variation, weight: f64, affine: &AugmentedMat2x2, xs: &[f64], ys: &[f64], out_x: &mut [f64], out_y: &mut [f64], rng: &mut R)
+{
+    let points = utils::zip((xs.iter().copied(), ys.iter().copied()));
+    let outputs = utils::zip((out_x.iter_mut(), out_y.iter_mut()));
+
+    match variation
+    {
+        Variation::Linear =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                *ox += weight * x;
+                *oy += weight * y;
+            }
+        },
+        Variation::Sinusoidal =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                *ox += weight * x.sin();
+                *oy += weight * y.sin();
+            }
+        },
+        Variation::Spherical =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let k = weight / (x * x + y * y).max(EPSILON);
+
+                *ox += k * x;
+                *oy += k * y;
+            }
+        },
+        Variation::Swirl =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let (sin_r2, cos_r2) = (x * x + y * y).sin_cos();
+
+                *ox += weight * (x * sin_r2 - y * cos_r2);
+                *oy += weight * (x * cos_r2 + y * sin_r2);
+            }
+        },
+        Variation::Horseshoe =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let k = weight / (x * x + y * y).sqrt().max(EPSILON);
+
+                *ox += k * (x - y) * (x + y);
+                *oy += k * 2. * x * y;
+            }
+        },
+        Variation::Bent =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                *ox += weight * if x < 0. { 2. * x } else { x };
+                *oy += weight * if y < 0. { y / 2. } else { y };
+            }
+        },
+        Variation::Fisheye | Variation::Eyefish =>
+        {
+            let swap = variation == Variation::Fisheye;
+
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let k = weight * 2. / ((x * x + y * y).sqrt().max(EPSILON) + 1.);
+                let (x, y) = if swap { (y, x) } else { (x, y) };
+
+                *ox += k * x;
+                *oy += k * y;
+            }
+        },
+        Variation::Bubble =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let k = weight * 4. / (x * x + y * y + 4.);
+
+                *ox += k * x;
+                *oy += k * y;
+            }
+        },
+        Variation::Cylinder =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                *ox += weight * x.sin();
+                *oy += weight * y;
+            }
+        },
+        _ =>
+        {
+            for ((x, y), (ox, oy)) in points.zip(outputs)
+            {
+                let p = variation.apply(Vec2D { x, y }, affine, rng);
+
+                *ox += weight * p.x;
+                *oy += weight * p.y;
+            }
+        },
+    }
+}
+
+/// Picks between 1 and `max_count` distinct variations with weights summing to 1.
+pub fn random_blend<R: Rng + ?Sized>(rng: &mut R, max_count: usize) -> Vec<WeightedVariation>
+{
+    let count = rng.gen_range(1..=max_count.max(1));
+    let mut blend: Vec<WeightedVariation> = vec![];
+
+    while blend.len() < count
+    {
+        let variation = Variation::random(rng);
+
+        if blend.iter().all(|v| v.variation != variation)
+        {
+            blend.push(WeightedVariation { variation, weight: rng.gen_range(0.1..1.) });
+        }
+    }
+
+    let total: f64 = blend.iter().map(|v| v.weight).sum();
+
+    for v in blend.iter_mut()
+    {
+        v.weight /= total;
+    }
+
+    blend
+}
+
+#[cfg(test)]
+mod tests
+{
+    use rand::rngs::mock::StepRng;
+
+    use super::{ apply_blend, apply_blend_batch, random_blend, Variation, WeightedVariation };
+    use super::super::math::{ AugmentedMat2x2, Vec2D };
+
+    // (0.3, 0.4) gives r = 0.5, sin(theta) = 0.6 and cos(theta) = 0.8, which keeps expected values readable.
+    const P: Vec2D = Vec2D { x: 0.3, y: 0.4 };
+
+    fn identity() -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::new([[1., 0., 0.], [0., 1., 0.]])
+    }
+
+    fn dependent_affine() -> AugmentedMat2x2
+    {
+        AugmentedMat2x2::new([[1., 0.5, 0.7], [0., 1., 0.8]])
+    }
+
+    fn assert_variation(variation: Variation, affine: AugmentedMat2x2, expected: (f64, f64))
+    {
+        let result = variation.apply(P, &affine, &mut StepRng::new(0, 0));
+
+        assert!
+        (
+            (result.x - expected.0).abs() < 1e-9 && (result.y - expected.1).abs() < 1e-9,
+            "{:?}: got ({}, {}), expected {:?}", variation, result.x, result.y, expected
+        );
+    }
+
+    #[test]
+    fn test_linear()
+    {
+        assert_variation(Variation::Linear, identity(), (0.3, 0.4));
+    }
+
+    #[test]
+    fn test_sinusoidal()
+    {
+        assert_variation(Variation::Sinusoidal, identity(), (0.29552020666133955, 0.3894183423086505));
+    }
+
+    #[test]
+    fn test_spherical()
+    {
+        assert_variation(Variation::Spherical, identity(), (1.2, 1.6));
+    }
+
+    #[test]
+    fn test_swirl()
+    {
+        assert_variation(Variation::Swirl, identity(), (-0.31334378090790105, 0.3896353102150026));
+    }
+
+    #[test]
+    fn test_horseshoe()
+    {
+        assert_variation(Variation::Horseshoe, identity(), (-0.14000000000000004, 0.48));
+    }
+
+    #[test]
+    fn test_polar()
+    {
+        assert_variation(Variation::Polar, identity(), (0.20483276469913345, -0.5));
+    }
+
+    #[test]
+    fn test_handkerchief()
+    {
+        assert_variation(Variation::Handkerchief, identity(), (0.455044984008793, 0.49486068633741));
+    }
+
+    #[test]
+    fn test_heart()
+    {
+        assert_variation(Variation::Heart, identity(), (0.15811388300841897, -0.4743416490252569));
+    }
+
+    #[test]
+    fn test_disc()
+    {
+        assert_variation(Variation::Disc, identity(), (0.20483276469913345, 1.2542389482464836e-17));
+    }
+
+    #[test]
+    fn test_spiral()
+    {
+        assert_variation(Variation::Spiral, identity(), (2.558851077208406, -0.5551651237807456));
+    }
+
+    #[test]
+    fn test_hyperbolic()
+    {
+        assert_variation(Variation::Hyperbolic, identity(), (1.2, 0.4));
+    }
+
+    #[test]
+    fn test_diamond()
+    {
+        assert_variation(Variation::Diamond, identity(), (0.5265495371342236, 0.3835404308833624));
+    }
+
+    #[test]
+    fn test_ex()
+    {
+        assert_variation(Variation::Ex, identity(), (0.8616372561344048, -0.10784272649030102));
+    }
+
+    #[test]
+    fn test_julia()
+    {
+        let expected = (0.6708203932499369, 0.223606797749979);
+        let affine = identity();
+
+        assert_variation(Variation::Julia, affine, expected);
+
+        let flipped = Variation::Julia.apply(P, &affine, &mut StepRng::new(u64::MAX, 0));
+
+        assert!((flipped.x + expected.0).abs() < 1e-9 && (flipped.y + expected.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_bent()
+    {
+        let affine = identity();
+        let mut rng = StepRng::new(0, 0);
+
+        assert_eq!(Variation::Bent.apply(Vec2D { x: 1., y: 1. }, &affine, &mut rng), Vec2D { x: 1., y: 1. });
+        assert_eq!(Variation::Bent.apply(Vec2D { x: -1., y: 1. }, &affine, &mut rng), Vec2D { x: -2., y: 1. });
+        assert_eq!(Variation::Bent.apply(Vec2D { x: 1., y: -1. }, &affine, &mut rng), Vec2D { x: 1., y: -0.5 });
+        assert_eq!(Variation::Bent.apply(Vec2D { x: -1., y: -1. }, &affine, &mut rng), Vec2D { x: -2., y: -0.5 });
+    }
+
+    #[test]
+    fn test_waves()
+    {
+        assert_variation(Variation::Waves, dependent_affine(), (0.6643173917346752, 0.8517714714916838));
+    }
+
+    #[test]
+    fn test_fisheye()
+    {
+        assert_variation(Variation::Fisheye, identity(), (0.5333333333333333, 0.39999999999999997));
+    }
+
+    #[test]
+    fn test_popcorn()
+    {
+        assert_variation(Variation::Popcorn, dependent_affine(), (0.6774129578097201, 1.1617109722205354));
+    }
+
+    #[test]
+    fn test_exponential()
+    {
+        assert_variation(Variation::Exponential, identity(), (0.15345329802839158, 0.4722806890672284));
+    }
+
+    #[test]
+    fn test_power()
+    {
+        assert_variation(Variation::Power, identity(), (0.5278031643091577, 0.3958523732318682));
+    }
+
+    #[test]
+    fn test_cosine()
+    {
+        assert_variation(Variation::Cosine, identity(), (0.6354383968274886, -0.332305612053513));
+    }
+
+    #[test]
+    fn test_rings()
+    {
+        assert_variation(Variation::Rings, dependent_affine(), (-0.17999999999999985, -0.13499999999999987));
+    }
+
+    #[test]
+    fn test_fan()
+    {
+        assert_variation(Variation::Fan, dependent_affine(), (0.49602435808296985, -0.06292722930796907));
+    }
+
+    #[test]
+    fn test_eyefish()
+    {
+        assert_variation(Variation::Eyefish, identity(), (0.39999999999999997, 0.5333333333333333));
+    }
+
+    #[test]
+    fn test_bubble()
+    {
+        assert_variation(Variation::Bubble, identity(), (0.2823529411764706, 0.3764705882352941));
+    }
+
+    #[test]
+    fn test_cylinder()
+    {
+        assert_variation(Variation::Cylinder, identity(), (0.29552020666133955, 0.4));
+    }
+
+    #[test]
+    fn test_names_round_trip()
+    {
+        for variation in Variation::ALL.iter()
+        {
+            assert_eq!(Variation::from_name(variation.name()), Some(*variation));
+        }
+
+        assert_eq!(Variation::from_name("unknown"), None);
+    }
+
+    #[test]
+    fn test_apply_blend()
+    {
+        let affine = identity();
+        let mut rng = StepRng::new(0, 0);
+        let blend =
+        [
+            WeightedVariation { variation: Variation::Linear, weight: 0.5 },
+            WeightedVariation { variation: Variation::Spherical, weight: 0.25 },
+        ];
+
+        let result = apply_blend(&blend, P, &affine, &mut rng);
+
+        assert!((result.x - 0.45).abs() < 1e-12 && (result.y - 0.6).abs() < 1e-12);
+        assert_eq!(apply_blend(&[], P, &affine, &mut rng), P);
+    }
+
+    #[test]
+    fn test_batch_matches_apply_blend()
+    {
+        let affine = dependent_affine();
+        let xs = [0.3, -0.7, 0., 1.5, -0.2, 1e-12];
+        let ys = [0.4, 0.1, 0., -2., -0.9, 0.];
+
+        for variation in Variation::ALL.iter()
+        {
+            let blend = [WeightedVariation { variation: *variation, weight: 0.7 }, WeightedVariation { variation: Variation::Linear, weight: 0.3 }];
+            let (mut out_x, mut out_y) = ([0.; 6], [0.; 6]);
+
+            apply_blend_batch(&blend, &affine, &xs, &ys, &mut out_x, &mut out_y, &mut StepRng::new(0, 0));
+
+            for i in 0..xs.len()
+            {
+                let expected = apply_blend(&blend, Vec2D { x: xs[i], y: ys[i] }, &affine, &mut StepRng::new(0, 0));
+
+                assert!
+                (
+                    (out_x[i] - expected.x).abs() < 1e-12 && (out_y[i] - expected.y).abs() < 1e-12,
+                    "{:?} at ({}, {}): got ({}, {}), expected {:?}", variation, xs[i], ys[i], out_x[i], out_y[i], expected
+                );
+            }
+        }
+
+        let (mut out_x, mut out_y) = ([0.; 6], [0.; 6]);
+
+        apply_blend_batch(&[], &affine, &xs, &ys, &mut out_x, &mut out_y, &mut StepRng::new(0, 0));
+        assert_eq!((out_x, out_y), (xs, ys));
+    }
+
+    #[test]
+    fn test_random_blend_is_normalized()
+    {
+        let mut rng = rand::thread_rng();
+
+        for _ in 0..100
+        {
+            let blend = random_blend(&mut rng, 3);
+            let total: f64 = blend.iter().map(|v| v.weight).sum();
+
+            assert!(!blend.is_empty() && blend.len() <= 3);
+            assert!((total - 1.).abs() < 1e-12);
+        }
+    }
+}